@@ -0,0 +1,97 @@
+//! Identity arena at registry scale.
+//!
+//! `cargo bench --bench identity_store` builds the arena from 1,000,000
+//! synthetic rows (pass another count, e.g. `-- 5000000`), then times ID
+//! lookups, variation inserts, the blocked `StoreSnapshot` the server
+//! matches against, and writing and reading it back as a snapshot file.
+
+use std::time::Instant;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use my_project::utils::blocking::BlockingConfig;
+use my_project::utils::identity_store::StoreSnapshot;
+use my_project::utils::linked_list::{insert_variation, rebuild_identity_dictionary, IdentityRow};
+
+const FIRST_NAMES: [&str; 12] = [
+    "محمد", "احمد", "علي", "عمر", "يوسف", "سامي", "فاطمه", "مريم", "سلمي", "ليلي", "خديجه", "امينه",
+];
+const LAST_NAMES: [&str; 10] = [
+    "الطرابلسي", "بن علي", "الحسني", "الجبالي", "المرزوقي", "السويسي", "القاسمي", "بن سالم", "العياري", "الزواري",
+];
+const PLACES: [&str; 6] = ["تونس", "صفاقس", "سوسه", "قابس", "بنزرت", "القيروان"];
+
+/// `count` rows; one in ten repeats an earlier person with another spelling.
+fn synthetic_rows(count: usize, rng: &mut StdRng) -> Vec<IdentityRow> {
+    let mut rows: Vec<IdentityRow> = Vec::with_capacity(count);
+    for i in 0..count {
+        if i > 0 && i % 10 == 0 {
+            let mut row = rows[rng.gen_range(0..i)].clone();
+            row.9 = format!("{} ", row.9);
+            rows.push(row);
+            continue;
+        }
+        let pick = |names: &[&str], rng: &mut StdRng| names.choose(rng).expect("non-empty").to_string();
+        let first = pick(&FIRST_NAMES, rng);
+        let last = pick(&LAST_NAMES, rng);
+        let father = pick(&FIRST_NAMES[..6], rng);
+        let grandfather = pick(&FIRST_NAMES[..6], rng);
+        let mother_last = pick(&LAST_NAMES, rng);
+        let mother = pick(&FIRST_NAMES[6..], rng);
+        let dob = Some((rng.gen_range(1..=28), rng.gen_range(1..=12), rng.gen_range(1940..=2010)));
+        rows.push((
+            first.clone(), last.clone(), father.clone(), grandfather.clone(), mother_last.clone(), mother.clone(),
+            dob, rng.gen_range(1..=2), pick(&PLACES, rng),
+            first, last, father, grandfather, mother_last, mother,
+        ));
+    }
+    rows
+}
+
+fn report(step: &str, count: usize, started: Instant) {
+    let seconds = started.elapsed().as_secs_f64();
+    println!("{:<28} {:>10} in {:>8.3}s  ({:>12.0}/s)", step, count, seconds, count as f64 / seconds);
+}
+
+fn main() {
+    let count: usize = std::env::args().skip(1).find_map(|a| a.parse().ok()).unwrap_or(1_000_000);
+    let mut rng = StdRng::seed_from_u64(7);
+
+    let started = Instant::now();
+    let rows = synthetic_rows(count, &mut rng);
+    report("generate rows", count, started);
+
+    let started = Instant::now();
+    let mut arena = rebuild_identity_dictionary(rows);
+    report("rebuild_identity_dictionary", count, started);
+    println!("  {} distinct identities", arena.len());
+
+    let ids: Vec<String> = arena.iter().map(|node| node.id.clone()).collect();
+    let started = Instant::now();
+    let found = ids.iter().filter(|id| arena.get(id).is_some()).count();
+    report("lookup by id", ids.len(), started);
+    assert_eq!(found, ids.len());
+
+    let started = Instant::now();
+    for id in &ids {
+        let node = arena.get_mut(id).expect("indexed");
+        insert_variation(&mut node.first_name_variations, "Mohamed");
+    }
+    report("insert_variation", ids.len(), started);
+
+    let started = Instant::now();
+    let snapshot = StoreSnapshot::build(arena.into_records(), &BlockingConfig::default());
+    report("StoreSnapshot::build", snapshot.len(), started);
+
+    let blocking = BlockingConfig::default();
+    let path = std::env::temp_dir().join("identity_store_bench.snap").to_string_lossy().into_owned();
+    let started = Instant::now();
+    snapshot.save(&path, &blocking).expect("write snapshot");
+    report("StoreSnapshot::save", snapshot.len(), started);
+    println!("  {} MB", std::fs::metadata(&path).expect("snapshot written").len() / 1_000_000);
+
+    let started = Instant::now();
+    let loaded = StoreSnapshot::load_file(&path, &blocking).expect("read snapshot");
+    report("StoreSnapshot::load_file", loaded.len(), started);
+    std::fs::remove_file(&path).expect("remove snapshot");
+}
@@ -1,25 +1,38 @@
+use std::time::Duration;
 use bb8::Pool;
 use bb8_postgres::PostgresConnectionManager;
 use tokio_postgres::NoTls;
 
 pub type ConnectionPool = Pool<PostgresConnectionManager<NoTls>>;
 
-pub async fn create_pool() -> ConnectionPool {
-    let manager = PostgresConnectionManager::new_from_stringlike(
+fn manager() -> PostgresConnectionManager<NoTls> {
+    PostgresConnectionManager::new_from_stringlike(
         "host=localhost port=5432 user=postgres password=9155 dbname=tunisian_citizens",
         NoTls,
     )
-        .expect("Invalid connection string");
+        .expect("Invalid connection string")
+}
 
+pub async fn create_pool() -> ConnectionPool {
     Pool::builder()
         .max_size(10)
-        .build(manager)
+        .build(manager())
         .await
         .expect("Failed to build pool")
 }
 
-pub async fn init_db(pool: &ConnectionPool) {
-    let conn = pool.get().await.expect("Failed to get connection");
+/// A pool that only connects when a handler asks for a connection, for a
+/// server started from a registry snapshot while PostgreSQL may be down.
+pub fn create_lazy_pool() -> ConnectionPool {
+    Pool::builder()
+        .max_size(10)
+        .connection_timeout(Duration::from_secs(2))
+        .build_unchecked(manager())
+}
+
+/// Create the users, usage and adjudication tables if they do not exist.
+pub async fn init_db(pool: &ConnectionPool) -> Result<(), String> {
+    let conn = pool.get().await.map_err(|e| format!("Failed to get connection: {}", e))?;
     conn.batch_execute(
         "
         CREATE TABLE IF NOT EXISTS users (
@@ -32,9 +45,39 @@ pub async fn init_db(pool: &ConnectionPool) {
             user_id TEXT NOT NULL,
             api_link TEXT NOT NULL,
             timestamp TIMESTAMPTZ NOT NULL DEFAULT NOW()
-        )
+        );
+        CREATE TABLE IF NOT EXISTS match_requests (
+            id UUID PRIMARY KEY,
+            user_id TEXT NOT NULL,
+            profile TEXT NOT NULL,
+            input_id TEXT NOT NULL,
+            input JSONB NOT NULL,
+            created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+        );
+        CREATE TABLE IF NOT EXISTS match_candidates (
+            request_id UUID NOT NULL REFERENCES match_requests (id) ON DELETE CASCADE,
+            candidate_id TEXT NOT NULL,
+            rank INT NOT NULL,
+            score DOUBLE PRECISION NOT NULL,
+            match_probability DOUBLE PRECISION,
+            candidate JSONB NOT NULL,
+            PRIMARY KEY (request_id, candidate_id)
+        );
+        CREATE TABLE IF NOT EXISTS match_decisions (
+            id SERIAL PRIMARY KEY,
+            request_id UUID NOT NULL,
+            candidate_id TEXT NOT NULL,
+            decision TEXT NOT NULL CHECK (decision IN ('match', 'non_match', 'unsure')),
+            reviewer TEXT NOT NULL,
+            comment TEXT,
+            decided_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+            FOREIGN KEY (request_id, candidate_id)
+                REFERENCES match_candidates (request_id, candidate_id) ON DELETE CASCADE
+        );
+        CREATE INDEX IF NOT EXISTS match_decisions_pair
+            ON match_decisions (request_id, candidate_id, decided_at DESC)
     ",
     )
         .await
-        .expect("Failed to create tables");
+        .map_err(|e| format!("Failed to create tables: {}", e))
 }
@@ -2,13 +2,18 @@
 
 use axum::{
     routing::post,
-    extract::Json,
-    http::StatusCode,
+    body::{Body, Bytes},
+    extract::{rejection::JsonRejection, DefaultBodyLimit, Extension, FromRef, Json, Path, Query, State},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
     Router, middleware as axum_middleware,
 };
-use serde::{Deserialize, Serialize};
+use serde::Deserialize;
+use std::convert::Infallible;
 use std::net::SocketAddr;
-use rayon::prelude::*;
+use std::sync::Arc;
+use std::time::Duration;
+use uuid::Uuid;
 
 pub mod utils;
 pub mod models;
@@ -16,61 +21,250 @@ pub mod db;
 pub mod handlers;
 pub mod middleware;
 
+use crate::models::Claims;
 use crate::utils::{
-    loader::{load_identities_by_generation, generation_key},
-    matching::{
-        should_consider_candidate,
-        best_score_against_variations,
-        score_pair_with_soundex,
-        calculate_full_score,
+    adjudication::{
+        confirmed_decisions, get_match_request, record_decision, review_queue, store_match_request, Decision,
+        DecisionRecord, ReviewBand, ReviewItem, StoredMatchRequest,
     },
-    normalization::{normalize_arabic, remove_diacritics, standardize_prefixes}, // Added for input normalization
+    classifier::MatchModel,
+    batch::{
+        csv_header, encode_results, match_rows, parse_batch, BatchFormat, BatchJobs, JobState, JobStatus,
+        BATCH_CHUNK_SIZE,
+    },
+    blocking::BlockingConfig,
+    gold_set::{gold_set_to_csv, gold_set_to_json},
+    identity_store::IdentityStore,
+    pipeline::{rank_candidates_with_model, select_matches, InputIdentity, MatchLimits, MatchParams, MatchResult},
     linked_list::IdentityNode,
+    scoring_profile::ScoringProfiles,
+    variants::{VariantClass, VariantDictionary},
 };
 
+/// Shared state: the DB pool for the auth/usage handlers, the scoring
+/// profiles and the in-memory registry for `/match`, and the background
+/// `/match/batch` jobs. `model` is the trained match classifier, if any.
+#[derive(Clone)]
+struct AppState {
+    pool: db::ConnectionPool,
+    profiles: Arc<ScoringProfiles>,
+    store: Arc<IdentityStore>,
+    jobs: Arc<BatchJobs>,
+    limits: Arc<MatchLimits>,
+    model: Option<Arc<MatchModel>>,
+}
+
+impl FromRef<AppState> for db::ConnectionPool {
+    fn from_ref(state: &AppState) -> Self {
+        state.pool.clone()
+    }
+}
+
+impl FromRef<AppState> for Arc<ScoringProfiles> {
+    fn from_ref(state: &AppState) -> Self {
+        state.profiles.clone()
+    }
+}
+
+impl FromRef<AppState> for Arc<IdentityStore> {
+    fn from_ref(state: &AppState) -> Self {
+        state.store.clone()
+    }
+}
+
+impl FromRef<AppState> for Arc<BatchJobs> {
+    fn from_ref(state: &AppState) -> Self {
+        state.jobs.clone()
+    }
+}
+
+impl FromRef<AppState> for Arc<MatchLimits> {
+    fn from_ref(state: &AppState) -> Self {
+        state.limits.clone()
+    }
+}
+
+impl FromRef<AppState> for Option<Arc<MatchModel>> {
+    fn from_ref(state: &AppState) -> Self {
+        state.model.clone()
+    }
+}
+
+/// Query string of `/match`, e.g. `/match?profile=border-control&min_score=60&top_k=10`.
+#[derive(Debug, Deserialize)]
+struct MatchQuery {
+    profile: Option<String>,
+    min_score: Option<f64>,
+    /// Threshold in percent when ranking on the match probability
+    min_probability: Option<f64>,
+    top_k: Option<usize>,
+    include_below_threshold: Option<bool>,
+    /// Rank on the classifier's match probability; on by default when a
+    /// model is loaded, `probability=false` keeps the profile's total
+    probability: Option<bool>,
+}
+
+impl MatchQuery {
+    fn params(&self) -> MatchParams {
+        MatchParams {
+            min_score: self.min_score,
+            min_probability: self.min_probability,
+            top_k: self.top_k,
+            include_below_threshold: self.include_below_threshold,
+        }
+    }
+}
+
+/// Body of `/match`: the identity, optionally with `min_score`,
+/// `min_probability`, `top_k` and `include_below_threshold` next to its fields.
+#[derive(Debug, Deserialize)]
+struct MatchRequest {
+    #[serde(flatten)]
+    identity: InputIdentity,
+    #[serde(flatten)]
+    params: MatchParams,
+}
+
+/// Query string of `/match/batch`, e.g. `/match/batch?format=csv&mode=async`.
 #[derive(Debug, Deserialize)]
-struct InputIdentity {
-    first_name:       String,
-    last_name:        String,
-    father_name:      String,
-    grandfather_name: String,
-    mother_last_name: String,
-    mother_name:      String,
-    dob:              Option<(u32, u32, u32)>,
-    sex:              u8,
-    place_of_birth:   String,
-}
-
-#[derive(Debug, Serialize)]
-struct IdentityRecord {
-    first_name:       String,
-    last_name:        String,
-    father_name:      String,
-    grandfather_name: String,
-    mother_last_name: String,
-    mother_name:      String,
-    dob:              (u32, u32, u32),
-    sex:              u8,
-    place_of_birth:   String,
-}
-
-#[derive(Debug, Serialize)]
-struct FieldScore {
-    field: String,
-    score: f64,
-}
-
-#[derive(Debug, Serialize)]
-struct MatchResult {
-    matched_identity: IdentityRecord,
-    total_score:      f64,
-    breakdown:        Vec<FieldScore>,
+struct BatchQuery {
+    profile: Option<String>,
+    min_score: Option<f64>,
+    min_probability: Option<f64>,
+    top_k: Option<usize>,
+    include_below_threshold: Option<bool>,
+    /// Upload format when the Content-Type does not tell: json, ndjson or csv
+    input_format: Option<String>,
+    /// Result format: ndjson (default) or csv
+    format: Option<String>,
+    /// `async` runs the batch as a background job whatever its size
+    mode: Option<String>,
+    /// As for `/match`: rank on the classifier's match probability
+    probability: Option<bool>,
+}
+
+/// Body of `POST /variants`: the spellings of one name, e.g.
+/// `{"names": ["فاطمة", "فطومة", "Fatma", "Fatima"]}`.
+#[derive(Debug, Deserialize)]
+struct NewVariantClass {
+    names: Vec<String>,
+}
+
+/// Body of `POST /match/requests/:id/decisions`, e.g.
+/// `{"candidate_id": "…", "decision": "non_match", "comment": "different mother"}`.
+#[derive(Debug, Deserialize)]
+struct NewDecision {
+    candidate_id: String,
+    decision: Decision,
+    comment: Option<String>,
+}
+
+/// Query string of `/review/queue`; the band defaults to REVIEW_MIN_SCORE
+/// and REVIEW_MAX_SCORE.
+#[derive(Debug, Deserialize)]
+struct ReviewQuery {
+    min_score: Option<f64>,
+    max_score: Option<f64>,
+    limit: Option<usize>,
+}
+
+/// Query string of `/decisions/export`.
+#[derive(Debug, Deserialize)]
+struct ExportQuery {
+    format: Option<String>,
+}
+
+/// Query string of `/match/batch/:job_id/results`.
+#[derive(Debug, Deserialize)]
+struct ResultsQuery {
+    format: Option<String>,
 }
 
 #[tokio::main]
 async fn main() {
-    let pool = db::create_pool().await;
-    db::init_db(&pool).await;
+    // Started from a REGISTRY_SNAPSHOT file the server does not need PostgreSQL
+    // to match: the pool connects on demand, and only login/registration,
+    // user management, usage tracking and adjudication fail while it is down
+    let snapshot_path = std::env::var("REGISTRY_SNAPSHOT").ok().filter(|path| !path.is_empty());
+    let pool = match &snapshot_path {
+        Some(_) => {
+            let pool = db::create_lazy_pool();
+            if let Err(e) = db::init_db(&pool).await {
+                eprintln!("⚠️  PostgreSQL unavailable, serving /match from the snapshot only: {}", e);
+            }
+            pool
+        }
+        None => {
+            let pool = db::create_pool().await;
+            db::init_db(&pool).await.expect("Failed to initialize the database");
+            pool
+        }
+    };
+
+    let profiles = ScoringProfiles::from_env().expect("Failed to load scoring profiles");
+    println!("📐 Scoring profiles: {:?} (default: {})", profiles.names(), profiles.default_name());
+
+    // Blocking keys from BLOCKING_CONFIG (.toml/.json), all keys by default
+    let blocking = BlockingConfig::from_env().expect("Failed to load blocking config");
+    println!("🧱 Blocking keys: {:?}", blocking.keys);
+
+    // Load the registry once, from the REGISTRY_SNAPSHOT file when set (see
+    // `main_cli --write-snapshot`) or else from PostgreSQL; refresh it from the
+    // same source every STORE_REFRESH_SECS (default 3600, or 0 = never when
+    // started from a snapshot) and on NOTIFY when STORE_NOTIFY_CHANNEL is set.
+    // Name variants from NAME_VARIANTS (.csv/.json), attached to every record
+    let variants = VariantDictionary::from_env().expect("Failed to load name variants");
+    println!("📚 {} name variant classes", variants.len());
+    let store = match &snapshot_path {
+        Some(path) => {
+            println!("📦 Loading the identity registry from {}…", path);
+            let store = IdentityStore::from_snapshot_file(path, blocking, variants).expect("Failed to load registry snapshot");
+            Arc::new(store)
+        }
+        None => {
+            println!("🔍 Loading the identity registry into memory…");
+            Arc::new(IdentityStore::load(blocking, variants).await.expect("Failed to load the identity registry"))
+        }
+    };
+    println!("✅ Identity store ready: {} records", store.snapshot().len());
+    let refresh_secs = std::env::var("STORE_REFRESH_SECS")
+        .ok()
+        .and_then(|v| v.parse::<u64>().ok())
+        .unwrap_or(if snapshot_path.is_some() { 0 } else { 3600 });
+    if refresh_secs > 0 {
+        store.spawn_periodic_refresh(Duration::from_secs(refresh_secs));
+    }
+    if let Ok(channel) = std::env::var("STORE_NOTIFY_CHANNEL") {
+        store.spawn_notify_refresh(channel);
+    }
+
+    let limits = MatchLimits::from_env().expect("Invalid match limits");
+    println!(
+        "🎯 Default threshold {}% ({}% on the match probability), top {} (at most {})",
+        limits.default_min_score, limits.default_min_probability, limits.default_top_k, limits.max_top_k
+    );
+
+    // Trained match classifier from MATCH_MODEL (see `main_cli --train-model`)
+    let model = MatchModel::from_env().expect("Failed to load match model").map(Arc::new);
+    if let Some(model) = &model {
+        println!("🧠 Match model trained on {} pairs at {}", model.trained_pairs, model.trained_at);
+    }
+
+    let state = AppState {
+        pool: pool.clone(),
+        profiles: Arc::new(profiles),
+        store,
+        // Finished jobs are kept BATCH_JOB_TTL_SECS (default 86400)
+        jobs: Arc::new(BatchJobs::from_env()),
+        limits: Arc::new(limits),
+        model,
+    };
+
+    // Batch uploads may be far larger than axum's 2 MB default
+    let batch_max_bytes = std::env::var("BATCH_MAX_BYTES")
+        .ok()
+        .and_then(|v| v.parse::<usize>().ok())
+        .unwrap_or(256 * 1024 * 1024);
 
     // Public routes
     let public_routes = Router::new()
@@ -81,6 +275,18 @@ async fn main() {
     // Protected routes
     let protected_routes = Router::new()
         .route("/match", post(match_identity))
+        .route(
+            "/match/batch",
+            post(match_batch).layer(DefaultBodyLimit::max(batch_max_bytes)),
+        )
+        .route("/match/batch/:job_id", axum::routing::get(batch_job_status))
+        .route("/match/batch/:job_id/results", axum::routing::get(batch_job_results))
+        .route("/match/requests/:id", axum::routing::get(get_stored_match))
+        .route("/match/requests/:id/decisions", post(add_decision))
+        .route("/review/queue", axum::routing::get(get_review_queue))
+        .route("/decisions/export", axum::routing::get(export_decisions))
+        .route("/variants", axum::routing::get(list_variants).post(add_variant_class))
+        .route("/variants/:id", axum::routing::delete(remove_variant_class))
         .route(
             "/api/usage/:user_id",
             axum::routing::get(handlers::get_api_usage),
@@ -102,7 +308,7 @@ async fn main() {
             pool.clone(),
             middleware::track_api_usage,
         ))
-        .with_state(pool);
+        .with_state(state);
 
     let addr = SocketAddr::from(([127, 0, 0, 1], 3000));
     println!("🚀 Server running on http://{}", addr);
@@ -115,185 +321,410 @@ async fn main() {
         .expect("Server error");
 }
 
+/// Match one identity. Threshold and count come from the query string,
+/// then the body, then the profile's `min_score`, then the server defaults.
+/// With a match model loaded, results are ranked on its probability (in
+/// percent) and held to `min_probability` rather than the profile's
+/// `min_score`, unless `probability=false`.
+/// Requests returning candidates are stored for adjudication in the
+/// background, so a slow or unavailable database does not hold up the
+/// answer; their ID comes back in the `X-Match-Request-Id` header (and is
+/// not found later if storing failed).
+/// A body that does not parse, e.g. a birth date out of range, is a 400.
+#[allow(clippy::too_many_arguments)]
 async fn match_identity(
-    Json(input): Json<InputIdentity>,
-) -> (StatusCode, Json<Vec<MatchResult>>) {
+    State(pool): State<db::ConnectionPool>,
+    State(profiles): State<Arc<ScoringProfiles>>,
+    State(store): State<Arc<IdentityStore>>,
+    State(limits): State<Arc<MatchLimits>>,
+    State(model): State<Option<Arc<MatchModel>>>,
+    Extension(claims): Extension<Claims>,
+    Query(query): Query<MatchQuery>,
+    request: Result<Json<MatchRequest>, JsonRejection>,
+) -> (StatusCode, HeaderMap, Json<Vec<MatchResult>>) {
+    let mut headers = HeaderMap::new();
+    let request = match request {
+        Ok(Json(request)) => request,
+        Err(e) => {
+            println!("⚠️ Invalid match request: {}", e.body_text());
+            return (StatusCode::BAD_REQUEST, headers, Json(vec![]));
+        }
+    };
+    let Some(profile) = profiles.get(query.profile.as_deref()) else {
+        println!("⚠️ Unknown scoring profile {:?}", query.profile);
+        return (StatusCode::BAD_REQUEST, headers, Json(vec![]));
+    };
+    println!("📐 Using scoring profile '{}'", profile.name);
+    let model = match ranking_model(query.probability, model) {
+        Ok(model) => model,
+        Err(e) => {
+            println!("⚠️ {}", e);
+            return (StatusCode::BAD_REQUEST, headers, Json(vec![]));
+        }
+    };
+    let model = model.as_deref();
+    let options = match limits.resolve(&query.params().or(request.params).or(profile.match_params())) {
+        Ok(options) => options,
+        Err(e) => {
+            println!("⚠️ {}", e);
+            return (StatusCode::BAD_REQUEST, headers, Json(vec![]));
+        }
+    };
+    let input = request.identity;
+
     // --- Normalize input strings once ---
-    let normalize_fn = |s: &str| standardize_prefixes(&normalize_arabic(&remove_diacritics(s)));
-
-    let norm_input_first_name = normalize_fn(&input.first_name);
-    let norm_input_last_name = normalize_fn(&input.last_name);
-    let norm_input_father_name = normalize_fn(&input.father_name);
-    let norm_input_grandfather_name = normalize_fn(&input.grandfather_name);
-    let norm_input_mother_last_name = normalize_fn(&input.mother_last_name);
-    let norm_input_mother_name = normalize_fn(&input.mother_name);
-    let norm_input_place_of_birth = normalize_fn(&input.place_of_birth);
-    // --- End of input normalization ---
-
-    // 1) Compute decade key
-    let gen = input
-        .dob
-        .map(|(_, _, y)| generation_key(y as i32))
-        .unwrap_or_else(|| generation_key(0));
-    println!("🔍 Computed generation key: {}", gen);
-
-    // 2) Load only that decade
-    println!("🔍 Connecting to PostgreSQL to load generation {}…", gen);
-    let records: Vec<IdentityNode> = load_identities_by_generation(gen).await;
-    println!("✅ {} rows in generation {}", records.len(), gen);
-    if records.is_empty() {
-        println!("⚠️ No records found for generation {}; aborting.", gen);
-        return (StatusCode::NOT_FOUND, Json(vec![]));
+    let norm_input = input.normalized();
+
+    // 1) Current in-memory registry
+    let snapshot = store.snapshot();
+    if snapshot.is_empty() {
+        println!("⚠️ Identity store is empty; aborting.");
+        return (StatusCode::NOT_FOUND, headers, Json(vec![]));
     }
 
-    // 3) Pre-filter using normalized input
-    let candidates: Vec<&IdentityNode> = records
-        .iter()
-        .filter(|id_node| { // Renamed `id` to `id_node` to avoid conflict if we destructure input later
-            should_consider_candidate(
-                &( // Pass normalized input fields
-                   &norm_input_first_name,
-                   &norm_input_last_name,
-                   &norm_input_father_name,
-                   &norm_input_grandfather_name,
-                   &norm_input_mother_last_name,
-                   &norm_input_mother_name,
-                   input.dob, // DOB, sex are not strings, no normalization needed here
-                   input.sex,
-                   &norm_input_place_of_birth,
-                ),
-                &( // IdentityNode fields are already normalized by loader (for base names)
-                   &id_node.first_name,
-                   &id_node.last_name,
-                   &id_node.father_name,
-                   &id_node.grandfather_name,
-                   &id_node.mother_last_name,
-                   &id_node.mother_name,
-                   id_node.dob,
-                   id_node.sex,
-                   &id_node.place_of_birth, // place_of_birth in IdentityNode is raw, but loader normalizes it for storage.
-                   // For should_consider_candidate, it expects normalized if used,
-                   // but current implementation only uses last_name for soundex.
-                   // Let's assume id_node.place_of_birth is the normalized version as per loader.rs for consistency with other name fields.
-                   // If id_node.place_of_birth was raw, it would need normalization here or inside should_consider_candidate.
-                   // Given loader.rs normalizes all text fields it extracts for the IdentityNode main fields, this should be fine.
-                ),
-            )
-        })
-        .collect();
-    println!("✅ {} candidates after pre-filter", candidates.len());
+    // 2-3) Indexed lookup by generation and sex, blocking keys, then pre-filter
+    let candidates: Vec<&IdentityNode> = snapshot.candidates(&norm_input, profile.year_tolerance);
+    println!("✅ {} candidates out of {} records", candidates.len(), snapshot.len());
     if candidates.is_empty() {
         println!("⚠️ All records filtered out; returning empty result.");
-        return (StatusCode::OK, Json(vec![]));
+        return (StatusCode::OK, headers, Json(vec![]));
     }
 
     // 4) Score & sort using normalized input
     println!("▶ Scoring {} candidates in parallel…", candidates.len());
-    let mut results: Vec<MatchResult> = candidates
-        .par_iter()
-        .map(|id_node| { // Renamed `id` to `id_node`
-            let mut breakdown = Vec::new();
-
-            // Name fields - use normalized input
-            let fields_to_score = [
-                ("الاسم الأول",    &norm_input_first_name,    &id_node.first_name,    &id_node.first_name_variations),
-                ("اسم العائلة",    &norm_input_last_name,     &id_node.last_name,     &id_node.last_name_variations),
-                ("اسم الأب",       &norm_input_father_name,   &id_node.father_name,   &id_node.father_name_variations),
-                ("اسم الجد",       &norm_input_grandfather_name, &id_node.grandfather_name, &id_node.grandfather_name_variations),
-                ("اسم عائلة الأم", &norm_input_mother_last_name, &id_node.mother_last_name, &id_node.mother_last_name_variations),
-                ("اسم الأم",       &norm_input_mother_name,    &id_node.mother_name,    &id_node.mother_name_variations),
-            ];
-            for (label, norm_inp_field, id_base_field, id_vars) in fields_to_score {
-                // best_score_against_variations expects normalized input and normalized base,
-                // and handles normalization of raw variations internally.
-                let raw_score = best_score_against_variations(norm_inp_field, id_base_field, id_vars) * 100.0_f64;
-                breakdown.push(FieldScore { field: label.to_string(), score: raw_score.round() });
+    let results: Vec<MatchResult> = rank_candidates_with_model(profile, model, &norm_input, &candidates);
+    println!("✅ Scoring done ({} results).", results.len());
+
+    // 5) Threshold & return top-K
+    let filtered: Vec<MatchResult> = select_matches(results, &options);
+    println!(
+        "✅ Returning {} match(es) of the top {} ≥ {}%{}.",
+        filtered.len(),
+        options.top_k,
+        if model.is_some() { options.min_probability } else { options.min_score },
+        if options.include_below_threshold { " (with those below)" } else { "" }
+    );
+
+    // 6) Keep the request for adjudication without waiting for the database
+    if !filtered.is_empty() {
+        let id = Uuid::new_v4();
+        headers.insert("x-match-request-id", HeaderValue::from_str(&id.to_string()).expect("UUID header"));
+        let (profile_name, results) = (profile.name.clone(), filtered.clone());
+        tokio::spawn(async move {
+            if let Err(e) = store_match_request(&pool, id, &claims.sub, &profile_name, &input, &results).await {
+                eprintln!("Failed to store match request {}: {}", id, e);
             }
+        });
+    }
 
-            // DOB
-            let dob_score: f64 = if let (Some((d1,m1,y1)), Some((d2,m2,y2))) = (input.dob, id_node.dob) {
-                let mut s: f64 = 0.0;
-                if d1==d2 { s+=1.0/3.0 }
-                if m1==m2 { s+=1.0/3.0 }
-                if y1==y2 { s+=1.0/3.0 }
-                (s * 100.0_f64).round()
-            } else { 0.0 };
-            breakdown.push(FieldScore { field: "تاريخ الميلاد".into(), score: dob_score });
-
-            // Place - use normalized input and normalized IdentityNode.place_of_birth
-            // score_pair_with_soundex expects both inputs to be pre-normalized for Jaro/Lev,
-            // and handles Soundex internal normalization.
-            let place_score = (score_pair_with_soundex(&norm_input_place_of_birth, &id_node.place_of_birth) * 100.0_f64).round();
-            breakdown.push(FieldScore { field: "مكان الولادة".into(), score: place_score });
-
-            // Sex
-            let sex_score = if input.sex == id_node.sex { 100.0 } else { 0.0 };
-            breakdown.push(FieldScore { field: "الجنس".into(), score: sex_score });
-
-            // Total - use normalized inputs
-            let raw_total = calculate_full_score(
-                ( // Normalized input names
-                  &norm_input_first_name,
-                  &norm_input_last_name,
-                  &norm_input_father_name,
-                  &norm_input_grandfather_name,
-                  &norm_input_mother_last_name,
-                  &norm_input_mother_name,
-                ),
-                ( // Normalized IdentityNode names (already normalized by loader)
-                  &id_node.first_name,
-                  &id_node.last_name,
-                  &id_node.father_name,
-                  &id_node.grandfather_name,
-                  &id_node.mother_last_name,
-                  &id_node.mother_name,
-                ),
-                ( // Variations (calculate_full_score doesn't use these directly, best_score_against_variations does)
-                  &id_node.first_name_variations,
-                  &id_node.last_name_variations,
-                  &id_node.father_name_variations,
-                  &id_node.grandfather_name_variations,
-                  &id_node.mother_last_name_variations,
-                  &id_node.mother_name_variations,
-                ),
-                input.dob,
-                id_node.dob,
-                &norm_input_place_of_birth, // Normalized input place
-                &id_node.place_of_birth,   // Normalized IdentityNode place
-                input.sex,
-                id_node.sex,
-            ) * 100.0_f64;
-            let total_score = raw_total.round();
-
-            let dob_tuple = id_node.dob.unwrap_or((0,0,0)); // id_node here
-            let record = IdentityRecord {
-                first_name:       id_node.first_name.clone(),
-                last_name:        id_node.last_name.clone(),
-                father_name:      id_node.father_name.clone(),
-                grandfather_name: id_node.grandfather_name.clone(),
-                mother_last_name: id_node.mother_last_name.clone(),
-                mother_name:      id_node.mother_name.clone(),
-                dob:              dob_tuple,
-                sex:              id_node.sex,
-                place_of_birth:   id_node.place_of_birth.clone(),
-            };
-
-            MatchResult { matched_identity: record, total_score, breakdown }
-        })
-        .collect();
+    (StatusCode::OK, headers, Json(filtered))
+}
 
-    // *** Sort by descending total_score so take(1) is the highest match ***
-    results.sort_unstable_by(|a, b| b.total_score.partial_cmp(&a.total_score).unwrap());
+/// A stored `/match` request with its candidates and their decisions.
+async fn get_stored_match(
+    State(pool): State<db::ConnectionPool>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<StoredMatchRequest>, (StatusCode, String)> {
+    get_match_request(&pool, id)
+        .await
+        .map_err(|e| {
+            eprintln!("Failed to get match request: {}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, "Failed to get match request".to_string())
+        })?
+        .map(Json)
+        .ok_or((StatusCode::NOT_FOUND, format!("Unknown match request {}", id)))
+}
 
-    println!("✅ Scoring done ({} results).", results.len());
+/// Record the caller's decision on one candidate of a stored request.
+async fn add_decision(
+    State(pool): State<db::ConnectionPool>,
+    Extension(claims): Extension<Claims>,
+    Path(id): Path<Uuid>,
+    Json(body): Json<NewDecision>,
+) -> Result<(StatusCode, Json<DecisionRecord>), (StatusCode, String)> {
+    let decision = record_decision(&pool, id, &body.candidate_id, body.decision, &claims.email, body.comment.as_deref())
+        .await
+        .map_err(|e| {
+            eprintln!("Failed to record decision: {}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, "Failed to record decision".to_string())
+        })?
+        .ok_or((
+            StatusCode::NOT_FOUND,
+            format!("Match request {} did not return candidate {:?}", id, body.candidate_id),
+        ))?;
+    println!("⚖️ {} marked {} {} for request {}", claims.email, decision.candidate_id, decision.decision.as_str(), id);
+    Ok((StatusCode::CREATED, Json(decision)))
+}
+
+/// Low-confidence candidates still waiting for a verdict, oldest first.
+async fn get_review_queue(
+    State(pool): State<db::ConnectionPool>,
+    Query(query): Query<ReviewQuery>,
+) -> Result<Json<Vec<ReviewItem>>, (StatusCode, String)> {
+    let defaults = ReviewBand::from_env();
+    let band = ReviewBand {
+        min_score: query.min_score.unwrap_or(defaults.min_score),
+        max_score: query.max_score.unwrap_or(defaults.max_score),
+    };
+    if !band.min_score.is_finite() || !band.max_score.is_finite() {
+        return Err((StatusCode::BAD_REQUEST, "min_score and max_score must be numbers".to_string()));
+    }
+    let items = review_queue(&pool, band, query.limit.unwrap_or(50).min(1000))
+        .await
+        .map_err(|e| {
+            eprintln!("Failed to get review queue: {}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, "Failed to get review queue".to_string())
+        })?;
+    Ok(Json(items))
+}
+
+/// Confirmed decisions as a gold set file: CSV (default) or `?format=json`.
+async fn export_decisions(
+    State(pool): State<db::ConnectionPool>,
+    Query(query): Query<ExportQuery>,
+) -> Result<Response, (StatusCode, String)> {
+    let records = confirmed_decisions(&pool).await.map_err(|e| {
+        eprintln!("Failed to export decisions: {}", e);
+        (StatusCode::INTERNAL_SERVER_ERROR, "Failed to export decisions".to_string())
+    })?;
+    let (body, content_type, filename) = match query.format.as_deref().unwrap_or("csv") {
+        "csv" => (gold_set_to_csv(&records), "text/csv", "attachment; filename=\"gold_set.csv\""),
+        "json" => (gold_set_to_json(&records), "application/json", "attachment; filename=\"gold_set.json\""),
+        other => return Err((StatusCode::BAD_REQUEST, format!("Unknown export format {:?}", other))),
+    };
+    println!("📤 Exported {} confirmed decisions", records.len());
+    Ok(([(header::CONTENT_TYPE, content_type), (header::CONTENT_DISPOSITION, filename)], body).into_response())
+}
 
-    // 5) Threshold & return top-1
-    let filtered: Vec<MatchResult> = results
-        .into_iter()
-        .filter(|r| r.total_score >= 75.0)
-        .take(3) // Changed from 1 to 3
-        .collect();
-    println!("✅ Returning up to {} match(es) ≥ 75%.", filtered.len());
+/// Result format of a batch: NDJSON unless `csv` is asked for.
+fn output_format(name: Option<&str>) -> Result<BatchFormat, (StatusCode, String)> {
+    match name.map(BatchFormat::from_name) {
+        None => Ok(BatchFormat::Ndjson),
+        Some(Some(BatchFormat::Csv)) => Ok(BatchFormat::Csv),
+        Some(Some(_)) => Ok(BatchFormat::Ndjson),
+        Some(None) => Err((StatusCode::BAD_REQUEST, format!("Unknown result format {:?}", name))),
+    }
+}
+
+fn download(format: BatchFormat, body: Body) -> Response {
+    let filename = match format {
+        BatchFormat::Csv => "attachment; filename=\"matches.csv\"",
+        _ => "attachment; filename=\"matches.ndjson\"",
+    };
+    (
+        [(header::CONTENT_TYPE, format.content_type()), (header::CONTENT_DISPOSITION, filename)],
+        body,
+    )
+        .into_response()
+}
+
+/// The model to rank on: the loaded one unless `probability=false`; an error
+/// when the probability is asked for and no model is loaded.
+fn ranking_model(probability: Option<bool>, model: Option<Arc<MatchModel>>) -> Result<Option<Arc<MatchModel>>, String> {
+    match (probability, model) {
+        (Some(true), None) => Err("Match probability asked for but no match model is loaded".to_string()),
+        (Some(false), _) => Ok(None),
+        (_, model) => Ok(model),
+    }
+}
+
+/// Match a whole file of identities (JSON array, NDJSON or CSV).
+/// Small batches stream their results back as rows are scored; batches over
+/// BATCH_ASYNC_ROWS (default 10000) or sent with `mode=async` become a
+/// background job to poll at `/match/batch/:job_id`. Rows are ranked on the
+/// match model like `/match`.
+#[allow(clippy::too_many_arguments)]
+async fn match_batch(
+    State(profiles): State<Arc<ScoringProfiles>>,
+    State(store): State<Arc<IdentityStore>>,
+    State(jobs): State<Arc<BatchJobs>>,
+    State(limits): State<Arc<MatchLimits>>,
+    State(model): State<Option<Arc<MatchModel>>>,
+    Query(query): Query<BatchQuery>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<Response, (StatusCode, String)> {
+    let profile = profiles
+        .get(query.profile.as_deref())
+        .cloned()
+        .ok_or((StatusCode::BAD_REQUEST, format!("Unknown scoring profile {:?}", query.profile)))?;
+    let model = ranking_model(query.probability, model).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+
+    let input_format = match query.input_format.as_deref() {
+        Some(name) => BatchFormat::from_name(name)
+            .ok_or((StatusCode::BAD_REQUEST, format!("Unknown input format {:?}", name)))?,
+        None => headers
+            .get(header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .and_then(BatchFormat::from_content_type)
+            .unwrap_or(BatchFormat::Json),
+    };
+    let output_format = output_format(query.format.as_deref())?;
+    let params = MatchParams {
+        min_score: query.min_score,
+        min_probability: query.min_probability,
+        top_k: query.top_k,
+        include_below_threshold: query.include_below_threshold,
+    };
+    let options = limits
+        .resolve(&params.or(profile.match_params()))
+        .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+
+    let rows = parse_batch(&body, input_format).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    let snapshot = store.snapshot();
+    if snapshot.is_empty() {
+        return Err((StatusCode::NOT_FOUND, "Identity store is empty".to_string()));
+    }
+    println!("📦 Batch of {} rows with scoring profile '{}'", rows.len(), profile.name);
 
-    (StatusCode::OK, Json(filtered))
+    let async_rows = std::env::var("BATCH_ASYNC_ROWS")
+        .ok()
+        .and_then(|v| v.parse::<usize>().ok())
+        .unwrap_or(10_000);
+
+    if query.mode.as_deref() == Some("async") || rows.len() > async_rows {
+        let job = jobs.create(&profile.name, rows.len());
+        let job_id = job.id;
+        let scoring = tokio::task::spawn_blocking({
+            let job = Arc::clone(&job);
+            move || {
+                for chunk in rows.chunks(BATCH_CHUNK_SIZE) {
+                    job.push_results(match_rows(&profile, model.as_deref(), &snapshot, chunk, &options));
+                }
+            }
+        });
+        // A panic while scoring marks the job failed instead of leaving it running
+        tokio::spawn(async move {
+            match scoring.await {
+                Ok(()) => {
+                    job.finish();
+                    println!("✅ Batch job {} done ({} rows).", job.id, job.total);
+                }
+                Err(e) => {
+                    let error = panic_message(e);
+                    eprintln!("❌ Batch job {} failed: {}", job.id, error);
+                    job.fail(error);
+                }
+            }
+        });
+        let accepted = serde_json::json!({
+            "job_id": job_id,
+            "status_url": format!("/match/batch/{}", job_id),
+            "results_url": format!("/match/batch/{}/results", job_id),
+        });
+        return Ok((StatusCode::ACCEPTED, Json(accepted)).into_response());
+    }
+
+    // Score chunk by chunk off the async runtime and stream each as it is ready
+    let (tx, rx) = tokio::sync::mpsc::channel::<String>(4);
+    tokio::task::spawn_blocking(move || {
+        if output_format == BatchFormat::Csv && tx.blocking_send(csv_header()).is_err() {
+            return;
+        }
+        for chunk in rows.chunks(BATCH_CHUNK_SIZE) {
+            let results = match_rows(&profile, model.as_deref(), &snapshot, chunk, &options);
+            if tx.blocking_send(encode_results(&results, output_format)).is_err() {
+                return; // client went away
+            }
+        }
+    });
+    let stream = futures_util::stream::unfold(rx, |mut rx| async move {
+        rx.recv().await.map(|chunk| (Ok::<_, Infallible>(chunk), rx))
+    });
+
+    Ok(download(output_format, Body::from_stream(stream)))
+}
+
+async fn batch_job_status(
+    State(jobs): State<Arc<BatchJobs>>,
+    Path(job_id): Path<Uuid>,
+) -> Result<Json<JobStatus>, (StatusCode, String)> {
+    let job = jobs
+        .get(&job_id)
+        .ok_or((StatusCode::NOT_FOUND, format!("Unknown batch job {}", job_id)))?;
+    Ok(Json(job.status(jobs.ttl())))
+}
+
+/// Text of a panic in a blocking task, or why the task did not complete.
+fn panic_message(error: tokio::task::JoinError) -> String {
+    if !error.is_panic() {
+        return error.to_string();
+    }
+    let payload = error.into_panic();
+    match payload.downcast_ref::<&str>() {
+        Some(message) => message.to_string(),
+        None => payload.downcast_ref::<String>().cloned().unwrap_or_else(|| "panic".to_string()),
+    }
+}
+
+/// Download the results of a finished job as NDJSON (default) or `?format=csv`.
+async fn batch_job_results(
+    State(jobs): State<Arc<BatchJobs>>,
+    Path(job_id): Path<Uuid>,
+    Query(query): Query<ResultsQuery>,
+) -> Result<Response, (StatusCode, String)> {
+    let job = jobs
+        .get(&job_id)
+        .ok_or((StatusCode::NOT_FOUND, format!("Unknown batch job {}", job_id)))?;
+    match job.state() {
+        JobState::Completed => {}
+        JobState::Running => return Err((StatusCode::CONFLICT, format!("Batch job {} is still running", job_id))),
+        JobState::Failed { error } => {
+            return Err((StatusCode::INTERNAL_SERVER_ERROR, format!("Batch job {} failed: {}", job_id, error)))
+        }
+    }
+
+    let format = output_format(query.format.as_deref())?;
+    let mut body = job.encode_results(format);
+    if format == BatchFormat::Csv {
+        body.insert_str(0, &csv_header());
+    }
+    Ok(download(format, Body::from(body)))
+}
+
+async fn list_variants(State(store): State<Arc<IdentityStore>>) -> Json<Vec<VariantClass>> {
+    Json(store.variants().classes())
+}
+
+/// 409 when there is no dictionary file to save an edit to, 500 when saving fails.
+fn variants_save_error(error: std::io::Error) -> (StatusCode, String) {
+    if error.kind() == std::io::ErrorKind::Unsupported {
+        (StatusCode::CONFLICT, format!("Name variants cannot be edited: {}", error))
+    } else {
+        (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to save name variants: {}", error))
+    }
+}
+
+/// Add a variant class, save the dictionary file and re-attach the variants
+/// to the records in memory.
+async fn add_variant_class(
+    State(store): State<Arc<IdentityStore>>,
+    Json(body): Json<NewVariantClass>,
+) -> Result<(StatusCode, Json<VariantClass>), (StatusCode, String)> {
+    let class = tokio::task::spawn_blocking(move || store.update_variants(|variants| variants.add(body.names)))
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, panic_message(e)))?
+        .map_err(variants_save_error)?
+        .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    println!("📚 Added name variant class {}: {:?}", class.id, class.names);
+    Ok((StatusCode::CREATED, Json(class)))
+}
+
+/// Remove a variant class, save the dictionary file and re-attach the variants.
+async fn remove_variant_class(
+    State(store): State<Arc<IdentityStore>>,
+    Path(id): Path<u32>,
+) -> Result<Json<VariantClass>, (StatusCode, String)> {
+    let class = tokio::task::spawn_blocking(move || {
+        store.update_variants(|variants| {
+            variants
+                .remove(id)
+                .ok_or((StatusCode::NOT_FOUND, format!("Unknown name variant class {}", id)))
+        })
+    })
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, panic_message(e)))?
+    .map_err(variants_save_error)??;
+    println!("📚 Removed name variant class {}: {:?}", class.id, class.names);
+    Ok(Json(class))
 }
@@ -1,112 +1,346 @@
-use serde::{Deserialize, Serialize};
-use std::{
-    io::{self, Write},
-};
-use rayon::prelude::*;
+use std::io::{self, Write};
 
 pub mod utils;
+use bb8::Pool;
+use bb8_postgres::PostgresConnectionManager;
+use tokio_postgres::NoTls;
 use utils::{
-    loader::{load_identities_by_generation, generation_key},
-    matching::{
-        should_consider_candidate,
-        best_score_against_variations,
-        score_pair_with_soundex,
-        calculate_full_score,
-    },
+    adjudication::confirmed_decisions,
+    blocking::{evaluate_blocking_on_gold_set, BlockingConfig},
+    gold_set::{load_gold_set_for_records, save_gold_set},
+    identity_store::StoreSnapshot,
+    loader::{load_identities_by_generations, generations_for, REGISTRY_DB},
+    registry_file::{is_registry_extract, load_registry_file, ColumnMapping},
+    classifier::{cross_validate, gold_features, MatchModel},
+    pipeline::{rank_candidates_with_model, select_matches, InputIdentity, MatchLimits, MatchParams, MatchResult},
+    dob::BirthDate,
     linked_list::IdentityNode,
+    scoring_profile::ScoringProfiles,
+    variants::VariantDictionary,
+    evaluate::{evaluate_gold_set, evaluate_scores},
+    scoring_profile::save_profiles,
+    tuning::{field_scores, tune_profile, Objective},
+    variant_mining::{
+        apply_approved, carry_over_reviews, load_proposals, mine_variants, save_proposals, DEFAULT_MIN_SUPPORT,
+    },
 };
 
-#[derive(Debug, Deserialize)]
-struct InputIdentity {
-    first_name:       String,
-    last_name:        String,
-    father_name:      String,
-    grandfather_name: String,
-    mother_last_name: String,
-    mother_name:      String,
-    dob:              Option<(u32, u32, u32)>,
-    sex:              u8,
-    place_of_birth:   String,
-}
-
-#[derive(Debug, Serialize)]
-struct FieldScore {
-    field: String,
-    score: f64,
+/// Command-line options: `--profiles <file.toml|file.json>`, `--profile <name>`,
+/// `--blocking <file.toml|file.json>`, `--blocking-report <gold set .csv|.json>`,
+/// `--variants <file.csv|file.json>`, `--evaluate <gold set>` (with `--json <file>`
+/// to also write the report as JSON), `--tune <gold set>` (with `--objective f1|recall@0.95`,
+/// `--folds <k>` and `--out <profiles .toml|.json>`), `--mine-variants <gold set>` (with
+/// `--min-support <n>` and `--proposals <file.json>`), `--apply-variants <file.json>`,
+/// `--train-model <gold set>` (with `--folds <k>` and `--out <model.json>`), `--model <model.json>`,
+/// `--export-decisions <gold set .csv|.json>`, `--registry <file.csv|file.jsonl|snapshot>`
+/// (read instead of PostgreSQL, `--snapshot <file>` being the same; `REGISTRY_SNAPSHOT`
+/// otherwise) with `--columns <file.toml|file.json>` naming the extract's columns,
+/// `--write-snapshot <file>` (from PostgreSQL or a `--registry` extract),
+/// `--min-score <percent>`, `--min-probability <percent>` (with a model),
+/// `--top-k <n>` and `--include-below-threshold`.
+#[derive(Debug, Default)]
+struct CliArgs {
+    profiles_path:   Option<String>,
+    profile:         Option<String>,
+    blocking_path:   Option<String>,
+    blocking_report: Option<String>,
+    variants_path:   Option<String>,
+    evaluate:        Option<String>,
+    json_path:       Option<String>,
+    tune:            Option<String>,
+    objective:       Option<String>,
+    folds:           Option<usize>,
+    out_path:        Option<String>,
+    mine_variants:   Option<String>,
+    min_support:     Option<usize>,
+    proposals_path:  Option<String>,
+    apply_variants:  Option<String>,
+    train_model:     Option<String>,
+    model_path:      Option<String>,
+    export_decisions: Option<String>,
+    write_snapshot:  Option<String>,
+    registry_path:   Option<String>,
+    columns_path:    Option<String>,
+    match_params:    MatchParams,
 }
 
-#[derive(Debug, Serialize)]
-struct MatchResult {
-    matched_identity: IdentityNodeSummary,
-    total_score:      f64,
-    breakdown:        Vec<FieldScore>,
+fn parse_args() -> CliArgs {
+    let mut args = CliArgs::default();
+    let mut iter = std::env::args().skip(1);
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--profiles" => args.profiles_path = iter.next(),
+            "--profile"  => args.profile = iter.next(),
+            "--blocking" => args.blocking_path = iter.next(),
+            "--blocking-report" => args.blocking_report = iter.next(),
+            "--variants" => args.variants_path = iter.next(),
+            "--evaluate" => args.evaluate = iter.next(),
+            "--json" => args.json_path = iter.next(),
+            "--tune" => args.tune = iter.next(),
+            "--objective" => args.objective = iter.next(),
+            "--folds" => args.folds = iter.next().and_then(|v| v.parse().ok()),
+            "--out" => args.out_path = iter.next(),
+            "--mine-variants" => args.mine_variants = iter.next(),
+            "--min-support" => args.min_support = iter.next().and_then(|v| v.parse().ok()),
+            "--proposals" => args.proposals_path = iter.next(),
+            "--apply-variants" => args.apply_variants = iter.next(),
+            "--train-model" => args.train_model = iter.next(),
+            "--model" => args.model_path = iter.next(),
+            "--export-decisions" => args.export_decisions = iter.next(),
+            "--write-snapshot" => args.write_snapshot = iter.next(),
+            "--registry" | "--snapshot" => args.registry_path = iter.next(),
+            "--columns" => args.columns_path = iter.next(),
+            "--min-score" => args.match_params.min_score = iter.next().and_then(|v| v.parse().ok()),
+            "--min-probability" => args.match_params.min_probability = iter.next().and_then(|v| v.parse().ok()),
+            "--top-k" => args.match_params.top_k = iter.next().and_then(|v| v.parse().ok()),
+            "--include-below-threshold" => args.match_params.include_below_threshold = Some(true),
+            other => eprintln!("⚠️  Ignoring unknown argument {}", other),
+        }
+    }
+    args.registry_path = args.registry_path.or_else(|| std::env::var("REGISTRY_SNAPSHOT").ok().filter(|p| !p.is_empty()));
+    args
 }
 
-/// A small serde‐friendly copy of IdentityNode for output
-#[derive(Debug, Serialize)]
-struct IdentityNodeSummary {
-    first_name:       String,
-    last_name:        String,
-    father_name:      String,
-    grandfather_name: String,
-    mother_last_name: String,
-    mother_name:      String,
-    dob:              (u32, u32, u32),
-    sex:              u8,
-    place_of_birth:   String,
+/// The whole registry from the `--registry` file when one is given,
+/// otherwise from PostgreSQL; without dictionary variants.
+async fn load_registry(args: &CliArgs, blocking: &BlockingConfig, columns: &ColumnMapping) -> Vec<IdentityNode> {
+    match &args.registry_path {
+        Some(path) if is_registry_extract(path) => load_registry_file(path, columns).expect("Failed to read registry file"),
+        Some(path) => StoreSnapshot::load_file(path, blocking).expect("Failed to load registry snapshot").into_records(),
+        None => load_identities_by_generations(None).await.expect("Failed to load the registry"),
+    }
 }
 
 #[tokio::main]
 async fn main() {
+    // 0) Pick the scoring profile for this run
+    let args = parse_args();
+    let profiles = match &args.profiles_path {
+        Some(path) => ScoringProfiles::load(path),
+        None => ScoringProfiles::from_env(),
+    }
+    .expect("Failed to load scoring profiles");
+    let Some(profile) = profiles.get(args.profile.as_deref()) else {
+        println!("⚠️  Unknown profile {:?}; available: {:?}", args.profile, profiles.names());
+        return;
+    };
+    println!("📐 Using scoring profile '{}'", profile.name);
+
+    let blocking = match &args.blocking_path {
+        Some(path) => BlockingConfig::load(path),
+        None => BlockingConfig::from_env(),
+    }
+    .expect("Failed to load blocking config");
+
+    let variants = match &args.variants_path {
+        Some(path) => VariantDictionary::load(path),
+        None => VariantDictionary::from_env(),
+    }
+    .expect("Failed to load name variants");
+
+    let columns = match &args.columns_path {
+        Some(path) => ColumnMapping::load(path),
+        None => ColumnMapping::from_env(),
+    }
+    .expect("Failed to load registry column mapping");
+
+    // Same threshold and top-K defaults as the server
+    let limits = MatchLimits::from_env().expect("Invalid match limits");
+
+    // Score every labelled pair of a gold set at the configured threshold
+    if let Some(gold_path) = &args.evaluate {
+        println!("🔍 Loading the whole registry…");
+        let mut records: Vec<IdentityNode> = load_registry(&args, &blocking, &columns).await;
+        variants.attach_all(&mut records);
+        let gold = load_gold_set_for_records(gold_path, &records).expect("Failed to load gold set");
+        let threshold = limits.resolve(&args.match_params.clone().or(profile.match_params())).expect("Invalid match parameters").min_score;
+        let report = evaluate_gold_set(profile, &records, &gold, threshold);
+        report.print();
+        if let Some(path) = &args.json_path {
+            let json = serde_json::to_string_pretty(&report).expect("report serializes");
+            std::fs::write(path, json).expect("Failed to write evaluation report");
+            println!("💾 Report written to {}", path);
+        }
+        return;
+    }
+
+    // Learn the profile's weights and threshold from a gold set
+    if let Some(gold_path) = &args.tune {
+        let objective_name = args.objective.as_deref().unwrap_or("f1");
+        let Some(objective) = Objective::parse(objective_name) else {
+            println!("⚠️  Unknown objective {:?}; use f1 or recall@<precision>", objective_name);
+            return;
+        };
+        println!("🔍 Loading the whole registry…");
+        let mut records: Vec<IdentityNode> = load_registry(&args, &blocking, &columns).await;
+        variants.attach_all(&mut records);
+        let gold = load_gold_set_for_records(gold_path, &records).expect("Failed to load gold set");
+        println!("▶ Scoring {} labelled pairs field by field…", gold.len());
+        let features = field_scores(profile, &records, &gold);
+        let report = tune_profile(profile, &features, objective, args.folds.unwrap_or(5), 42);
+        report.print();
+
+        let out = args.out_path.as_deref().unwrap_or("tuned_profiles.toml");
+        save_profiles(out, std::slice::from_ref(&report.profile)).expect("Failed to write tuned profile");
+        println!("💾 Profile '{}' written to {} (load it with --profiles {})", report.profile.name, out, out);
+        return;
+    }
+
+    // Write the operators' confirmed decisions as a gold set
+    if let Some(out) = &args.export_decisions {
+        let manager = PostgresConnectionManager::new_from_stringlike(REGISTRY_DB, NoTls).expect("Invalid connection string");
+        let pool = Pool::builder().max_size(1).build(manager).await.expect("Failed to build pool");
+        let records = confirmed_decisions(&pool).await.expect("Failed to read decisions");
+        save_gold_set(out, &records).expect("Failed to write gold set");
+        println!("💾 {} confirmed decisions written to {}", records.len(), out);
+        return;
+    }
+
+    // Normalize and index the whole registry once into a snapshot file
+    if let Some(out) = &args.write_snapshot {
+        println!("🔍 Loading the whole registry…");
+        // Without the dictionary variants: whoever loads the snapshot attaches its own
+        let records: Vec<IdentityNode> = match &args.registry_path {
+            Some(path) if is_registry_extract(path) => load_registry_file(path, &columns).expect("Failed to read registry file"),
+            _ => load_identities_by_generations(None).await.expect("Failed to load the registry"),
+        };
+        let snapshot = StoreSnapshot::build(records, &blocking);
+        snapshot.save(out, &blocking).expect("Failed to write registry snapshot");
+        println!("💾 {} records written to {} (start from it with --registry {} or REGISTRY_SNAPSHOT)", snapshot.len(), out, out);
+        return;
+    }
+
+    // Fit the match classifier on a gold set
+    if let Some(gold_path) = &args.train_model {
+        println!("🔍 Loading the whole registry…");
+        let mut records: Vec<IdentityNode> = load_registry(&args, &blocking, &columns).await;
+        variants.attach_all(&mut records);
+        let gold = load_gold_set_for_records(gold_path, &records).expect("Failed to load gold set");
+        println!("▶ Extracting features of {} labelled pairs…", gold.len());
+        let samples = gold_features(&records, &gold);
+        let model = match MatchModel::train(&samples) {
+            Ok(model) => model,
+            Err(e) => {
+                println!("⚠️  {}", e);
+                return;
+            }
+        };
+        println!("🧠 Coefficients:");
+        for (feature, weight) in model.coefficients() {
+            println!("  {:<30} {:>9.4}", feature, weight);
+        }
+
+        // Probabilities in percent, so the report reads like a profile's
+        let scored: Vec<(f64, bool)> = samples
+            .iter()
+            .map(|(features, is_match)| (model.probability(features) * 100.0, *is_match))
+            .collect();
+        let threshold = limits.resolve(&args.match_params).expect("Invalid match parameters").min_probability;
+        evaluate_scores("match model (training pairs)", &scored, threshold).print();
+
+        // The training pairs flatter the model; score each pair with a model
+        // that did not see it, on the folds `--tune` uses
+        let folds = args.folds.unwrap_or(5);
+        match cross_validate(&samples, folds, 42) {
+            Ok(held_out) => {
+                evaluate_scores(&format!("match model ({}-fold held out)", folds), &held_out, threshold).print()
+            }
+            Err(e) => println!("⚠️  No held-out evaluation: {}", e),
+        }
+
+        let out = args.out_path.as_deref().unwrap_or("match_model.json");
+        model.save(out).expect("Failed to write match model");
+        println!("💾 Model written to {} (use it with --model {} or MATCH_MODEL)", out, out);
+        return;
+    }
+
+    // Propose name variants from the confirmed pairs of a gold set
+    if let Some(gold_path) = &args.mine_variants {
+        println!("🔍 Loading the whole registry…");
+        let records: Vec<IdentityNode> = load_registry(&args, &blocking, &columns).await;
+        let gold = load_gold_set_for_records(gold_path, &records).expect("Failed to load gold set");
+        let min_support = args.min_support.unwrap_or(DEFAULT_MIN_SUPPORT);
+        let mut proposals = mine_variants(&gold, &variants, min_support);
+
+        // Re-mining keeps the decisions already taken in the proposals file
+        let out = args.proposals_path.as_deref().unwrap_or("variant_proposals.json");
+        if let Ok(previous) = load_proposals(out) {
+            carry_over_reviews(&mut proposals, &previous);
+        }
+        save_proposals(out, &proposals).expect("Failed to write variant proposals");
+
+        println!("📚 {} variant proposals with support ≥ {}:", proposals.len(), min_support);
+        for p in &proposals {
+            println!("  {:>5}  {} ~ {}  {:?} [{:?}]", p.support, p.names[0], p.names[1], p.fields, p.status);
+        }
+        println!("✍️  Mark them \"approved\" or \"rejected\" in {}, then run --apply-variants {}", out, out);
+        return;
+    }
+
+    // Add the approved proposals to the variant dictionary file
+    if let Some(proposals_path) = &args.apply_variants {
+        let Some(dictionary_path) = variants.path().map(str::to_string) else {
+            println!("⚠️  No variant dictionary file: pass --variants <file> or set NAME_VARIANTS.");
+            return;
+        };
+        let proposals = load_proposals(proposals_path).expect("Failed to read variant proposals");
+        let mut variants = variants;
+        let added = apply_approved(&proposals, &mut variants);
+        variants.save().expect("Failed to save name variants");
+        println!("✅ {} approved variants added to {} ({} classes)", added, dictionary_path, variants.len());
+        return;
+    }
+
+    // Report blocking recall on a gold set instead of matching
+    if let Some(gold_path) = &args.blocking_report {
+        println!("🔍 Loading the whole registry…");
+        let records: Vec<IdentityNode> = load_registry(&args, &blocking, &columns).await;
+        let gold = load_gold_set_for_records(gold_path, &records).expect("Failed to load gold set");
+        evaluate_blocking_on_gold_set(&blocking, &records, &gold).print();
+        return;
+    }
+
+    // Rank on the classifier's probability when a model is given
+    let model = match &args.model_path {
+        Some(path) => MatchModel::load(path).map(Some),
+        None => MatchModel::from_env(),
+    }
+    .expect("Failed to load match model");
+    if let Some(model) = &model {
+        println!("🧠 Match model trained on {} pairs", model.trained_pairs);
+    }
+
     // 1) Read user input first
     println!("▶ Enter the identity to match:");
     let input = read_identity_from_stdin();
 
-    // 2) Compute the decade key
-    let gen = input
-        .dob
-        .map(|(_, _, y)| generation_key(y as i32))
-        .unwrap_or_else(|| generation_key(0));
-    println!("🔍 Loading records for generation {}…", gen);
-
-    // 3) Load only that slice from Postgres
-    let records: Vec<IdentityNode> = load_identities_by_generation(gen).await;
-    if records.is_empty() {
-        println!("⚠️  No records found for generation {}.", gen);
-        return;
-    }
-    println!("✅ Loaded {} records.", records.len());
-
-    // 4) Pre-filter
-    let candidates: Vec<&IdentityNode> = records
-        .iter()
-        .filter(|id| {
-            should_consider_candidate(
-                &(
-                    &input.first_name,
-                    &input.last_name,
-                    &input.father_name,
-                    &input.grandfather_name,
-                    &input.mother_last_name,
-                    &input.mother_name,
-                    input.dob,
-                    input.sex,
-                    &input.place_of_birth,
-                ),
-                &(
-                    &id.first_name,
-                    &id.last_name,
-                    &id.father_name,
-                    &id.grandfather_name,
-                    &id.mother_last_name,
-                    &id.mother_name,
-                    id.dob,
-                    id.sex,
-                    &id.place_of_birth,
-                ),
-            )
-        })
-        .collect();
+    // 2) Compute the decade keys; no known year means every generation
+    let gens = generations_for(input.dob.as_ref(), profile.year_tolerance);
+
+    // 3) Load only that slice from Postgres, or the whole registry file
+    let snapshot = match &args.registry_path {
+        Some(path) if is_registry_extract(path) => {
+            let mut records = load_registry_file(path, &columns).expect("Failed to read registry file");
+            variants.attach_all(&mut records);
+            StoreSnapshot::build(records, &blocking)
+        }
+        Some(path) => StoreSnapshot::load_file(path, &blocking).expect("Failed to load registry snapshot"),
+        None => {
+            println!("🔍 Loading records for generations {:?}…", gens);
+            let mut records: Vec<IdentityNode> = load_identities_by_generations(gens.as_deref()).await.expect("Failed to load the registry");
+            if records.is_empty() {
+                println!("⚠️  No records found for generations {:?}.", gens);
+                return;
+            }
+            println!("✅ Loaded {} records.", records.len());
+            variants.attach_all(&mut records);
+            StoreSnapshot::build(records, &blocking)
+        }
+    };
+
+    // 4) Blocking & pre-filter
+    let norm_input = input.normalized();
+    let candidates: Vec<&IdentityNode> = snapshot.candidates(&norm_input, profile.year_tolerance);
     println!("✅ {} candidates after pre-filter.", candidates.len());
     if candidates.is_empty() {
         println!("No candidates passed the pre-filter. Try lowering your filter criteria.");
@@ -114,83 +348,24 @@ async fn main() {
     }
 
     // 5) Score & sort
-    let mut scored: Vec<MatchResult> = candidates
-        .par_iter()
-        .map(|id| {
-            let mut breakdown = Vec::new();
-            // name fields
-            let fields = [
-                ("الاسم الأول",   &input.first_name,    &id.first_name,    &id.first_name_variations),
-                ("اسم العائلة",   &input.last_name,     &id.last_name,     &id.last_name_variations),
-                ("اسم الأب",      &input.father_name,   &id.father_name,   &id.father_name_variations),
-                ("اسم الجد",      &input.grandfather_name, &id.grandfather_name, &id.grandfather_name_variations),
-                ("اسم عائلة الأم",&input.mother_last_name,&id.mother_last_name,&id.mother_last_name_variations),
-                ("اسم الأم",      &input.mother_name,    &id.mother_name,    &id.mother_name_variations),
-            ];
-            for (label, inp, base, vars) in fields {
-                let raw = best_score_against_variations(inp, base, vars) * 100.0_f64;
-                breakdown.push(FieldScore { field: label.to_string(), score: raw.round() });
-            }
-            // DOB
-            let dob_score: f64 = if let (Some((d1,m1,y1)), Some((d2,m2,y2)))=(input.dob,id.dob) {
-                let mut s: f64 = 0.0;
-                if d1==d2 { s+=1.0/3.0 }
-                if m1==m2 { s+=1.0/3.0 }
-                if y1==y2 { s+=1.0/3.0 }
-                (s * 100.0_f64).round()
-            } else { 0.0 };
-            breakdown.push(FieldScore { field: "تاريخ الميلاد".into(), score: dob_score });
-            // place
-            let place_score = (score_pair_with_soundex(&input.place_of_birth,&id.place_of_birth)*100.0_f64).round();
-            breakdown.push(FieldScore { field: "مكان الولادة".into(), score: place_score });
-            // sex
-            let sex_score = if input.sex==id.sex { 100.0 } else { 0.0 };
-            breakdown.push(FieldScore { field: "الجنس".into(), score: sex_score });
-            // total
-            let raw_total = calculate_full_score(
-                (
-                    &input.first_name,&input.last_name,&input.father_name,
-                    &input.grandfather_name,&input.mother_last_name,&input.mother_name
-                ),
-                (
-                    &id.first_name,&id.last_name,&id.father_name,
-                    &id.grandfather_name,&id.mother_last_name,&id.mother_name
-                ),
-                (
-                    &id.first_name_variations,&id.last_name_variations,
-                    &id.father_name_variations,&id.grandfather_name_variations,
-                    &id.mother_last_name_variations,&id.mother_name_variations
-                ),
-                input.dob,id.dob,&input.place_of_birth,&id.place_of_birth,input.sex,id.sex
-            ) * 100.0_f64;
-            let total_score = raw_total.round();
-
-            // build summary
-            let dob_tuple = id.dob.unwrap_or((0,0,0));
-            let summary = IdentityNodeSummary {
-                first_name:      id.first_name.clone(),
-                last_name:       id.last_name.clone(),
-                father_name:     id.father_name.clone(),
-                grandfather_name:id.grandfather_name.clone(),
-                mother_last_name:id.mother_last_name.clone(),
-                mother_name:     id.mother_name.clone(),
-                dob:             dob_tuple,
-                sex:             id.sex,
-                place_of_birth:  id.place_of_birth.clone(),
-            };
-
-            MatchResult { matched_identity: summary, total_score, breakdown }
-        })
-        .collect();
-    // sort descending
-    scored.sort_by(|a,b| b.total_score.partial_cmp(&a.total_score).unwrap());
-
-    // 6) Print top-3
-    println!("\n▶ Top 3 matches:");
-    for (i, m) in scored.into_iter().take(3).enumerate() {
-        println!("Match #{} → {}%", i+1, m.total_score);
-        for fs in &m.breakdown {
-            println!("  {:<15} : {:>5.1}%", fs.field, fs.score);
+    let scored: Vec<MatchResult> = rank_candidates_with_model(profile, model.as_ref(), &norm_input, &candidates);
+
+    // 6) Threshold & print top-K (same defaults and caps as the server)
+    let options = limits.resolve(&args.match_params.clone().or(profile.match_params())).expect("Invalid match parameters");
+    let selected = select_matches(scored, &options);
+    let threshold = if model.is_some() { options.min_probability } else { options.min_score };
+    println!("\n▶ Top {} matches ≥ {}% ({} found):", options.top_k, threshold, selected.len());
+    for (i, m) in selected.into_iter().enumerate() {
+        let below = if m.below_threshold { " [below threshold]" } else { "" };
+        let probability = m.match_probability.map(|p| format!(", match probability {:.2}%", p * 100.0)).unwrap_or_default();
+        println!("Match #{} → {}% (profile {}{}){}", i+1, m.total_score(), m.explanation.profile, probability, below);
+        for fs in &m.explanation.breakdown {
+            let variation = fs.matched_variation.as_deref().map(|v| format!(" ← {}", v)).unwrap_or_default();
+            let reason = fs.reason.as_deref().map(|r| format!(" ({})", r)).unwrap_or_default();
+            println!(
+                "  {:<15} : {:>6.2}% × {:>4.2} = {:>6.2}  [{}]{}{}",
+                fs.field, fs.score, fs.weight, fs.contribution, fs.metric, variation, reason
+            );
         }
     }
 }
@@ -212,23 +387,36 @@ fn read_identity_from_stdin() -> InputIdentity {
     let ml = ask("mother_last_name");
     let mom = ask("mother_name");
 
-    let day = ask("dob day").parse().ok();
-    let month = ask("dob month").parse().ok();
-    let year = ask("dob year").parse().ok();
+    // Leave day or month empty (or 0) when unknown; the year may also be
+    // a range ("1980-1985") or an age estimate ("~40")
+    let day = ask("dob day").parse().unwrap_or(0);
+    let month = ask("dob month").parse().unwrap_or(0);
+    let year = ask("dob year");
+    let dob = if let Some(age) = year.strip_prefix('~') {
+        age.trim().parse().ok().map(|age| BirthDate::Age { age, reference_date: None, tolerance: 1 })
+    } else if let Some((from, to)) = year.split_once('-') {
+        match (from.trim().parse(), to.trim().parse()) {
+            (Ok(from), Ok(to)) => Some(BirthDate::YearRange { from, to }),
+            _ => None,
+        }
+    } else {
+        year.parse().ok().map(|y| BirthDate::Tuple(day, month, y))
+    };
+    if let Some(Err(e)) = dob.map(|d| d.validate()) {
+        panic!("Invalid birth date: {}", e);
+    }
     let sex = ask("sex (1=M,2=F)").parse().unwrap_or(0);
     let place = ask("place_of_birth");
 
     InputIdentity {
+        id: None,
         first_name: first.clone(),
         last_name: last.clone(),
         father_name: father.clone(),
         grandfather_name: grand.clone(),
         mother_last_name: ml.clone(),
         mother_name: mom.clone(),
-        dob: match (day, month, year) {
-            (Some(d), Some(m), Some(y)) => Some((d, m, y)),
-            _ => None,
-        },
+        dob,
         sex,
         place_of_birth: place.clone(),
     }
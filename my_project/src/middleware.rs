@@ -10,6 +10,9 @@ use jsonwebtoken::{decode, DecodingKey, Validation};
 // Import the shared models
 use crate::{db::ConnectionPool, models::Claims};
 
+/// Record each authenticated call in `api_usage`. Tracking is best effort:
+/// when PostgreSQL is unreachable (e.g. a server started from a registry
+/// snapshot) the request is still served.
 pub async fn track_api_usage(
     State(pool): State<ConnectionPool>,
     headers: HeaderMap,
@@ -32,17 +35,24 @@ pub async fn track_api_usage(
                 let user_id = token_data.claims.sub;
                 let api_link = request.uri().to_string();
 
-                let conn = pool.get().await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-
-                conn.execute(
-                    "INSERT INTO api_usage (user_id, api_link) VALUES ($1, $2)",
-                    &[&user_id, &api_link],
-                )
-                    .await
-                    .map_err(|e| {
+                // In the background, so a slow or unreachable database does
+                // not hold up the request
+                tokio::spawn(async move {
+                    let tracked = match pool.get().await {
+                        Ok(conn) => conn
+                            .execute(
+                                "INSERT INTO api_usage (user_id, api_link) VALUES ($1, $2)",
+                                &[&user_id, &api_link],
+                            )
+                            .await
+                            .map(|_| ())
+                            .map_err(|e| e.to_string()),
+                        Err(e) => Err(e.to_string()),
+                    };
+                    if let Err(e) = tracked {
                         eprintln!("Failed to track API usage: {}", e);
-                        StatusCode::INTERNAL_SERVER_ERROR
-                    })?;
+                    }
+                });
             }
         }
     }
@@ -0,0 +1,375 @@
+// src/utils/adjudication.rs
+
+//! Operators' decisions on `/match` results.
+//!
+//! Every `/match` that returns candidates is stored (`match_requests`,
+//! `match_candidates`); operators then record whether each candidate is the
+//! person asked for (`match_decisions`, one row per decision so earlier ones
+//! stay on record, the latest one counting). Undecided or unsure candidates
+//! scoring inside the review band make up the review queue, and confirmed
+//! decisions export as a gold set.
+
+use std::time::SystemTime;
+use bb8::Pool;
+use bb8_postgres::PostgresConnectionManager;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio_postgres::{NoTls, Row};
+use uuid::Uuid;
+use crate::utils::gold_set::GoldSetRecord;
+use crate::utils::linked_list::hashed_identity_id;
+use crate::utils::pipeline::{InputIdentity, MatchResult};
+
+type DbPool = Pool<PostgresConnectionManager<NoTls>>;
+
+/// An operator's verdict on one candidate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Decision {
+    Match,
+    NonMatch,
+    Unsure,
+}
+
+impl Decision {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Decision::Match => "match",
+            Decision::NonMatch => "non_match",
+            Decision::Unsure => "unsure",
+        }
+    }
+
+    pub fn parse(text: &str) -> Option<Self> {
+        match text {
+            "match" => Some(Decision::Match),
+            "non_match" => Some(Decision::NonMatch),
+            "unsure" => Some(Decision::Unsure),
+            _ => None,
+        }
+    }
+
+    /// Gold set label; `None` for `Unsure`.
+    pub fn label(&self) -> Option<bool> {
+        match self {
+            Decision::Match => Some(true),
+            Decision::NonMatch => Some(false),
+            Decision::Unsure => None,
+        }
+    }
+}
+
+/// One recorded decision.
+#[derive(Debug, Clone, Serialize)]
+pub struct DecisionRecord {
+    pub id: i32,
+    pub request_id: Uuid,
+    pub candidate_id: String,
+    pub decision: Decision,
+    pub reviewer: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub comment: Option<String>,
+    pub decided_at: String,
+}
+
+/// A candidate returned for a stored request, with its decisions, newest first.
+#[derive(Debug, Clone, Serialize)]
+pub struct StoredCandidate {
+    pub candidate_id: String,
+    pub rank: i32,
+    /// Profile total in percent
+    pub score: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub match_probability: Option<f64>,
+    pub candidate: Value,
+    pub decisions: Vec<DecisionRecord>,
+}
+
+/// A stored `/match` request and what it returned.
+#[derive(Debug, Clone, Serialize)]
+pub struct StoredMatchRequest {
+    pub id: Uuid,
+    pub user_id: String,
+    pub profile: String,
+    pub input: Value,
+    pub created_at: String,
+    pub candidates: Vec<StoredCandidate>,
+}
+
+/// A candidate waiting for a decision.
+#[derive(Debug, Clone, Serialize)]
+pub struct ReviewItem {
+    pub request_id: Uuid,
+    pub candidate_id: String,
+    pub profile: String,
+    pub score: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub match_probability: Option<f64>,
+    pub input: Value,
+    pub candidate: Value,
+    pub requested_at: String,
+    /// `unsure` when already looked at without a verdict
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub decision: Option<Decision>,
+}
+
+/// Scores (percent, the match probability when there is one) counted as
+/// low-confidence: from `min_score` up to, not including, `max_score`.
+#[derive(Debug, Clone, Copy)]
+pub struct ReviewBand {
+    pub min_score: f64,
+    pub max_score: f64,
+}
+
+impl ReviewBand {
+    /// REVIEW_MIN_SCORE (default 50) and REVIEW_MAX_SCORE (default 85).
+    pub fn from_env() -> Self {
+        let read = |name: &str, default: f64| {
+            std::env::var(name).ok().and_then(|v| v.parse().ok()).unwrap_or(default)
+        };
+        ReviewBand {
+            min_score: read("REVIEW_MIN_SCORE", 50.0),
+            max_score: read("REVIEW_MAX_SCORE", 85.0),
+        }
+    }
+}
+
+/// Gold set ID of a match input: its registry ID when given, otherwise the
+/// `hashed_identity_id` a registry record with the same fields would have.
+pub fn input_id(input: &InputIdentity) -> String {
+    if let Some(id) = &input.id {
+        return id.clone();
+    }
+    let norm = input.normalized();
+    hashed_identity_id(
+        [
+            &norm.first_name,
+            &norm.last_name,
+            &norm.father_name,
+            &norm.grandfather_name,
+            &norm.mother_last_name,
+            &norm.mother_name,
+        ],
+        norm.dob.and_then(|d| d.as_tuple()),
+        norm.sex,
+        &norm.place_of_birth,
+    )
+}
+
+fn timestamp(row: &Row, column: &str) -> String {
+    let time: SystemTime = row.get(column);
+    DateTime::<Utc>::from(time).to_rfc3339()
+}
+
+fn decision_from_row(row: &Row) -> DecisionRecord {
+    let decision: String = row.get("decision");
+    DecisionRecord {
+        id: row.get("id"),
+        request_id: row.get("request_id"),
+        candidate_id: row.get("candidate_id"),
+        decision: Decision::parse(&decision).unwrap_or(Decision::Unsure),
+        reviewer: row.get("reviewer"),
+        comment: row.get("comment"),
+        decided_at: timestamp(row, "decided_at"),
+    }
+}
+
+/// Store a `/match` request under `id` with the candidates it returned.
+pub async fn store_match_request(
+    pool: &DbPool,
+    id: Uuid,
+    user_id: &str,
+    profile: &str,
+    input: &InputIdentity,
+    results: &[MatchResult],
+) -> Result<(), String> {
+    let input_json = serde_json::to_value(input).map_err(|e| e.to_string())?;
+    let input_id = input_id(input);
+
+    let mut conn = pool.get().await.map_err(|e| e.to_string())?;
+    let tx = conn.transaction().await.map_err(|e| e.to_string())?;
+    tx.execute(
+        "INSERT INTO match_requests (id, user_id, profile, input_id, input) VALUES ($1, $2, $3, $4, $5)",
+        &[&id, &user_id, &profile, &input_id, &input_json],
+    )
+    .await
+    .map_err(|e| e.to_string())?;
+
+    let insert = tx
+        .prepare(
+            "INSERT INTO match_candidates (request_id, candidate_id, rank, score, match_probability, candidate)
+             VALUES ($1, $2, $3, $4, $5, $6)
+             ON CONFLICT DO NOTHING",
+        )
+        .await
+        .map_err(|e| e.to_string())?;
+    for (rank, result) in results.iter().enumerate() {
+        let candidate = serde_json::to_value(&result.matched_identity).map_err(|e| e.to_string())?;
+        tx.execute(
+            &insert,
+            &[
+                &id,
+                &result.matched_identity.id,
+                &(rank as i32 + 1),
+                &result.total_score(),
+                &result.match_probability,
+                &candidate,
+            ],
+        )
+        .await
+        .map_err(|e| e.to_string())?;
+    }
+    tx.commit().await.map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// A stored request with its candidates and their decisions.
+pub async fn get_match_request(pool: &DbPool, id: Uuid) -> Result<Option<StoredMatchRequest>, String> {
+    let conn = pool.get().await.map_err(|e| e.to_string())?;
+    let Some(request) = conn
+        .query_opt("SELECT * FROM match_requests WHERE id = $1", &[&id])
+        .await
+        .map_err(|e| e.to_string())?
+    else {
+        return Ok(None);
+    };
+
+    let decisions: Vec<DecisionRecord> = conn
+        .query(
+            "SELECT * FROM match_decisions WHERE request_id = $1 ORDER BY decided_at DESC, id DESC",
+            &[&id],
+        )
+        .await
+        .map_err(|e| e.to_string())?
+        .iter()
+        .map(decision_from_row)
+        .collect();
+
+    let candidates = conn
+        .query("SELECT * FROM match_candidates WHERE request_id = $1 ORDER BY rank", &[&id])
+        .await
+        .map_err(|e| e.to_string())?
+        .iter()
+        .map(|row| {
+            let candidate_id: String = row.get("candidate_id");
+            StoredCandidate {
+                decisions: decisions.iter().filter(|d| d.candidate_id == candidate_id).cloned().collect(),
+                candidate_id,
+                rank: row.get("rank"),
+                score: row.get("score"),
+                match_probability: row.get("match_probability"),
+                candidate: row.get("candidate"),
+            }
+        })
+        .collect();
+
+    Ok(Some(StoredMatchRequest {
+        id,
+        user_id: request.get("user_id"),
+        profile: request.get("profile"),
+        input: request.get("input"),
+        created_at: timestamp(&request, "created_at"),
+        candidates,
+    }))
+}
+
+/// Record a decision on a candidate of a stored request; `None` when the
+/// request did not return that candidate.
+pub async fn record_decision(
+    pool: &DbPool,
+    request_id: Uuid,
+    candidate_id: &str,
+    decision: Decision,
+    reviewer: &str,
+    comment: Option<&str>,
+) -> Result<Option<DecisionRecord>, String> {
+    let conn = pool.get().await.map_err(|e| e.to_string())?;
+    let row = conn
+        .query_opt(
+            "INSERT INTO match_decisions (request_id, candidate_id, decision, reviewer, comment)
+             SELECT request_id, candidate_id, $3, $4, $5
+             FROM match_candidates WHERE request_id = $1 AND candidate_id = $2
+             RETURNING *",
+            &[&request_id, &candidate_id, &decision.as_str(), &reviewer, &comment],
+        )
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(row.as_ref().map(decision_from_row))
+}
+
+/// Oldest candidates in `band` with no decision yet, or only `unsure`.
+pub async fn review_queue(pool: &DbPool, band: ReviewBand, limit: usize) -> Result<Vec<ReviewItem>, String> {
+    let conn = pool.get().await.map_err(|e| e.to_string())?;
+    let rows = conn
+        .query(
+            "SELECT c.request_id, c.candidate_id, c.score, c.match_probability, c.candidate,
+                    r.profile, r.input, r.created_at, d.decision
+             FROM match_candidates c
+             JOIN match_requests r ON r.id = c.request_id
+             LEFT JOIN LATERAL (
+                 SELECT decision FROM match_decisions
+                 WHERE request_id = c.request_id AND candidate_id = c.candidate_id
+                 ORDER BY decided_at DESC, id DESC
+                 LIMIT 1
+             ) d ON TRUE
+             WHERE COALESCE(c.match_probability * 100, c.score) >= $1
+               AND COALESCE(c.match_probability * 100, c.score) < $2
+               AND (d.decision IS NULL OR d.decision = 'unsure')
+             ORDER BY r.created_at, c.rank
+             LIMIT $3",
+            &[&band.min_score, &band.max_score, &(limit as i64)],
+        )
+        .await
+        .map_err(|e| e.to_string())?;
+
+    Ok(rows
+        .iter()
+        .map(|row| {
+            let decision: Option<String> = row.get("decision");
+            ReviewItem {
+                request_id: row.get("request_id"),
+                candidate_id: row.get("candidate_id"),
+                profile: row.get("profile"),
+                score: row.get("score"),
+                match_probability: row.get("match_probability"),
+                input: row.get("input"),
+                candidate: row.get("candidate"),
+                requested_at: timestamp(row, "created_at"),
+                decision: decision.as_deref().and_then(Decision::parse),
+            }
+        })
+        .collect())
+}
+
+/// Latest decision on every (input, candidate) pair, across requests,
+/// where it is `match` or `non_match`: a gold set for `gold_set::save_gold_set`.
+/// Each pair carries the stored input, so one whose input is not a registry
+/// record still resolves when the gold set is loaded.
+pub async fn confirmed_decisions(pool: &DbPool) -> Result<Vec<GoldSetRecord>, String> {
+    let conn = pool.get().await.map_err(|e| e.to_string())?;
+    let rows = conn
+        .query(
+            "SELECT DISTINCT ON (r.input_id, d.candidate_id) r.input_id, r.input, d.candidate_id, d.decision
+             FROM match_decisions d
+             JOIN match_requests r ON r.id = d.request_id
+             ORDER BY r.input_id, d.candidate_id, d.decided_at DESC, d.id DESC",
+            &[],
+        )
+        .await
+        .map_err(|e| e.to_string())?;
+
+    Ok(rows
+        .iter()
+        .filter_map(|row| {
+            let decision: String = row.get("decision");
+            let input: serde_json::Value = row.get("input");
+            Some(GoldSetRecord {
+                input_id: row.get("input_id"),
+                candidate_id: row.get("candidate_id"),
+                is_match: Decision::parse(&decision)?.label()?,
+                input: serde_json::from_value(input).ok(),
+            })
+        })
+        .collect())
+}
@@ -0,0 +1,404 @@
+// src/utils/batch.rs
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+use chrono::{DateTime, TimeDelta, Utc};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use uuid::Uuid;
+use crate::utils::dob::BirthDate;
+use crate::utils::identity_store::StoreSnapshot;
+use crate::utils::classifier::MatchModel;
+use crate::utils::pipeline::{rank_candidates_with_model, select_matches, InputIdentity, MatchOptions, MatchResult};
+use crate::utils::scoring_profile::ScoringProfile;
+
+/// Rows scored together before their results are streamed out or stored.
+pub const BATCH_CHUNK_SIZE: usize = 512;
+
+/// Upload and download formats of `/match/batch`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BatchFormat {
+    /// A JSON array of identities (input only)
+    Json,
+    /// One JSON object per line
+    Ndjson,
+    Csv,
+}
+
+impl BatchFormat {
+    /// `json`, `ndjson` (or `jsonl`) and `csv`.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "json"            => Some(BatchFormat::Json),
+            "ndjson" | "jsonl" => Some(BatchFormat::Ndjson),
+            "csv"             => Some(BatchFormat::Csv),
+            _ => None,
+        }
+    }
+
+    pub fn from_content_type(content_type: &str) -> Option<Self> {
+        let mime = content_type.split(';').next().unwrap_or("").trim().to_lowercase();
+        match mime.as_str() {
+            "application/json" => Some(BatchFormat::Json),
+            "application/x-ndjson" | "application/ndjson" | "application/jsonl" => Some(BatchFormat::Ndjson),
+            "text/csv" | "application/csv" => Some(BatchFormat::Csv),
+            _ => None,
+        }
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            BatchFormat::Json   => "application/json",
+            BatchFormat::Ndjson => "application/x-ndjson",
+            BatchFormat::Csv    => "text/csv; charset=utf-8",
+        }
+    }
+}
+
+/// One identity of an upload with the caller's row id
+/// (the 1-based row number when the upload has none).
+#[derive(Debug, Clone)]
+pub struct BatchRow {
+    pub row_id: String,
+    pub input:  InputIdentity,
+}
+
+/// JSON / NDJSON row: an `InputIdentity` plus an optional `row_id`.
+#[derive(Debug, Deserialize)]
+struct JsonBatchRow {
+    #[serde(default)]
+    row_id: Option<Value>,
+    #[serde(flatten)]
+    identity: InputIdentity,
+}
+
+/// CSV row; `dob` is text as accepted by `BirthDate::parse`, or empty.
+#[derive(Debug, Deserialize)]
+struct CsvBatchRow {
+    #[serde(default)]
+    row_id:           Option<String>,
+    #[serde(default)]
+    first_name:       String,
+    #[serde(default)]
+    last_name:        String,
+    #[serde(default)]
+    father_name:      String,
+    #[serde(default)]
+    grandfather_name: String,
+    #[serde(default)]
+    mother_last_name: String,
+    #[serde(default)]
+    mother_name:      String,
+    #[serde(default)]
+    dob:              String,
+    #[serde(default)]
+    sex:              u8,
+    #[serde(default)]
+    place_of_birth:   String,
+}
+
+fn row_id(value: Option<Value>, line: usize) -> String {
+    match value {
+        Some(Value::String(id)) if !id.is_empty() => id,
+        Some(Value::Null) | None => line.to_string(),
+        Some(other) => other.to_string(),
+    }
+}
+
+/// Parse an uploaded batch. Errors name the offending row.
+pub fn parse_batch(body: &[u8], format: BatchFormat) -> Result<Vec<BatchRow>, String> {
+    match format {
+        BatchFormat::Json => {
+            let rows: Vec<JsonBatchRow> =
+                serde_json::from_slice(body).map_err(|e| format!("Invalid JSON array: {}", e))?;
+            Ok(rows
+                .into_iter()
+                .enumerate()
+                .map(|(i, row)| BatchRow { row_id: row_id(row.row_id, i + 1), input: row.identity })
+                .collect())
+        }
+        BatchFormat::Ndjson => {
+            let text = std::str::from_utf8(body).map_err(|e| format!("Invalid UTF-8: {}", e))?;
+            text.lines()
+                .enumerate()
+                .filter(|(_, line)| !line.trim().is_empty())
+                .map(|(i, line)| {
+                    let row: JsonBatchRow =
+                        serde_json::from_str(line).map_err(|e| format!("Line {}: {}", i + 1, e))?;
+                    Ok(BatchRow { row_id: row_id(row.row_id, i + 1), input: row.identity })
+                })
+                .collect()
+        }
+        BatchFormat::Csv => {
+            let mut reader = csv::ReaderBuilder::new().has_headers(true).trim(csv::Trim::All).from_reader(body);
+            reader
+                .deserialize::<CsvBatchRow>()
+                .enumerate()
+                .map(|(i, row)| {
+                    let row = row.map_err(|e| format!("Row {}: {}", i + 1, e))?;
+                    let dob = match row.dob.as_str() {
+                        "" => None,
+                        text => Some(BirthDate::parse(text).ok_or_else(|| format!("Row {}: invalid dob {:?}", i + 1, text))?),
+                    };
+                    Ok(BatchRow {
+                        row_id: row.row_id.filter(|id| !id.is_empty()).unwrap_or_else(|| (i + 1).to_string()),
+                        input: InputIdentity {
+                            id:               None,
+                            first_name:       row.first_name,
+                            last_name:        row.last_name,
+                            father_name:      row.father_name,
+                            grandfather_name: row.grandfather_name,
+                            mother_last_name: row.mother_last_name,
+                            mother_name:      row.mother_name,
+                            dob,
+                            sex:              row.sex,
+                            place_of_birth:   row.place_of_birth,
+                        },
+                    })
+                })
+                .collect()
+        }
+    }
+}
+
+/// Matches of one uploaded row.
+#[derive(Debug, Clone, Serialize)]
+pub struct BatchRowResult {
+    pub row_id:  String,
+    pub matches: Vec<MatchResult>,
+}
+
+/// Run one row through the `/match` pipeline, ranked on the match
+/// probability of `model` when given.
+pub fn match_row(
+    profile: &ScoringProfile,
+    model: Option<&MatchModel>,
+    snapshot: &StoreSnapshot,
+    row: &BatchRow,
+    options: &MatchOptions,
+) -> BatchRowResult {
+    let norm_input = row.input.normalized();
+    let candidates = snapshot.candidates(&norm_input, profile.year_tolerance);
+    let matches = select_matches(rank_candidates_with_model(profile, model, &norm_input, &candidates), options);
+    BatchRowResult { row_id: row.row_id.clone(), matches }
+}
+
+/// `match_row` over `rows` in parallel, in input order.
+pub fn match_rows(
+    profile: &ScoringProfile,
+    model: Option<&MatchModel>,
+    snapshot: &StoreSnapshot,
+    rows: &[BatchRow],
+    options: &MatchOptions,
+) -> Vec<BatchRowResult> {
+    rows.par_iter()
+        .map(|row| match_row(profile, model, snapshot, row, options))
+        .collect()
+}
+
+/// Column names of the CSV download; one line per match, or a single line
+/// with empty match columns for a row without matches.
+pub fn csv_header() -> String {
+    "row_id,rank,total_score,profile,identity_id,first_name,last_name,father_name,grandfather_name,\
+     mother_last_name,mother_name,dob,sex,place_of_birth,below_threshold,match_probability\n"
+        .to_string()
+}
+
+/// Serialize results in `format` (NDJSON for `Json`), without the CSV header.
+pub fn encode_results(results: &[BatchRowResult], format: BatchFormat) -> String {
+    match format {
+        BatchFormat::Json | BatchFormat::Ndjson => results
+            .iter()
+            .map(|result| serde_json::to_string(result).expect("batch result serializes") + "\n")
+            .collect(),
+        BatchFormat::Csv => {
+            let mut writer = csv::WriterBuilder::new().has_headers(false).from_writer(Vec::new());
+            for result in results {
+                if result.matches.is_empty() {
+                    writer
+                        .write_record([result.row_id.as_str(), "", "", "", "", "", "", "", "", "", "", "", "", "", "", ""])
+                        .expect("write to memory");
+                }
+                for (rank, m) in result.matches.iter().enumerate() {
+                    let record = &m.matched_identity;
+                    let (d, mo, y) = record.dob;
+                    writer
+                        .write_record([
+                            result.row_id.clone(),
+                            (rank + 1).to_string(),
+                            m.total_score().to_string(),
+                            m.explanation.profile.clone(),
+                            record.id.clone(),
+                            record.first_name.clone(),
+                            record.last_name.clone(),
+                            record.father_name.clone(),
+                            record.grandfather_name.clone(),
+                            record.mother_last_name.clone(),
+                            record.mother_name.clone(),
+                            format!("{:02}/{:02}/{}", d, mo, y),
+                            record.sex.to_string(),
+                            record.place_of_birth.clone(),
+                            m.below_threshold.to_string(),
+                            m.match_probability.map(|p| p.to_string()).unwrap_or_default(),
+                        ])
+                        .expect("write to memory");
+                }
+            }
+            String::from_utf8(writer.into_inner().expect("flush to memory")).expect("CSV is UTF-8")
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "state", rename_all = "snake_case")]
+pub enum JobState {
+    Running,
+    Completed,
+    /// Scoring stopped, e.g. on a panic; the results so far are kept
+    Failed { error: String },
+}
+
+/// A batch scored in the background; results accumulate in input order.
+#[derive(Debug)]
+pub struct BatchJob {
+    pub id:         Uuid,
+    pub profile:    String,
+    pub total:      usize,
+    pub created_at: DateTime<Utc>,
+    processed:      AtomicUsize,
+    /// When the job ended, and its error if it failed
+    finished:       Mutex<Option<(DateTime<Utc>, Option<String>)>>,
+    results:        Mutex<Vec<BatchRowResult>>,
+}
+
+/// What `GET /match/batch/:job_id` reports.
+#[derive(Debug, Clone, Serialize)]
+pub struct JobStatus {
+    pub job_id:      Uuid,
+    pub profile:     String,
+    #[serde(flatten)]
+    pub state:       JobState,
+    pub total:       usize,
+    pub processed:   usize,
+    pub created_at:  DateTime<Utc>,
+    pub finished_at: Option<DateTime<Utc>>,
+    /// When a finished job and its results are dropped
+    pub expires_at:  Option<DateTime<Utc>>,
+}
+
+impl BatchJob {
+    /// Append the results of the next chunk of rows.
+    pub fn push_results(&self, chunk: Vec<BatchRowResult>) {
+        let count = chunk.len();
+        self.results.lock().expect("batch job poisoned").extend(chunk);
+        self.processed.fetch_add(count, Ordering::Relaxed);
+    }
+
+    pub fn finish(&self) {
+        *self.finished.lock().expect("batch job poisoned") = Some((Utc::now(), None));
+    }
+
+    pub fn fail(&self, error: String) {
+        *self.finished.lock().expect("batch job poisoned") = Some((Utc::now(), Some(error)));
+    }
+
+    pub fn finished_at(&self) -> Option<DateTime<Utc>> {
+        self.finished.lock().expect("batch job poisoned").as_ref().map(|(at, _)| *at)
+    }
+
+    pub fn state(&self) -> JobState {
+        match &*self.finished.lock().expect("batch job poisoned") {
+            None => JobState::Running,
+            Some((_, None)) => JobState::Completed,
+            Some((_, Some(error))) => JobState::Failed { error: error.clone() },
+        }
+    }
+
+    /// Status with the time the job expires after `ttl`.
+    pub fn status(&self, ttl: TimeDelta) -> JobStatus {
+        let finished_at = self.finished_at();
+        JobStatus {
+            job_id:      self.id,
+            profile:     self.profile.clone(),
+            state:       self.state(),
+            total:       self.total,
+            processed:   self.processed.load(Ordering::Relaxed),
+            created_at:  self.created_at,
+            finished_at,
+            expires_at:  finished_at.and_then(|at| at.checked_add_signed(ttl)),
+        }
+    }
+
+    /// Results so far, serialized in `format`.
+    pub fn encode_results(&self, format: BatchFormat) -> String {
+        encode_results(&self.results.lock().expect("batch job poisoned"), format)
+    }
+}
+
+/// Longest time a finished job is kept: a year.
+const MAX_JOB_TTL: Duration = Duration::from_secs(365 * 86_400);
+
+/// In-memory registry of background batch jobs. Finished jobs are dropped
+/// with their results `ttl` after they end.
+#[derive(Debug)]
+pub struct BatchJobs {
+    jobs: RwLock<HashMap<Uuid, Arc<BatchJob>>>,
+    ttl:  TimeDelta,
+}
+
+impl BatchJobs {
+    /// `ttl` is capped at `MAX_JOB_TTL`.
+    pub fn new(ttl: Duration) -> Self {
+        BatchJobs {
+            jobs: RwLock::new(HashMap::new()),
+            ttl:  TimeDelta::from_std(ttl.min(MAX_JOB_TTL)).expect("MAX_JOB_TTL fits a TimeDelta"),
+        }
+    }
+
+    /// Keep finished jobs for BATCH_JOB_TTL_SECS (default 86400, at most a year).
+    pub fn from_env() -> Self {
+        let ttl = std::env::var("BATCH_JOB_TTL_SECS")
+            .ok()
+            .and_then(|v| v.parse::<u64>().ok())
+            .unwrap_or(86_400);
+        Self::new(Duration::from_secs(ttl))
+    }
+
+    pub fn ttl(&self) -> TimeDelta {
+        self.ttl
+    }
+
+    /// Register a new running job of `total` rows.
+    pub fn create(&self, profile: &str, total: usize) -> Arc<BatchJob> {
+        self.evict_expired();
+        let job = Arc::new(BatchJob {
+            id:          Uuid::new_v4(),
+            profile:     profile.to_string(),
+            total,
+            created_at:  Utc::now(),
+            processed:   AtomicUsize::new(0),
+            finished:    Mutex::new(None),
+            results:     Mutex::new(Vec::with_capacity(total)),
+        });
+        self.jobs.write().expect("batch jobs poisoned").insert(job.id, Arc::clone(&job));
+        job
+    }
+
+    pub fn get(&self, id: &Uuid) -> Option<Arc<BatchJob>> {
+        self.evict_expired();
+        self.jobs.read().expect("batch jobs poisoned").get(id).cloned()
+    }
+
+    /// Drop the jobs that finished more than `ttl` ago.
+    pub fn evict_expired(&self) {
+        let now = Utc::now();
+        let expired = |job: &BatchJob| job.finished_at().is_some_and(|at| now - at > self.ttl);
+        if !self.jobs.read().expect("batch jobs poisoned").values().any(|job| expired(job)) {
+            return;
+        }
+        self.jobs.write().expect("batch jobs poisoned").retain(|_, job| !expired(job));
+    }
+}
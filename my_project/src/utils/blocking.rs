@@ -0,0 +1,559 @@
+// src/utils/blocking.rs
+
+use std::collections::{BTreeSet, HashMap};
+use std::fs;
+use std::io;
+use std::path::Path;
+use serde::{Deserialize, Serialize};
+use crate::utils::gold_set::GoldSetIdentity;
+use crate::utils::linked_list::IdentityNode;
+use crate::utils::phonetic::{aramix_soundex, maghrebi_metaphone};
+use crate::utils::pipeline::{InputIdentity, NormalizedInput};
+use crate::utils::transliteration::skeleton;
+
+/// Ways of grouping records that could describe the same person.
+/// A record is a candidate for a query when they share at least one key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BlockingKey {
+    /// Soundex of the last name (the historical pre-filter)
+    LastNameSoundex,
+    /// Soundex of the first name + Soundex of the last name
+    FirstLastSoundex,
+    /// Enough shared character q-grams of the last name
+    LastNameQgrams,
+    /// Soundex of the father's + grandfather's names
+    FatherGrandfatherPhonetic,
+    /// Neighbours in the registry sorted by last + first name
+    SortedNeighbourhood,
+    /// Script-neutral consonant skeleton of the last name, so Latin
+    /// spellings reach Arabic records and the other way round
+    LastNameSkeleton,
+    /// Primary and alternate `maghrebi_metaphone` codes of the last name
+    LastNameMetaphone,
+}
+
+impl BlockingKey {
+    pub fn name(&self) -> &'static str {
+        match self {
+            BlockingKey::LastNameSoundex           => "last_name_soundex",
+            BlockingKey::FirstLastSoundex          => "first_last_soundex",
+            BlockingKey::LastNameQgrams            => "last_name_qgrams",
+            BlockingKey::FatherGrandfatherPhonetic => "father_grandfather_phonetic",
+            BlockingKey::SortedNeighbourhood       => "sorted_neighbourhood",
+            BlockingKey::LastNameSkeleton          => "last_name_skeleton",
+            BlockingKey::LastNameMetaphone         => "last_name_metaphone",
+        }
+    }
+}
+
+/// Which keys to build and how wide they are.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct BlockingConfig {
+    pub keys: Vec<BlockingKey>,
+    /// Length of the last-name q-grams
+    #[serde(default = "default_qgram_size")]
+    pub qgram_size: usize,
+    /// Share of the query's q-grams a record must contain (0–1)
+    #[serde(default = "default_qgram_min_share")]
+    pub qgram_min_share: f64,
+    /// Records taken on each side of the query in the sorted registry
+    #[serde(default = "default_window")]
+    pub window: usize,
+}
+
+fn default_qgram_size() -> usize {
+    2
+}
+
+fn default_qgram_min_share() -> f64 {
+    0.6
+}
+
+fn default_window() -> usize {
+    5
+}
+
+impl Default for BlockingConfig {
+    fn default() -> Self {
+        BlockingConfig {
+            keys: vec![
+                BlockingKey::LastNameSoundex,
+                BlockingKey::FirstLastSoundex,
+                BlockingKey::LastNameQgrams,
+                BlockingKey::FatherGrandfatherPhonetic,
+                BlockingKey::SortedNeighbourhood,
+                BlockingKey::LastNameSkeleton,
+                BlockingKey::LastNameMetaphone,
+            ],
+            qgram_size: default_qgram_size(),
+            qgram_min_share: default_qgram_min_share(),
+            window: default_window(),
+        }
+    }
+}
+
+impl BlockingConfig {
+    /// Loads a blocking configuration from a .toml or .json file; one that
+    /// does not `validate` is an `InvalidData` error.
+    pub fn load(file_path: &str) -> io::Result<Self> {
+        let path = Path::new(file_path);
+        let extension = path.extension().and_then(|ext| ext.to_str()).unwrap_or("");
+        let contents = fs::read_to_string(path)?;
+
+        let config: Self = match extension.to_lowercase().as_str() {
+            "toml" => toml::from_str(&contents)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?,
+            "json" => serde_json::from_str(&contents)?,
+            _ => return Err(io::Error::new(io::ErrorKind::InvalidInput, "Unsupported file format")),
+        };
+        config
+            .validate()
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", file_path, e)))?;
+        Ok(config)
+    }
+
+    /// Reject a configuration that would silently find no or fewer
+    /// candidates: no keys, q-grams of no characters, a q-gram share outside
+    /// 0–1 or an empty sorted-neighbourhood window.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.keys.is_empty() {
+            return Err("no blocking keys: every query would have no candidates".to_string());
+        }
+        if self.qgram_size == 0 {
+            return Err("qgram_size must be at least 1".to_string());
+        }
+        if !(0.0..=1.0).contains(&self.qgram_min_share) {
+            return Err(format!("qgram_min_share {} is not between 0 and 1", self.qgram_min_share));
+        }
+        if self.window == 0 {
+            return Err("window must be at least 1".to_string());
+        }
+        Ok(())
+    }
+
+    /// Loads the configuration from `BLOCKING_CONFIG` if set, otherwise the default one.
+    pub fn from_env() -> io::Result<Self> {
+        match std::env::var("BLOCKING_CONFIG") {
+            Ok(path) if !path.is_empty() => Self::load(&path),
+            _ => Ok(Self::default()),
+        }
+    }
+}
+
+/// The normalized name fields blocking keys are computed from.
+#[derive(Debug, Clone, Copy)]
+pub struct BlockingFields<'a> {
+    pub first_name:       &'a str,
+    pub last_name:        &'a str,
+    pub father_name:      &'a str,
+    pub grandfather_name: &'a str,
+}
+
+impl<'a> From<&'a IdentityNode> for BlockingFields<'a> {
+    fn from(node: &'a IdentityNode) -> Self {
+        BlockingFields {
+            first_name:       &node.first_name,
+            last_name:        &node.last_name,
+            father_name:      &node.father_name,
+            grandfather_name: &node.grandfather_name,
+        }
+    }
+}
+
+impl<'a> From<&'a NormalizedInput> for BlockingFields<'a> {
+    fn from(input: &'a NormalizedInput) -> Self {
+        BlockingFields {
+            first_name:       &input.first_name,
+            last_name:        &input.last_name,
+            father_name:      &input.father_name,
+            grandfather_name: &input.grandfather_name,
+        }
+    }
+}
+
+/// Padded character q-grams of `text`, spaces removed (`"#ab", "ab#"` style padding).
+fn qgrams(text: &str, q: usize) -> BTreeSet<String> {
+    let q = q.max(1);
+    let mut chars: Vec<char> = vec!['#'; q - 1];
+    chars.extend(text.chars().filter(|c| !c.is_whitespace()));
+    chars.extend(std::iter::repeat_n('#', q - 1));
+    if chars.len() < q {
+        return BTreeSet::new();
+    }
+    chars.windows(q).map(|w| w.iter().collect()).collect()
+}
+
+fn sort_key(fields: &BlockingFields) -> String {
+    format!("{} {}", fields.last_name, fields.first_name)
+}
+
+/// Exact-match keys of `fields` for the hash-based blocking keys.
+fn hash_keys(key: BlockingKey, fields: &BlockingFields, config: &BlockingConfig) -> Vec<String> {
+    match key {
+        BlockingKey::LastNameSoundex => vec![aramix_soundex(fields.last_name)],
+        BlockingKey::FirstLastSoundex => vec![format!(
+            "{}|{}",
+            aramix_soundex(fields.first_name),
+            aramix_soundex(fields.last_name)
+        )],
+        BlockingKey::FatherGrandfatherPhonetic => {
+            if fields.father_name.is_empty() && fields.grandfather_name.is_empty() {
+                return Vec::new();
+            }
+            vec![format!(
+                "{}|{}",
+                aramix_soundex(fields.father_name),
+                aramix_soundex(fields.grandfather_name)
+            )]
+        }
+        BlockingKey::LastNameQgrams => qgrams(fields.last_name, config.qgram_size).into_iter().collect(),
+        BlockingKey::LastNameSkeleton => {
+            let key = skeleton(fields.last_name);
+            if key.is_empty() { Vec::new() } else { vec![key] }
+        }
+        BlockingKey::LastNameMetaphone => {
+            let (primary, alternate) = maghrebi_metaphone(fields.last_name);
+            let mut keys: Vec<String> = vec![primary, alternate];
+            keys.retain(|k| !k.is_empty());
+            keys.dedup();
+            keys
+        }
+        BlockingKey::SortedNeighbourhood => Vec::new(),
+    }
+}
+
+/// Multi-key blocking index over a slice of records; candidates are record
+/// positions in that slice.
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct BlockingIndex {
+    config: BlockingConfig,
+    hashed: HashMap<BlockingKey, HashMap<String, Vec<usize>>>,
+    /// (sort key, record position), sorted
+    sorted: Vec<(String, usize)>,
+}
+
+impl BlockingIndex {
+    pub fn build(records: &[&IdentityNode], config: &BlockingConfig) -> Self {
+        let mut hashed: HashMap<BlockingKey, HashMap<String, Vec<usize>>> = HashMap::new();
+        let mut sorted = Vec::new();
+
+        for (i, node) in records.iter().enumerate() {
+            let fields = BlockingFields::from(*node);
+            for &key in &config.keys {
+                if key == BlockingKey::SortedNeighbourhood {
+                    sorted.push((sort_key(&fields), i));
+                    continue;
+                }
+                let buckets = hashed.entry(key).or_default();
+                for k in hash_keys(key, &fields, config) {
+                    buckets.entry(k).or_default().push(i);
+                }
+            }
+        }
+        sorted.sort_unstable();
+
+        BlockingIndex { config: config.clone(), hashed, sorted }
+    }
+
+    /// Record positions found by one key, sorted and without duplicates.
+    pub fn candidates_for_key(&self, key: BlockingKey, fields: &BlockingFields) -> Vec<usize> {
+        let mut found: Vec<usize> = match key {
+            BlockingKey::SortedNeighbourhood => {
+                if self.sorted.is_empty() {
+                    return Vec::new();
+                }
+                let pos = self.sorted.partition_point(|(k, _)| k.as_str() < sort_key(fields).as_str());
+                let from = pos.saturating_sub(self.config.window);
+                let to = (pos + self.config.window).min(self.sorted.len());
+                self.sorted[from..to].iter().map(|(_, i)| *i).collect()
+            }
+            BlockingKey::LastNameQgrams => {
+                let Some(buckets) = self.hashed.get(&key) else { return Vec::new() };
+                let grams = hash_keys(key, fields, &self.config);
+                let needed = ((grams.len() as f64 * self.config.qgram_min_share).ceil() as usize).max(1);
+                let mut shared: HashMap<usize, usize> = HashMap::new();
+                for gram in &grams {
+                    for &i in buckets.get(gram).into_iter().flatten() {
+                        *shared.entry(i).or_default() += 1;
+                    }
+                }
+                shared.into_iter().filter(|&(_, n)| n >= needed).map(|(i, _)| i).collect()
+            }
+            _ => {
+                let Some(buckets) = self.hashed.get(&key) else { return Vec::new() };
+                hash_keys(key, fields, &self.config)
+                    .iter()
+                    .filter_map(|k| buckets.get(k))
+                    .flatten()
+                    .copied()
+                    .collect()
+            }
+        };
+        found.sort_unstable();
+        found.dedup();
+        found
+    }
+
+    /// Candidates of every configured key.
+    pub fn candidates_by_key(&self, fields: &BlockingFields) -> Vec<(BlockingKey, Vec<usize>)> {
+        self.config
+            .keys
+            .iter()
+            .map(|&key| (key, self.candidates_for_key(key, fields)))
+            .collect()
+    }
+
+    /// Union of the candidates of every configured key, sorted.
+    pub fn candidates(&self, fields: &BlockingFields) -> Vec<usize> {
+        let mut all: Vec<usize> = self
+            .candidates_by_key(fields)
+            .into_iter()
+            .flat_map(|(_, found)| found)
+            .collect();
+        all.sort_unstable();
+        all.dedup();
+        all
+    }
+}
+
+/// Recall and cost of one blocking key (or of their union).
+#[derive(Debug, Clone, Serialize)]
+pub struct KeyStats {
+    pub key: String,
+    /// Share of true matches the key retrieves
+    pub recall: f64,
+    /// Average number of candidates per query
+    pub mean_candidates: f64,
+    pub max_candidates: usize,
+}
+
+/// Per-key recall and candidate-set size over labelled queries.
+#[derive(Debug, Clone, Serialize)]
+pub struct BlockingReport {
+    pub records: usize,
+    pub queries: usize,
+    pub per_key: Vec<KeyStats>,
+    pub union: KeyStats,
+}
+
+/// Evaluate `config` on `records`, each query being paired with the position
+/// of its true match in `records`.
+pub fn evaluate_blocking(
+    config: &BlockingConfig,
+    records: &[IdentityNode],
+    queries: &[(NormalizedInput, usize)],
+) -> BlockingReport {
+    let refs: Vec<&IdentityNode> = records.iter().collect();
+    let index = BlockingIndex::build(&refs, config);
+
+    // (hits, total candidates, max candidates) per key, union last
+    let mut tallies: Vec<(usize, usize, usize)> = vec![(0, 0, 0); config.keys.len() + 1];
+    for (input, expected) in queries {
+        let fields = BlockingFields::from(input);
+        let by_key = index.candidates_by_key(&fields);
+        let mut union: Vec<usize> = Vec::new();
+        for (slot, (_, found)) in by_key.iter().enumerate() {
+            let tally = &mut tallies[slot];
+            tally.0 += found.binary_search(expected).is_ok() as usize;
+            tally.1 += found.len();
+            tally.2 = tally.2.max(found.len());
+            union.extend(found);
+        }
+        union.sort_unstable();
+        union.dedup();
+        let tally = tallies.last_mut().expect("union slot");
+        tally.0 += union.binary_search(expected).is_ok() as usize;
+        tally.1 += union.len();
+        tally.2 = tally.2.max(union.len());
+    }
+
+    let n = queries.len().max(1) as f64;
+    let stats = |key: &str, (hits, total, max): (usize, usize, usize)| KeyStats {
+        key: key.to_string(),
+        recall: hits as f64 / n,
+        mean_candidates: total as f64 / n,
+        max_candidates: max,
+    };
+    let per_key = config
+        .keys
+        .iter()
+        .zip(&tallies)
+        .map(|(key, tally)| stats(key.name(), *tally))
+        .collect();
+
+    BlockingReport {
+        records: records.len(),
+        queries: queries.len(),
+        per_key,
+        union: stats("union", *tallies.last().expect("union slot")),
+    }
+}
+
+/// `evaluate_blocking` on the confirmed matches of a gold set: each matching
+/// pair's input is a query and its candidate the record it must retrieve.
+/// Pairs whose candidate is not among `records` are skipped.
+pub fn evaluate_blocking_on_gold_set(
+    config: &BlockingConfig,
+    records: &[IdentityNode],
+    gold: &[(GoldSetIdentity, GoldSetIdentity, bool)],
+) -> BlockingReport {
+    let positions: HashMap<&str, usize> = records.iter().enumerate().map(|(i, node)| (node.id.as_str(), i)).collect();
+
+    let queries: Vec<(NormalizedInput, usize)> = gold
+        .iter()
+        .filter(|(_, _, is_match)| *is_match)
+        .filter_map(|(input, candidate, _)| {
+            let expected = *positions.get(candidate.id.as_str())?;
+            Some((InputIdentity::from(input).normalized(), expected))
+        })
+        .collect();
+
+    evaluate_blocking(config, records, &queries)
+}
+
+impl BlockingReport {
+    /// Plain-text table, one line per key then the union.
+    pub fn print(&self) {
+        println!("📊 Blocking report: {} queries over {} records", self.queries, self.records);
+        println!("  {:<28} {:>8} {:>12} {:>8}", "key", "recall", "mean cands", "max");
+        for stats in self.per_key.iter().chain(std::iter::once(&self.union)) {
+            println!(
+                "  {:<28} {:>7.2}% {:>12.1} {:>8}",
+                stats.key,
+                stats.recall * 100.0,
+                stats.mean_candidates,
+                stats.max_candidates
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::loader::RegistryRow;
+
+    fn record(id: &str, first_name: &str, last_name: &str, father_name: &str) -> IdentityNode {
+        RegistryRow {
+            registry_id:      Some(id.to_string()),
+            first_name:       first_name.to_string(),
+            last_name:        last_name.to_string(),
+            father_name:      father_name.to_string(),
+            grandfather_name: "صالح".to_string(),
+            mother_last_name: String::new(),
+            mother_name:      String::new(),
+            dob:              Some((15, 6, 1985)),
+            sex:              "1".to_string(),
+            place_of_birth:   String::new(),
+        }
+        .into_identity()
+    }
+
+    fn query(first_name: &str, last_name: &str, father_name: &str) -> NormalizedInput {
+        InputIdentity {
+            id:               None,
+            first_name:       first_name.to_string(),
+            last_name:        last_name.to_string(),
+            father_name:      father_name.to_string(),
+            grandfather_name: "صالح".to_string(),
+            mother_last_name: String::new(),
+            mother_name:      String::new(),
+            dob:              None,
+            sex:              1,
+            place_of_birth:   String::new(),
+        }
+        .normalized()
+    }
+
+    fn registry() -> Vec<IdentityNode> {
+        vec![
+            record("1", "أحمد", "الطرابلسي", "محمد"),
+            record("2", "سلمى", "الحسني", "علي"),
+            record("3", "محمد", "الطرابلسى", "الهادي"),
+            record("4", "يوسف", "بن عمر", "خليفة"),
+        ]
+    }
+
+    fn config(keys: &[BlockingKey]) -> BlockingConfig {
+        BlockingConfig { keys: keys.to_vec(), ..BlockingConfig::default() }
+    }
+
+    #[test]
+    fn last_name_soundex_finds_spelling_variants_only() {
+        let records = registry();
+        let refs: Vec<&IdentityNode> = records.iter().collect();
+        let index = BlockingIndex::build(&refs, &config(&[BlockingKey::LastNameSoundex]));
+        let input = query("احمد", "طرابلسي", "محمد");
+        assert_eq!(index.candidates(&BlockingFields::from(&input)), [0, 2]);
+    }
+
+    #[test]
+    fn skeleton_key_reaches_arabic_records_from_latin_input() {
+        let records = registry();
+        let refs: Vec<&IdentityNode> = records.iter().collect();
+        let input = query("Ahmed", "Trabelsi", "Mohamed");
+        let fields = BlockingFields::from(&input);
+
+        let soundex = BlockingIndex::build(&refs, &config(&[BlockingKey::LastNameSoundex]));
+        assert!(soundex.candidates(&fields).is_empty());
+        let skeleton = BlockingIndex::build(&refs, &config(&[BlockingKey::LastNameSkeleton]));
+        assert_eq!(skeleton.candidates(&fields), [0, 2]);
+    }
+
+    #[test]
+    fn candidates_are_the_union_of_every_key() {
+        let records = registry();
+        let refs: Vec<&IdentityNode> = records.iter().collect();
+        let index = BlockingIndex::build(&refs, &BlockingConfig::default());
+        // Another last name, but the same father and grandfather as record 2
+        let input = query("سلمى", "الطرابلسي", "علي");
+        let fields = BlockingFields::from(&input);
+
+        let mut expected: Vec<usize> = index.candidates_by_key(&fields).into_iter().flat_map(|(_, found)| found).collect();
+        expected.sort_unstable();
+        expected.dedup();
+        assert_eq!(index.candidates(&fields), expected);
+        assert!(index.candidates_for_key(BlockingKey::FatherGrandfatherPhonetic, &fields).contains(&1));
+        assert!(index.candidates(&fields).contains(&0));
+    }
+
+    #[test]
+    fn sorted_neighbourhood_takes_window_records_on_each_side() {
+        let records = registry();
+        let refs: Vec<&IdentityNode> = records.iter().collect();
+        let index = BlockingIndex::build(
+            &refs,
+            &BlockingConfig { window: 1, ..config(&[BlockingKey::SortedNeighbourhood]) },
+        );
+        // "سعيد" sorts between "حسني" and "طرابلسي": one record on each side
+        let input = query("سلمى", "سعيد", "علي");
+        assert_eq!(index.candidates(&BlockingFields::from(&input)).len(), 2);
+    }
+
+    #[test]
+    fn reports_recall_per_key_and_for_the_union() {
+        let records = registry();
+        let queries = vec![
+            (query("احمد", "طرابلسي", "محمد"), 0),
+            (query("Ahmed", "Trabelsi", "Mohamed"), 0),
+        ];
+        let keys = [BlockingKey::LastNameSoundex, BlockingKey::LastNameSkeleton];
+        let report = evaluate_blocking(&config(&keys), &records, &queries);
+
+        assert_eq!(report.queries, 2);
+        assert_eq!(report.per_key[0].key, "last_name_soundex");
+        assert_eq!(report.per_key[0].recall, 0.5);
+        assert_eq!(report.per_key[1].recall, 1.0);
+        assert_eq!(report.union.recall, 1.0);
+        assert_eq!(report.union.max_candidates, 2);
+    }
+
+    #[test]
+    fn rejects_configurations_that_find_nothing() {
+        assert!(BlockingConfig::default().validate().is_ok());
+        assert!(config(&[]).validate().is_err());
+        assert!(BlockingConfig { qgram_min_share: 1.5, ..BlockingConfig::default() }.validate().is_err());
+        assert!(BlockingConfig { window: 0, ..BlockingConfig::default() }.validate().is_err());
+    }
+}
@@ -0,0 +1,153 @@
+// src/utils/classifier.rs
+
+//! Logistic-regression match classifier trained on the gold set.
+//!
+//! Where a scoring profile adds hand-picked weights, the classifier learns
+//! how much each of the `features::pair_features` values says about a pair
+//! and returns a calibrated match probability.
+
+use std::fs;
+use std::io;
+use chrono::{DateTime, Utc};
+use linfa::prelude::*;
+use linfa_logistic::{FittedLogisticRegression, LogisticRegression};
+use ndarray::{Array1, Array2};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use crate::utils::evaluate::gold_pairs;
+use crate::utils::features::{pair_features, FEATURE_NAMES};
+use crate::utils::gold_set::GoldSetIdentity;
+use crate::utils::linked_list::IdentityNode;
+use crate::utils::pipeline::NormalizedInput;
+use crate::utils::tuning::assign_folds;
+
+/// L-BFGS iterations when training.
+const MAX_ITERATIONS: u64 = 200;
+
+/// A trained classifier with the feature layout it was trained on.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MatchModel {
+    /// `FEATURE_NAMES` at training time
+    pub features: Vec<String>,
+    pub trained_pairs: usize,
+    pub trained_at: DateTime<Utc>,
+    model: FittedLogisticRegression<f64, bool>,
+}
+
+/// Feature vectors and labels of every labelled pair of a gold set (as
+/// returned by `load_gold_set_for_records`).
+pub fn gold_features(
+    records: &[IdentityNode],
+    gold: &[(GoldSetIdentity, GoldSetIdentity, bool)],
+) -> Vec<(Vec<f64>, bool)> {
+    gold_pairs(records, gold)
+        .par_iter()
+        .map(|(input, candidate, is_match)| (pair_features(input, candidate), *is_match))
+        .collect()
+}
+
+/// Held-out match probabilities (in percent) of `samples` under k-fold
+/// cross-validation, with the fold assignment of `tuning::tune_profile`:
+/// each pair is scored by a model trained on the other folds.
+pub fn cross_validate(samples: &[(Vec<f64>, bool)], folds: usize, seed: u64) -> Result<Vec<(f64, bool)>, String> {
+    let (folds, fold_of) = assign_folds(samples.len(), folds, seed);
+    if folds == 0 {
+        return Err(format!("Cross-validation needs at least 2 folds and as many pairs, got {} pairs", samples.len()));
+    }
+    let mut held_out = Vec::with_capacity(samples.len());
+    for fold in 0..folds {
+        let train: Vec<(Vec<f64>, bool)> = samples
+            .iter()
+            .zip(&fold_of)
+            .filter(|&(_, &f)| f != fold)
+            .map(|(sample, _)| sample.clone())
+            .collect();
+        let model = MatchModel::train(&train).map_err(|e| format!("Fold {}: {}", fold + 1, e))?;
+        held_out.extend(
+            samples
+                .iter()
+                .zip(&fold_of)
+                .filter(|&(_, &f)| f == fold)
+                .map(|((features, is_match), _)| (model.probability(features) * 100.0, *is_match)),
+        );
+    }
+    Ok(held_out)
+}
+
+impl MatchModel {
+    /// Fit an L2-regularized logistic regression; the samples need both
+    /// matches and non-matches.
+    pub fn train(samples: &[(Vec<f64>, bool)]) -> Result<Self, String> {
+        if !samples.iter().any(|(_, m)| *m) || !samples.iter().any(|(_, m)| !*m) {
+            return Err("Training needs both matching and non-matching pairs".to_string());
+        }
+        // linfa-logistic 0.6 assigns the ±1 targets by watching the label
+        // change from row to row, which is only right when the rows come
+        // grouped by label
+        let mut ordered: Vec<&(Vec<f64>, bool)> = samples.iter().collect();
+        ordered.sort_by_key(|(_, m)| *m);
+
+        let width = FEATURE_NAMES.len();
+        let rows: Vec<f64> = ordered.iter().flat_map(|(features, _)| features.iter().copied()).collect();
+        let x = Array2::from_shape_vec((ordered.len(), width), rows).map_err(|e| e.to_string())?;
+        let y: Array1<bool> = ordered.iter().map(|(_, m)| *m).collect();
+
+        let model = LogisticRegression::default()
+            .max_iterations(MAX_ITERATIONS)
+            .fit(&Dataset::new(x, y))
+            .map_err(|e| format!("Training failed: {}", e))?;
+
+        Ok(MatchModel {
+            features: FEATURE_NAMES.iter().map(|f| f.to_string()).collect(),
+            trained_pairs: samples.len(),
+            trained_at: Utc::now(),
+            model,
+        })
+    }
+
+    /// Match probability of a feature vector laid out as `FEATURE_NAMES`.
+    pub fn probability(&self, features: &[f64]) -> f64 {
+        let x = Array2::from_shape_vec((1, features.len()), features.to_vec()).expect("one row of features");
+        self.model.predict_probabilities(&x)[0]
+    }
+
+    /// Match probability of a pair.
+    pub fn match_probability(&self, input: &NormalizedInput, candidate: &IdentityNode) -> f64 {
+        self.probability(&pair_features(input, candidate))
+    }
+
+    /// Learnt weight of each feature, then the intercept.
+    pub fn coefficients(&self) -> Vec<(&str, f64)> {
+        self.features
+            .iter()
+            .map(String::as_str)
+            .zip(self.model.params().iter().copied())
+            .chain(std::iter::once(("intercept", self.model.intercept())))
+            .collect()
+    }
+
+    /// Write the model as JSON.
+    pub fn save(&self, file_path: &str) -> io::Result<()> {
+        fs::write(file_path, serde_json::to_string_pretty(self)?)
+    }
+
+    /// Read a model written by `save`; it must use the current features.
+    pub fn load(file_path: &str) -> io::Result<Self> {
+        let model: MatchModel = serde_json::from_str(&fs::read_to_string(file_path)?)?;
+        if model.features != FEATURE_NAMES {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "model was trained on different features; retrain it",
+            ));
+        }
+        Ok(model)
+    }
+
+    /// Loads the model from `MATCH_MODEL` if set.
+    pub fn from_env() -> io::Result<Option<Self>> {
+        match std::env::var("MATCH_MODEL") {
+            Ok(path) if !path.is_empty() => Self::load(&path).map(Some),
+            _ => Ok(None),
+        }
+    }
+}
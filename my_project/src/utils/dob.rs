@@ -0,0 +1,387 @@
+// src/utils/dob.rs
+
+use chrono::Datelike;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// Earliest birth year a query may name.
+pub const MIN_BIRTH_YEAR: u32 = 1850;
+/// Largest age estimate a query may give.
+pub const MAX_AGE: u32 = 150;
+/// Largest tolerance of an age estimate, in years.
+pub const MAX_AGE_TOLERANCE: u32 = 20;
+
+/// Why two birth dates scored the way they did.
+/// A day or month of 0 means "unknown", as in registry entries like 00/00/1962.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DobReason {
+    Exact,
+    /// Either date is absent or has no year
+    Missing,
+    /// Years too far apart
+    Mismatch,
+    YearOffByOne,
+    /// Two adjacent digits of the year swapped (1985 vs 1958 counts: 8↔5)
+    YearDigitsTransposed,
+    DayMonthSwapped,
+    DayDiffers,
+    MonthDiffers,
+    DayAndMonthDiffer,
+    /// Day unknown on one side, compared on month and year
+    DayUnknown,
+    /// Month unknown on one side, compared on year only
+    YearOnly,
+    /// Candidate year inside the requested year range or age estimate
+    YearInRange,
+}
+
+impl DobReason {
+    pub fn code(&self) -> &'static str {
+        match self {
+            DobReason::Exact                => "exact",
+            DobReason::Missing              => "missing",
+            DobReason::Mismatch             => "mismatch",
+            DobReason::YearOffByOne         => "year_off_by_one",
+            DobReason::YearDigitsTransposed => "year_digits_transposed",
+            DobReason::DayMonthSwapped      => "day_month_swapped",
+            DobReason::DayDiffers           => "day_differs",
+            DobReason::MonthDiffers         => "month_differs",
+            DobReason::DayAndMonthDiffer    => "day_and_month_differ",
+            DobReason::DayUnknown           => "day_unknown",
+            DobReason::YearOnly             => "year_only",
+            DobReason::YearInRange          => "year_in_range",
+        }
+    }
+}
+
+/// Graded comparison of two birth dates.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DobComparison {
+    /// Similarity in [0, 1]
+    pub score: f64,
+    /// Every deviation found, year first; `[Exact]` when there is none
+    pub reasons: Vec<DobReason>,
+}
+
+impl DobComparison {
+    fn new(score: f64, reasons: Vec<DobReason>) -> Self {
+        let reasons = if reasons.is_empty() { vec![DobReason::Exact] } else { reasons };
+        DobComparison { score, reasons }
+    }
+
+    /// Reason codes joined with `+`, e.g. `day_month_swapped+year_off_by_one`.
+    pub fn reason_code(&self) -> String {
+        self.reasons.iter().map(DobReason::code).collect::<Vec<_>>().join("+")
+    }
+}
+
+/// True when `b` is `a` with two adjacent decimal digits swapped.
+fn digits_transposed(a: u32, b: u32) -> bool {
+    let (da, db) = (a.to_string().into_bytes(), b.to_string().into_bytes());
+    if da.len() != db.len() || da == db {
+        return false;
+    }
+    let diffs: Vec<usize> = (0..da.len()).filter(|&i| da[i] != db[i]).collect();
+    diffs.len() == 2 && diffs[1] == diffs[0] + 1 && da[diffs[0]] == db[diffs[1]] && da[diffs[1]] == db[diffs[0]]
+}
+
+/// 📅 Compare two `(day, month, year)` birth dates, tolerating the usual
+/// registry errors: day/month swaps, off-by-one years, transposed year digits
+/// and unknown (0) day or month.
+pub fn compare_dob(dob1: Option<(u32, u32, u32)>, dob2: Option<(u32, u32, u32)>) -> DobComparison {
+    let ((d1, m1, y1), (d2, m2, y2)) = match (dob1, dob2) {
+        (Some(a), Some(b)) if a.2 != 0 && b.2 != 0 => (a, b),
+        _ => return DobComparison::new(0.0, vec![DobReason::Missing]),
+    };
+
+    let mut reasons = Vec::new();
+
+    // 1) Year
+    let year_factor = if y1 == y2 {
+        1.0
+    } else if y1.abs_diff(y2) == 1 {
+        reasons.push(DobReason::YearOffByOne);
+        0.85
+    } else if digits_transposed(y1, y2) {
+        reasons.push(DobReason::YearDigitsTransposed);
+        0.75
+    } else {
+        return DobComparison::new(0.0, vec![DobReason::Mismatch]);
+    };
+
+    // 2) Day and month, as far as both sides know them
+    let day_month_factor = if m1 == 0 || m2 == 0 {
+        reasons.push(DobReason::YearOnly);
+        0.8
+    } else if d1 == 0 || d2 == 0 {
+        if m1 == m2 {
+            reasons.push(DobReason::DayUnknown);
+            0.9
+        } else {
+            reasons.push(DobReason::MonthDiffers);
+            0.6
+        }
+    } else if d1 == d2 && m1 == m2 {
+        1.0
+    } else if d1 == m2 && m1 == d2 {
+        reasons.push(DobReason::DayMonthSwapped);
+        0.9
+    } else if m1 == m2 {
+        reasons.push(DobReason::DayDiffers);
+        if digits_transposed(d1, d2) { 0.85 } else { 0.75 }
+    } else if d1 == d2 {
+        reasons.push(DobReason::MonthDiffers);
+        0.7
+    } else {
+        reasons.push(DobReason::DayAndMonthDiffer);
+        0.5
+    };
+
+    DobComparison::new(year_factor * day_month_factor, reasons)
+}
+
+/// Parse `dd/mm/yyyy` (also `-` or `.` separated); `00` stands for an unknown day or month.
+pub fn parse_dob(text: &str) -> Option<(u32, u32, u32)> {
+    let parts: Vec<&str> = text.trim().split(['/', '-', '.']).collect();
+    if parts.len() != 3 {
+        return None;
+    }
+    let day = parts[0].trim().parse::<u32>().ok()?;
+    let month = parts[1].trim().parse::<u32>().ok()?;
+    let year = parts[2].trim().parse::<u32>().ok()?;
+    if day > 31 || month > 12 || year == 0 {
+        return None;
+    }
+    Some((day, month, year))
+}
+
+/// Birth date of a query identity, as precise as the caller knows it.
+///
+/// Accepted JSON forms:
+/// - `[day, month, year]` (day/month may be 0 when unknown)
+/// - `{"day": 15, "month": 6, "year": 1985}`
+/// - `1985` or `{"year": 1985}`
+/// - `{"from": 1980, "to": 1985}`
+/// - `{"age": 40, "reference_date": [1, 1, 2024], "tolerance": 2}` (reference defaults to today)
+///
+/// Deserializing rejects a date that `validate` does not accept.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(remote = "Self", untagged)]
+pub enum BirthDate {
+    Tuple(u32, u32, u32),
+    Exact { day: u32, month: u32, year: u32 },
+    YearRange { from: u32, to: u32 },
+    Age {
+        age: u32,
+        #[serde(default)]
+        reference_date: Option<(u32, u32, u32)>,
+        #[serde(default)]
+        tolerance: u32,
+    },
+    YearOnly(u32),
+    Year { year: u32 },
+}
+
+impl<'de> Deserialize<'de> for BirthDate {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let date = BirthDate::deserialize(deserializer)?;
+        date.validate().map_err(serde::de::Error::custom)?;
+        Ok(date)
+    }
+}
+
+impl Serialize for BirthDate {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        BirthDate::serialize(self, serializer)
+    }
+}
+
+impl BirthDate {
+    /// Years between `MIN_BIRTH_YEAR` and the current year, days and months
+    /// in range (0 when unknown), ages up to `MAX_AGE` and tolerances up to
+    /// `MAX_AGE_TOLERANCE`, so that a query cannot span arbitrarily many
+    /// generations.
+    pub fn validate(&self) -> Result<(), String> {
+        let current_year = chrono::Utc::now().year() as u32;
+        let year = |year: u32| {
+            if (MIN_BIRTH_YEAR..=current_year).contains(&year) {
+                Ok(())
+            } else {
+                Err(format!("birth year {} is not between {} and {}", year, MIN_BIRTH_YEAR, current_year))
+            }
+        };
+        let day_month = |day: u32, month: u32| {
+            if day <= 31 && month <= 12 {
+                Ok(())
+            } else {
+                Err(format!("{}/{} is not a day and month", day, month))
+            }
+        };
+        match *self {
+            BirthDate::Tuple(d, m, y) | BirthDate::Exact { day: d, month: m, year: y } => {
+                day_month(d, m)?;
+                year(y)
+            }
+            BirthDate::YearOnly(y) | BirthDate::Year { year: y } => year(y),
+            BirthDate::YearRange { from, to } => {
+                year(from)?;
+                year(to)
+            }
+            BirthDate::Age { age, reference_date, tolerance } => {
+                if age > MAX_AGE {
+                    return Err(format!("age {} is over {}", age, MAX_AGE));
+                }
+                if tolerance > MAX_AGE_TOLERANCE {
+                    return Err(format!("age tolerance {} is over {}", tolerance, MAX_AGE_TOLERANCE));
+                }
+                match reference_date {
+                    Some((d, m, y)) => {
+                        day_month(d, m)?;
+                        year(y)
+                    }
+                    None => Ok(()),
+                }
+            }
+        }
+    }
+
+    /// The date as a `(day, month, year)` tuple when it names a single year;
+    /// unknown parts are 0. Ranges and age estimates return `None`.
+    pub fn as_tuple(&self) -> Option<(u32, u32, u32)> {
+        match *self {
+            BirthDate::Tuple(d, m, y) | BirthDate::Exact { day: d, month: m, year: y } => Some((d, m, y)),
+            BirthDate::YearOnly(y) | BirthDate::Year { year: y } => Some((0, 0, y)),
+            BirthDate::YearRange { .. } | BirthDate::Age { .. } => None,
+        }
+    }
+
+    /// Parse a text date: `dd/mm/yyyy`, `yyyy`, a range `1980-1985` or an age
+    /// estimate `~40`; `None` as well when it does not `validate`.
+    pub fn parse(text: &str) -> Option<BirthDate> {
+        Self::parse_unchecked(text).filter(|date| date.validate().is_ok())
+    }
+
+    fn parse_unchecked(text: &str) -> Option<BirthDate> {
+        let text = text.trim();
+        if let Some((d, m, y)) = parse_dob(text) {
+            return Some(BirthDate::Tuple(d, m, y));
+        }
+        if let Some(age) = text.strip_prefix('~') {
+            let age = age.trim().parse().ok()?;
+            return Some(BirthDate::Age { age, reference_date: None, tolerance: 1 });
+        }
+        if let Some((from, to)) = text.split_once('-') {
+            let (from, to) = (from.trim().parse().ok()?, to.trim().parse().ok()?);
+            return Some(BirthDate::YearRange { from, to });
+        }
+        text.parse().ok().filter(|&y| y != 0).map(BirthDate::YearOnly)
+    }
+
+    /// Earliest and latest possible birth year.
+    pub fn year_range(&self) -> (u32, u32) {
+        match *self {
+            BirthDate::YearRange { from, to } => (from.min(to), from.max(to)),
+            BirthDate::Age { age, reference_date, tolerance } => {
+                let ref_year = match reference_date {
+                    Some((_, _, y)) => y,
+                    None => chrono::Utc::now().year() as u32,
+                };
+                // Born in `ref_year - age`, or a year earlier if the birthday
+                // had not yet come round at the reference date
+                let latest = ref_year.saturating_sub(age);
+                let earliest = latest.saturating_sub(1);
+                (earliest.saturating_sub(tolerance), latest.saturating_add(tolerance))
+            }
+            _ => {
+                let (_, _, y) = self.as_tuple().unwrap_or((0, 0, 0));
+                (y, y)
+            }
+        }
+    }
+
+    /// Graded comparison against a registry date.
+    pub fn compare(&self, candidate: Option<(u32, u32, u32)>) -> DobComparison {
+        if let Some(tuple) = self.as_tuple() {
+            return compare_dob(Some(tuple), candidate);
+        }
+        let year = match candidate {
+            Some((_, _, y)) if y != 0 => y,
+            _ => return DobComparison::new(0.0, vec![DobReason::Missing]),
+        };
+        let (from, to) = self.year_range();
+        if (from..=to).contains(&year) {
+            DobComparison::new(0.8, vec![DobReason::YearInRange])
+        } else if year.saturating_add(1) == from || year == to.saturating_add(1) {
+            DobComparison::new(0.8 * 0.85, vec![DobReason::YearInRange, DobReason::YearOffByOne])
+        } else {
+            DobComparison::new(0.0, vec![DobReason::Mismatch])
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reasons(a: (u32, u32, u32), b: (u32, u32, u32)) -> String {
+        compare_dob(Some(a), Some(b)).reason_code()
+    }
+
+    #[test]
+    fn exact_date_scores_one() {
+        let comparison = compare_dob(Some((15, 6, 1985)), Some((15, 6, 1985)));
+        assert_eq!(comparison.score, 1.0);
+        assert_eq!(comparison.reasons, [DobReason::Exact]);
+    }
+
+    #[test]
+    fn names_the_usual_registry_errors() {
+        assert_eq!(reasons((15, 6, 1985), (6, 15, 1985)), "day_month_swapped");
+        assert_eq!(reasons((15, 6, 1985), (15, 6, 1986)), "year_off_by_one");
+        assert_eq!(reasons((15, 6, 1985), (15, 6, 1958)), "year_digits_transposed");
+        assert_eq!(reasons((15, 6, 1985), (16, 6, 1985)), "day_differs");
+        assert_eq!(reasons((15, 6, 1985), (15, 7, 1985)), "month_differs");
+        assert_eq!(reasons((15, 6, 1985), (3, 9, 1985)), "day_and_month_differ");
+        assert_eq!(reasons((0, 6, 1985), (15, 6, 1985)), "day_unknown");
+        assert_eq!(reasons((0, 0, 1985), (15, 6, 1985)), "year_only");
+        assert_eq!(reasons((15, 6, 1985), (15, 6, 1990)), "mismatch");
+        assert_eq!(reasons((6, 15, 1985), (15, 6, 1986)), "year_off_by_one+day_month_swapped");
+    }
+
+    #[test]
+    fn grades_deviations_below_an_exact_match() {
+        let score = |a, b| compare_dob(Some(a), Some(b)).score;
+        assert!((score((15, 6, 1985), (6, 15, 1985)) - 0.9).abs() < 1e-9);
+        assert!((score((15, 6, 1985), (15, 6, 1986)) - 0.85).abs() < 1e-9);
+        assert!((score((12, 6, 1985), (21, 6, 1985)) - 0.85).abs() < 1e-9); // transposed day digits
+        assert_eq!(score((15, 6, 1985), (15, 6, 1990)), 0.0);
+    }
+
+    #[test]
+    fn missing_dates_score_zero() {
+        assert_eq!(compare_dob(None, Some((15, 6, 1985))).reason_code(), "missing");
+        assert_eq!(compare_dob(Some((15, 6, 0)), Some((15, 6, 1985))).score, 0.0);
+    }
+
+    #[test]
+    fn year_ranges_and_ages_match_on_the_year() {
+        let range = BirthDate::YearRange { from: 1980, to: 1985 };
+        assert_eq!(range.compare(Some((1, 1, 1983))).reason_code(), "year_in_range");
+        assert_eq!(range.compare(Some((1, 1, 1986))).reason_code(), "year_in_range+year_off_by_one");
+        assert_eq!(range.compare(Some((1, 1, 1990))).reason_code(), "mismatch");
+
+        let age = BirthDate::Age { age: 40, reference_date: Some((1, 1, 2024)), tolerance: 0 };
+        assert_eq!(age.year_range(), (1983, 1984));
+    }
+
+    #[test]
+    fn parses_text_dates() {
+        assert_eq!(parse_dob("15/06/1985"), Some((15, 6, 1985)));
+        assert_eq!(parse_dob("00-00-1962"), Some((0, 0, 1962)));
+        assert_eq!(parse_dob("32/01/1985"), None);
+        assert_eq!(BirthDate::parse("1980-1985"), Some(BirthDate::YearRange { from: 1980, to: 1985 }));
+        assert_eq!(BirthDate::parse("~40"), Some(BirthDate::Age { age: 40, reference_date: None, tolerance: 1 }));
+        assert_eq!(BirthDate::parse("1985"), Some(BirthDate::YearOnly(1985)));
+        assert_eq!(BirthDate::parse("1985-2000000000"), None);
+    }
+}
@@ -0,0 +1,240 @@
+// src/utils/evaluate.rs
+
+//! Matcher quality on a labelled gold set: every pair is scored with a
+//! scoring profile and the scores are compared with the labels, at the
+//! configured threshold (confusion matrix, precision, recall, F1) and across
+//! all thresholds (PR curve, ROC-AUC).
+
+use std::collections::HashMap;
+use rayon::prelude::*;
+use serde::Serialize;
+use crate::utils::gold_set::GoldSetIdentity;
+use crate::utils::linked_list::IdentityNode;
+use crate::utils::matching::calculate_full_score;
+use crate::utils::pipeline::{InputIdentity, NormalizedInput};
+use crate::utils::scoring_profile::ScoringProfile;
+
+/// Outcome counts at one threshold.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct ConfusionMatrix {
+    pub true_positives:  usize,
+    pub false_positives: usize,
+    pub true_negatives:  usize,
+    pub false_negatives: usize,
+}
+
+impl ConfusionMatrix {
+    /// Count `(score, is_match)` pairs, a pair being predicted a match when
+    /// its score is at least `threshold`.
+    pub fn at(scored: &[(f64, bool)], threshold: f64) -> Self {
+        let mut matrix = ConfusionMatrix::default();
+        for &(score, is_match) in scored {
+            match (score >= threshold, is_match) {
+                (true, true)   => matrix.true_positives += 1,
+                (true, false)  => matrix.false_positives += 1,
+                (false, false) => matrix.true_negatives += 1,
+                (false, true)  => matrix.false_negatives += 1,
+            }
+        }
+        matrix
+    }
+
+    /// 1 when nothing is predicted a match.
+    pub fn precision(&self) -> f64 {
+        let predicted = self.true_positives + self.false_positives;
+        if predicted == 0 { 1.0 } else { self.true_positives as f64 / predicted as f64 }
+    }
+
+    /// 0 when there is no true match.
+    pub fn recall(&self) -> f64 {
+        let actual = self.true_positives + self.false_negatives;
+        if actual == 0 { 0.0 } else { self.true_positives as f64 / actual as f64 }
+    }
+
+    pub fn f1(&self) -> f64 {
+        let (p, r) = (self.precision(), self.recall());
+        if p + r == 0.0 { 0.0 } else { 2.0 * p * r / (p + r) }
+    }
+
+    /// Share of non-matches predicted a match.
+    pub fn false_positive_rate(&self) -> f64 {
+        let negatives = self.false_positives + self.true_negatives;
+        if negatives == 0 { 0.0 } else { self.false_positives as f64 / negatives as f64 }
+    }
+}
+
+/// One point of the precision/recall curve.
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct ThresholdPoint {
+    /// Score threshold in percent
+    pub threshold: f64,
+    pub precision: f64,
+    pub recall: f64,
+    pub f1: f64,
+    pub false_positive_rate: f64,
+}
+
+/// Quality of a scoring profile on a gold set.
+#[derive(Debug, Clone, Serialize)]
+pub struct EvaluationReport {
+    pub profile: String,
+    pub pairs: usize,
+    pub matches: usize,
+    pub non_matches: usize,
+    /// Threshold (percent) of the confusion matrix and the figures below it
+    pub threshold: f64,
+    pub confusion: ConfusionMatrix,
+    pub precision: f64,
+    pub recall: f64,
+    pub f1: f64,
+    /// Probability that a random match scores above a random non-match
+    pub roc_auc: f64,
+    /// Threshold of the curve point with the best F1
+    pub best_f1_threshold: f64,
+    pub best_f1: f64,
+    /// Every whole-percent threshold from 0 to 100
+    pub curve: Vec<ThresholdPoint>,
+}
+
+/// Area under the ROC curve through the Mann–Whitney statistic; ties count
+/// half. 0.5 when either class is empty.
+pub fn roc_auc(scored: &[(f64, bool)]) -> f64 {
+    let mut sorted: Vec<(f64, bool)> = scored.to_vec();
+    sorted.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+    // Sum of the (average, 1-based) ranks of the matches
+    let mut rank_sum = 0.0;
+    let mut i = 0;
+    while i < sorted.len() {
+        let mut j = i;
+        while j < sorted.len() && sorted[j].0 == sorted[i].0 {
+            j += 1;
+        }
+        let average_rank = (i + 1 + j) as f64 / 2.0;
+        rank_sum += average_rank * sorted[i..j].iter().filter(|(_, m)| *m).count() as f64;
+        i = j;
+    }
+
+    let positives = sorted.iter().filter(|(_, m)| *m).count() as f64;
+    let negatives = sorted.len() as f64 - positives;
+    if positives == 0.0 || negatives == 0.0 {
+        return 0.5;
+    }
+    (rank_sum - positives * (positives + 1.0) / 2.0) / (positives * negatives)
+}
+
+/// Evaluate `(score in percent, is_match)` pairs at `threshold` and across
+/// every whole-percent threshold.
+pub fn evaluate_scores(profile: &str, scored: &[(f64, bool)], threshold: f64) -> EvaluationReport {
+    let confusion = ConfusionMatrix::at(scored, threshold);
+    let curve: Vec<ThresholdPoint> = (0..=100)
+        .map(|t| {
+            let matrix = ConfusionMatrix::at(scored, t as f64);
+            ThresholdPoint {
+                threshold: t as f64,
+                precision: matrix.precision(),
+                recall: matrix.recall(),
+                f1: matrix.f1(),
+                false_positive_rate: matrix.false_positive_rate(),
+            }
+        })
+        .collect();
+    let best = curve
+        .iter()
+        .max_by(|a, b| a.f1.total_cmp(&b.f1))
+        .copied()
+        .expect("curve is never empty");
+    let matches = scored.iter().filter(|(_, m)| *m).count();
+
+    EvaluationReport {
+        profile: profile.to_string(),
+        pairs: scored.len(),
+        matches,
+        non_matches: scored.len() - matches,
+        threshold,
+        confusion,
+        precision: confusion.precision(),
+        recall: confusion.recall(),
+        f1: confusion.f1(),
+        roc_auc: roc_auc(scored),
+        best_f1_threshold: best.threshold,
+        best_f1: best.f1,
+        curve,
+    }
+}
+
+/// Labelled pairs of a gold set (as returned by `load_gold_set_for_records`)
+/// ready to score: the normalized input and its candidate among `records`,
+/// so the candidate's variations count. Pairs whose candidate is not among
+/// `records` are skipped.
+pub fn gold_pairs<'a>(
+    records: &'a [IdentityNode],
+    gold: &[(GoldSetIdentity, GoldSetIdentity, bool)],
+) -> Vec<(NormalizedInput, &'a IdentityNode, bool)> {
+    let by_id: HashMap<&str, &IdentityNode> = records.iter().map(|node| (node.id.as_str(), node)).collect();
+    gold.iter()
+        .filter_map(|(input, candidate, is_match)| {
+            let candidate = *by_id.get(candidate.id.as_str())?;
+            Some((InputIdentity::from(input).normalized(), candidate, *is_match))
+        })
+        .collect()
+}
+
+/// Score every labelled pair of a gold set with `profile`:
+/// `(total score in percent, is_match)`.
+pub fn score_gold_set(
+    profile: &ScoringProfile,
+    records: &[IdentityNode],
+    gold: &[(GoldSetIdentity, GoldSetIdentity, bool)],
+) -> Vec<(f64, bool)> {
+    gold_pairs(records, gold)
+        .par_iter()
+        .map(|(input, candidate, is_match)| (calculate_full_score(profile, input, candidate) * 100.0, *is_match))
+        .collect()
+}
+
+/// `score_gold_set` then `evaluate_scores`.
+pub fn evaluate_gold_set(
+    profile: &ScoringProfile,
+    records: &[IdentityNode],
+    gold: &[(GoldSetIdentity, GoldSetIdentity, bool)],
+    threshold: f64,
+) -> EvaluationReport {
+    evaluate_scores(&profile.name, &score_gold_set(profile, records, gold), threshold)
+}
+
+impl EvaluationReport {
+    /// Plain-text summary, confusion matrix and the curve every 5%.
+    pub fn print(&self) {
+        println!(
+            "📊 Evaluation of profile '{}': {} pairs ({} matches, {} non-matches)",
+            self.profile, self.pairs, self.matches, self.non_matches
+        );
+        println!(
+            "  At {}%: precision {:.2}%  recall {:.2}%  F1 {:.4}  |  ROC-AUC {:.4}",
+            self.threshold,
+            self.precision * 100.0,
+            self.recall * 100.0,
+            self.f1,
+            self.roc_auc
+        );
+        println!("  Best F1 {:.4} at {}%", self.best_f1, self.best_f1_threshold);
+
+        let c = &self.confusion;
+        println!("  {:<18} {:>12} {:>12}", "", "predicted ✔", "predicted ✘");
+        println!("  {:<18} {:>12} {:>12}", "actual match", c.true_positives, c.false_negatives);
+        println!("  {:<18} {:>12} {:>12}", "actual non-match", c.false_positives, c.true_negatives);
+
+        println!("  {:>9} {:>10} {:>8} {:>8} {:>8}", "threshold", "precision", "recall", "F1", "FPR");
+        for point in self.curve.iter().filter(|p| (p.threshold as u32).is_multiple_of(5)) {
+            println!(
+                "  {:>8}% {:>9.2}% {:>7.2}% {:>8.4} {:>7.2}%",
+                point.threshold,
+                point.precision * 100.0,
+                point.recall * 100.0,
+                point.f1,
+                point.false_positive_rate * 100.0
+            );
+        }
+    }
+}
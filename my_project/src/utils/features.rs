@@ -0,0 +1,80 @@
+// src/utils/features.rs
+
+//! Numeric description of an (input, candidate) pair for the match
+//! classifier (`classifier.rs`): per-field similarities rather than the
+//! weighted total of a scoring profile.
+
+use crate::utils::dob::compare_dob;
+use crate::utils::linked_list::{IdentityNode, Variations};
+use crate::utils::matching::{best_match_against_variations, cross_script_similarity};
+use crate::utils::phonetic::PhoneticKey;
+use crate::utils::pipeline::NormalizedInput;
+use crate::utils::similarity::{Jaro, NormalizedLevenshtein, SoundexEquality};
+
+/// Name of each value of `pair_features`, in order.
+pub const FEATURE_NAMES: [&str; 23] = [
+    "first_name_jaro", "first_name_levenshtein", "first_name_soundex",
+    "last_name_jaro", "last_name_levenshtein", "last_name_soundex",
+    "father_name_jaro", "father_name_levenshtein", "father_name_soundex",
+    "grandfather_name_jaro", "grandfather_name_levenshtein", "grandfather_name_soundex",
+    "mother_last_name_jaro", "mother_last_name_levenshtein", "mother_last_name_soundex",
+    "mother_name_jaro", "mother_name_levenshtein", "mother_name_soundex",
+    "dob_graded", "dob_year_delta", "dob_missing",
+    "sex_equal",
+    "place_of_birth_jaro",
+];
+
+/// Birth years further apart than this count as the largest delta.
+const MAX_YEAR_DELTA: f64 = 10.0;
+
+/// Jaro, normalized Levenshtein and Soundex equality of one name field,
+/// each the best over the candidate's variations. An empty side gives 0s.
+fn name_features(
+    norm_input: &str,
+    input_key: &PhoneticKey,
+    norm_base: &str,
+    base_key: &PhoneticKey,
+    variations: &Variations,
+) -> [f64; 3] {
+    if norm_input.is_empty() || norm_base.is_empty() {
+        return [0.0; 3];
+    }
+    let best = |metric| best_match_against_variations(metric, norm_input, input_key, norm_base, base_key, variations).0;
+    [best(&Jaro), best(&NormalizedLevenshtein), best(&SoundexEquality)]
+}
+
+/// Feature vector of a pair, laid out as `FEATURE_NAMES`; every value is in [0, 1].
+pub fn pair_features(input: &NormalizedInput, candidate: &IdentityNode) -> Vec<f64> {
+    let mut features = Vec::with_capacity(FEATURE_NAMES.len());
+    let (ik, ck) = (&input.name_keys, &candidate.name_keys);
+    for (norm_input, input_key, norm_base, base_key, variations) in [
+        (&input.first_name,       &ik.first_name,       &candidate.first_name,       &ck.first_name,       &candidate.first_name_variations),
+        (&input.last_name,        &ik.last_name,        &candidate.last_name,        &ck.last_name,        &candidate.last_name_variations),
+        (&input.father_name,      &ik.father_name,      &candidate.father_name,      &ck.father_name,      &candidate.father_name_variations),
+        (&input.grandfather_name, &ik.grandfather_name, &candidate.grandfather_name, &ck.grandfather_name, &candidate.grandfather_name_variations),
+        (&input.mother_last_name, &ik.mother_last_name, &candidate.mother_last_name, &ck.mother_last_name, &candidate.mother_last_name_variations),
+        (&input.mother_name,      &ik.mother_name,      &candidate.mother_name,      &ck.mother_name,      &candidate.mother_name_variations),
+    ] {
+        features.extend(name_features(norm_input, input_key, norm_base, base_key, variations));
+    }
+
+    // Birth date: graded comparison, year distance and whether it is known at all
+    let comparison = match &input.dob {
+        Some(dob) => dob.compare(candidate.dob),
+        None => compare_dob(None, candidate.dob),
+    };
+    let input_years = input.dob.map(|d| d.year_range()).filter(|&(_, to)| to != 0);
+    let candidate_year = candidate.dob.map(|(_, _, y)| y).filter(|&y| y != 0);
+    let (year_delta, missing) = match (input_years, candidate_year) {
+        (Some((from, to)), Some(y)) => {
+            let delta = if y < from { from - y } else { y.saturating_sub(to) };
+            ((delta as f64).min(MAX_YEAR_DELTA) / MAX_YEAR_DELTA, 0.0)
+        }
+        _ => (1.0, 1.0),
+    };
+    features.extend([comparison.score, year_delta, missing]);
+
+    features.push((input.sex == candidate.sex) as u8 as f64);
+    features.push(cross_script_similarity(&Jaro, &input.place_of_birth, &candidate.place_of_birth));
+    features
+}
@@ -1,14 +1,19 @@
+    use std::collections::HashMap;
     use std::fs::File;
     use std::io::{self, Read};
     use std::path::Path;
     use csv::ReaderBuilder;
     use serde_json::Value;
     use serde::{Deserialize, Serialize};
-    use crate::utils::linked_list::IdentityNode;
+    use crate::utils::linked_list::{is_stale_hashed_id, IdentityArena, IdentityNode};
+    use crate::utils::normalization::NORMALIZER_VERSION;
+    use crate::utils::pipeline::InputIdentity;
 
     /// Identity structure for gold set records
     #[derive(Debug, Clone, Deserialize, Serialize)]
     pub struct GoldSetIdentity {
+        /// `IdentityNode::id` of the record it was read from
+        pub id: String,
         pub first_name: String,
         pub last_name: String,
         pub father_name: String,
@@ -26,6 +31,9 @@
         pub input_id: String,
         pub candidate_id: String,
         pub is_match: bool,
+        /// The input itself, for an input that is not a registry record
+        /// (e.g. a `/match` request in exported decisions)
+        pub input: Option<InputIdentity>,
     }
 
     /// Loads a gold set from a CSV file
@@ -34,6 +42,7 @@
     /// - input_id: ID of the input identity
     /// - candidate_id: ID of the candidate identity
     /// - label: 1 for match, 0 for non-match
+    /// - input (optional): the input identity as JSON, when it is not a registry record
     ///
     /// Returns a Vec of GoldSetRecord
     pub fn load_gold_set_from_csv(file_path: &str) -> io::Result<Vec<GoldSetRecord>> {
@@ -53,11 +62,16 @@
             let input_id = record[0].to_string();
             let candidate_id = record[1].to_string();
             let label = record[2].parse::<u8>().unwrap_or(0);
+            let input = match record.get(3).filter(|json| !json.is_empty()) {
+                Some(json) => Some(serde_json::from_str(json)?),
+                None => None,
+            };
 
             records.push(GoldSetRecord {
                 input_id,
                 candidate_id,
                 is_match: label == 1,
+                input,
             });
         }
 
@@ -70,6 +84,7 @@
     /// - input_id: ID of the input identity
     /// - candidate_id: ID of the candidate identity
     /// - label: 1 for match, 0 for non-match
+    /// - input (optional): the input identity, when it is not a registry record
     ///
     /// Returns a Vec of GoldSetRecord
     pub fn load_gold_set_from_json(file_path: &str) -> io::Result<Vec<GoldSetRecord>> {
@@ -87,10 +102,15 @@
                     item.get("candidate_id").and_then(Value::as_str),
                     item.get("label").and_then(Value::as_u64),
                 ) {
+                    let input = match item.get("input") {
+                        Some(input) if !input.is_null() => Some(serde_json::from_value(input.clone())?),
+                        _ => None,
+                    };
                     records.push(GoldSetRecord {
                         input_id: input_id.to_string(),
                         candidate_id: candidate_id.to_string(),
                         is_match: label == 1,
+                        input,
                     });
                 }
             }
@@ -99,41 +119,144 @@
         Ok(records)
     }
 
-    /// Finds an identity by ID in a dictionary
-    fn find_identity_by_id(dictionary: &Option<Box<IdentityNode>>, id: &str) -> Option<GoldSetIdentity> {
-        let mut current = dictionary.as_ref().map(|b| b.as_ref());
-
-        while let Some(node) = current {
-            // In a real system, you would have an ID field in IdentityNode
-            // For this example, we'll use a combination of fields as an ID
-            let node_id = format!(
-                "{}{}{}{}{}{}",
-                node.first_name,
-                node.last_name,
-                node.father_name,
-                node.grandfather_name,
-                node.mother_last_name,
-                node.mother_name
-            );
+    /// Gold set records as CSV, in the format `load_gold_set_from_csv` reads
+    pub fn gold_set_to_csv(records: &[GoldSetRecord]) -> String {
+        let mut writer = csv::Writer::from_writer(Vec::new());
+        writer.write_record(["input_id", "candidate_id", "label", "input"]).expect("in-memory CSV");
+        for record in records {
+            let label = if record.is_match { "1" } else { "0" };
+            let input = record
+                .input
+                .as_ref()
+                .map(|input| serde_json::to_string(input).expect("JSON of an identity"))
+                .unwrap_or_default();
+            writer
+                .write_record([record.input_id.as_str(), record.candidate_id.as_str(), label, input.as_str()])
+                .expect("in-memory CSV");
+        }
+        String::from_utf8(writer.into_inner().expect("in-memory CSV")).expect("CSV of UTF-8 fields")
+    }
 
-            if node_id == id {
-                return Some(GoldSetIdentity {
-                    first_name: node.first_name.clone(),
-                    last_name: node.last_name.clone(),
-                    father_name: node.father_name.clone(),
-                    grandfather_name: node.grandfather_name.clone(),
-                    mother_last_name: node.mother_last_name.clone(),
-                    mother_name: node.mother_name.clone(),
-                    dob: node.dob,
-                    sex: node.sex,
-                    place_of_birth: node.place_of_birth.clone(),
+    /// Gold set records as JSON, in the format `load_gold_set_from_json` reads
+    pub fn gold_set_to_json(records: &[GoldSetRecord]) -> String {
+        let array: Vec<Value> = records
+            .iter()
+            .map(|record| {
+                let mut item = serde_json::json!({
+                    "input_id": record.input_id,
+                    "candidate_id": record.candidate_id,
+                    "label": record.is_match as u8,
                 });
-            }
+                if let Some(input) = &record.input {
+                    item["input"] = serde_json::to_value(input).expect("JSON of an identity");
+                }
+                item
+            })
+            .collect();
+        serde_json::to_string_pretty(&array).expect("JSON of strings and numbers")
+    }
+
+    /// Writes gold set records, choosing the format by file extension
+    pub fn save_gold_set(file_path: &str, records: &[GoldSetRecord]) -> io::Result<()> {
+        let extension = Path::new(file_path).extension().and_then(|ext| ext.to_str()).unwrap_or("");
+        let contents = match extension.to_lowercase().as_str() {
+            "csv" => gold_set_to_csv(records),
+            "json" => gold_set_to_json(records),
+            _ => return Err(io::Error::new(io::ErrorKind::InvalidInput, "Unsupported file format")),
+        };
+        std::fs::write(file_path, contents)
+    }
 
-            current = node.next_identity.as_ref().map(|b| b.as_ref());
+    /// ID of a dictionary entry as used in gold set files: its registry key
+    /// or stable hash (`IdentityNode::id`)
+    pub fn identity_id(node: &IdentityNode) -> String {
+        node.id.clone()
+    }
+
+    fn to_gold_identity(node: &IdentityNode) -> GoldSetIdentity {
+        GoldSetIdentity {
+            id: node.id.clone(),
+            first_name: node.first_name.clone(),
+            last_name: node.last_name.clone(),
+            father_name: node.father_name.clone(),
+            grandfather_name: node.grandfather_name.clone(),
+            mother_last_name: node.mother_last_name.clone(),
+            mother_name: node.mother_name.clone(),
+            dob: node.dob,
+            sex: node.sex,
+            place_of_birth: node.place_of_birth.clone(),
         }
+    }
 
-        None
+    /// Gold identity of an input given with the pair: its normalized fields
+    /// under the pair's input ID.
+    fn input_gold_identity(id: &str, input: &InputIdentity) -> GoldSetIdentity {
+        let norm = input.normalized();
+        GoldSetIdentity {
+            id: id.to_string(),
+            first_name: norm.first_name,
+            last_name: norm.last_name,
+            father_name: norm.father_name,
+            grandfather_name: norm.grandfather_name,
+            mother_last_name: norm.mother_last_name,
+            mother_name: norm.mother_name,
+            dob: norm.dob.and_then(|d| d.as_tuple()),
+            sex: norm.sex,
+            place_of_birth: norm.place_of_birth,
+        }
+    }
+
+    /// Resolve the pairs of `records` with `lookup`; an input that is not a
+    /// registry record falls back to the identity stored with the pair.
+    /// Pairs left unresolved are counted and reported.
+    fn resolve_pairs<'a>(
+        file_path: &str,
+        records: Vec<GoldSetRecord>,
+        lookup: impl Fn(&str) -> Option<&'a IdentityNode>,
+    ) -> Vec<(GoldSetIdentity, GoldSetIdentity, bool)> {
+        let total = records.len();
+        let mut pairs = Vec::with_capacity(total);
+        let mut stale = 0;
+        for record in records {
+            let input = match (lookup(&record.input_id), &record.input) {
+                (Some(node), _) => Some(to_gold_identity(node)),
+                (None, Some(input)) => Some(input_gold_identity(&record.input_id, input)),
+                (None, None) => None,
+            };
+            match (input, lookup(&record.candidate_id)) {
+                (Some(input), Some(candidate)) => pairs.push((input, to_gold_identity(candidate), record.is_match)),
+                _ if is_stale_hashed_id(&record.input_id) || is_stale_hashed_id(&record.candidate_id) => stale += 1,
+                _ => {}
+            }
+        }
+        if pairs.len() < total {
+            println!(
+                "⚠️  {} of {} pairs in {} name an ID missing from the registry and are skipped",
+                total - pairs.len(),
+                total,
+                file_path
+            );
+        }
+        if stale > 0 {
+            println!(
+                "⚠️  {} of them use hashed IDs of another normalizer version (current n{}); re-export the gold set",
+                stale,
+                NORMALIZER_VERSION
+            );
+        }
+        pairs
+    }
+
+    /// Reads the gold set records, choosing the format by file extension
+    fn load_gold_set_records(file_path: &str) -> io::Result<Vec<GoldSetRecord>> {
+        let path = Path::new(file_path);
+        let extension = path.extension().and_then(|ext| ext.to_str()).unwrap_or("");
+
+        match extension.to_lowercase().as_str() {
+            "csv" => load_gold_set_from_csv(file_path),
+            "json" => load_gold_set_from_json(file_path),
+            _ => Err(io::Error::new(io::ErrorKind::InvalidInput, "Unsupported file format")),
+        }
     }
 
     /// Loads a gold set and returns a Vec of (GoldSetIdentity, GoldSetIdentity, bool) tuples
@@ -143,28 +266,17 @@
     /// - .json: JSON format
     ///
     /// Returns a Vec of (GoldSetIdentity, GoldSetIdentity, bool) tuples
-    pub fn load_gold_set(file_path: &str, dictionary: &Option<Box<IdentityNode>>) -> io::Result<Vec<(GoldSetIdentity, GoldSetIdentity, bool)>> {
-        let path = Path::new(file_path);
-        let extension = path.extension().and_then(|ext| ext.to_str()).unwrap_or("");
-
-        let records = match extension.to_lowercase().as_str() {
-            "csv" => load_gold_set_from_csv(file_path)?,
-            "json" => load_gold_set_from_json(file_path)?,
-            _ => return Err(io::Error::new(io::ErrorKind::InvalidInput, "Unsupported file format")),
-        };
-
-        let mut result = Vec::new();
-
-        for record in records {
-            if let (Some(input), Some(candidate)) = (
-                find_identity_by_id(dictionary, &record.input_id),
-                find_identity_by_id(dictionary, &record.candidate_id),
-            ) {
-                result.push((input, candidate, record.is_match));
-            }
-        }
+    pub fn load_gold_set(file_path: &str, dictionary: &IdentityArena) -> io::Result<Vec<(GoldSetIdentity, GoldSetIdentity, bool)>> {
+        let records = load_gold_set_records(file_path)?;
+        Ok(resolve_pairs(file_path, records, |id| dictionary.get(id)))
+    }
 
-        Ok(result)
+    /// Same as `load_gold_set`, resolving IDs against loaded registry records
+    /// (e.g. from `loader::load_identities_by_generations`) through a hash map
+    pub fn load_gold_set_for_records(file_path: &str, records: &[IdentityNode]) -> io::Result<Vec<(GoldSetIdentity, GoldSetIdentity, bool)>> {
+        let gold = load_gold_set_records(file_path)?;
+        let by_id: HashMap<&str, &IdentityNode> = records.iter().map(|node| (node.id.as_str(), node)).collect();
+        Ok(resolve_pairs(file_path, gold, |id| by_id.get(id).copied()))
     }
 
     /// Creates a sample CSV gold set file for testing
@@ -0,0 +1,339 @@
+// src/utils/identity_store.rs
+
+use std::collections::{BTreeSet, HashMap};
+use std::future::poll_fn;
+use std::io;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tokio_postgres::{AsyncMessage, NoTls};
+use crate::utils::blocking::{BlockingConfig, BlockingFields, BlockingIndex};
+use crate::utils::linked_list::IdentityNode;
+use crate::utils::loader::{generation_key, generations_for, load_identities_by_generations, REGISTRY_DB};
+use crate::utils::normalization::NORMALIZER_VERSION;
+use crate::utils::pipeline::{demographic_filter, NormalizedInput};
+use crate::utils::registry_snapshot::{read_snapshot, write_snapshot};
+use crate::utils::variants::VariantDictionary;
+
+/// Records of one (generation, sex) partition and their blocking index.
+#[derive(Debug, Default, Deserialize, Serialize)]
+struct Partition {
+    /// Positions in `StoreSnapshot::records`
+    members: Vec<usize>,
+    /// Blocking index over `members`
+    index: BlockingIndex,
+}
+
+/// Immutable, fully indexed copy of the registry.
+/// Readers keep an `Arc` to it while scoring; a refresh swaps in a new one.
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct StoreSnapshot {
+    records: Vec<IdentityNode>,
+    partitions: HashMap<(i32, u8), Partition>,
+    /// Sorted generation keys present in `partitions`
+    generations: BTreeSet<i32>,
+    #[serde(skip)]
+    pub loaded_at: Option<Instant>,
+}
+
+impl StoreSnapshot {
+    /// Index already normalized records (as produced by the loader).
+    pub fn build(records: Vec<IdentityNode>, blocking: &BlockingConfig) -> Self {
+        let mut members: HashMap<(i32, u8), Vec<usize>> = HashMap::new();
+        let mut generations = BTreeSet::new();
+
+        for (i, node) in records.iter().enumerate() {
+            let gen = generation_key(node.dob.map(|(_, _, y)| y as i32).unwrap_or(0));
+            generations.insert(gen);
+            members.entry((gen, node.sex)).or_default().push(i);
+        }
+
+        let partitions = members
+            .into_iter()
+            .map(|(key, members)| {
+                let refs: Vec<&IdentityNode> = members.iter().map(|&i| &records[i]).collect();
+                let index = BlockingIndex::build(&refs, blocking);
+                (key, Partition { members, index })
+            })
+            .collect();
+
+        StoreSnapshot { records, partitions, generations, loaded_at: Some(Instant::now()) }
+    }
+
+    pub fn len(&self) -> usize {
+        self.records.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+
+    pub fn records(&self) -> &[IdentityNode] {
+        &self.records
+    }
+
+    pub fn into_records(self) -> Vec<IdentityNode> {
+        self.records
+    }
+
+    /// Write the records and their index to a snapshot file (see
+    /// `registry_snapshot`), with the `NORMALIZER_VERSION` and the blocking
+    /// configuration they were normalized and indexed with. Build it from
+    /// records without dictionary variants: those are attached on loading.
+    pub fn save(&self, file_path: &str, blocking: &BlockingConfig) -> io::Result<()> {
+        write_snapshot(file_path, &(Utc::now(), NORMALIZER_VERSION, blocking, self))
+    }
+
+    /// Read a snapshot written by `save`. One normalized by another
+    /// `NORMALIZER_VERSION` or indexed with other blocking keys than
+    /// `blocking` is rejected like one of an older schema.
+    pub fn load_file(file_path: &str, blocking: &BlockingConfig) -> io::Result<Self> {
+        let (written_at, normalized_with, indexed_with, mut snapshot): (DateTime<Utc>, u32, BlockingConfig, StoreSnapshot) =
+            read_snapshot(file_path)?;
+        if normalized_with != NORMALIZER_VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "{} was normalized with normalizer version {}, expected {}; rebuild it",
+                    file_path, normalized_with, NORMALIZER_VERSION
+                ),
+            ));
+        }
+        if &indexed_with != blocking {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{} was indexed with another blocking configuration; rebuild it", file_path),
+            ));
+        }
+        println!("📦 Registry snapshot {}: {} records, written at {}", file_path, snapshot.len(), written_at);
+        snapshot.loaded_at = Some(Instant::now());
+        Ok(snapshot)
+    }
+
+    /// Candidates for `input`: only the partitions of the decades around its
+    /// birth date (all of them when undated) and of its sex, then the union of
+    /// the blocking keys, then the sex and birth-year filter.
+    pub fn candidates(&self, input: &NormalizedInput, year_tolerance: u32) -> Vec<&IdentityNode> {
+        let gens: Vec<i32> = match generations_for(input.dob.as_ref(), year_tolerance) {
+            Some(gens) => gens,
+            None => self.generations.iter().copied().collect(),
+        };
+        let fields = BlockingFields::from(input);
+
+        let mut indices: Vec<usize> = gens
+            .iter()
+            .filter_map(|gen| self.partitions.get(&(*gen, input.sex)))
+            .flat_map(|partition| {
+                partition
+                    .index
+                    .candidates(&fields)
+                    .into_iter()
+                    .map(|local| partition.members[local])
+            })
+            .collect();
+        indices.sort_unstable();
+
+        demographic_filter(input, indices.iter().map(|&i| &self.records[i]), year_tolerance)
+    }
+}
+
+/// Where a store reloads the registry from on `refresh`.
+enum RegistrySource {
+    /// The registry table in PostgreSQL
+    Database,
+    /// A snapshot file written by `StoreSnapshot::save`
+    SnapshotFile(String),
+}
+
+/// Long-lived in-memory registry shared by all `/match` requests.
+pub struct IdentityStore {
+    snapshot: RwLock<Arc<StoreSnapshot>>,
+    blocking: BlockingConfig,
+    /// Attached to the records of every snapshot
+    variants: RwLock<Arc<VariantDictionary>>,
+    source: RegistrySource,
+}
+
+impl IdentityStore {
+    /// A store holding `records`, with the dictionary variants attached.
+    pub fn from_records(mut records: Vec<IdentityNode>, blocking: BlockingConfig, variants: VariantDictionary) -> Self {
+        variants.attach_all(&mut records);
+        let snapshot = StoreSnapshot::build(records, &blocking);
+        IdentityStore {
+            snapshot: RwLock::new(Arc::new(snapshot)),
+            blocking,
+            variants: RwLock::new(Arc::new(variants)),
+            source: RegistrySource::Database,
+        }
+    }
+
+    /// A store serving the snapshot file `file_path` (see `StoreSnapshot::save`)
+    /// without PostgreSQL, with the dictionary variants attached; `refresh`
+    /// re-reads the file.
+    pub fn from_snapshot_file(file_path: &str, blocking: BlockingConfig, variants: VariantDictionary) -> io::Result<Self> {
+        let mut snapshot = StoreSnapshot::load_file(file_path, &blocking)?;
+        // Variations are not indexed, so the index read from the file still holds
+        variants.attach_all(&mut snapshot.records);
+        Ok(IdentityStore {
+            snapshot: RwLock::new(Arc::new(snapshot)),
+            blocking,
+            variants: RwLock::new(Arc::new(variants)),
+            source: RegistrySource::SnapshotFile(file_path.to_string()),
+        })
+    }
+
+    /// Load and index the whole registry table.
+    pub async fn load(blocking: BlockingConfig, variants: VariantDictionary) -> Result<Self, String> {
+        let records = load_identities_by_generations(None).await?;
+        Ok(Self::from_records(records, blocking, variants))
+    }
+
+    /// The current variant dictionary.
+    pub fn variants(&self) -> Arc<VariantDictionary> {
+        self.variants.read().expect("identity store poisoned").clone()
+    }
+
+    /// Edit the variant dictionary, save it to its file and re-attach the
+    /// variants to the records in memory. Nothing is saved when `edit` fails;
+    /// a dictionary without a file is an `Unsupported` error, as its edits
+    /// would be lost on restart.
+    pub fn update_variants<T, E>(
+        &self,
+        edit: impl FnOnce(&mut VariantDictionary) -> Result<T, E>,
+    ) -> io::Result<Result<T, E>> {
+        let mut variants = self.variants.write().expect("identity store poisoned");
+        if variants.path().is_none() {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "no NAME_VARIANTS file to save the name variants to",
+            ));
+        }
+        let mut updated = (**variants).clone();
+        let result = edit(&mut updated);
+        if result.is_ok() {
+            updated.save()?;
+            self.reattach_variants(&updated);
+            *variants = Arc::new(updated);
+        }
+        Ok(result)
+    }
+
+    /// Rebuild the snapshot from the records in memory with the dictionary
+    /// variants swapped for those of `variants`, without reloading the registry.
+    fn reattach_variants(&self, variants: &VariantDictionary) {
+        let started = Instant::now();
+        let mut records = self.snapshot().records().to_vec();
+        variants.attach_all(&mut records);
+        let snapshot = Arc::new(StoreSnapshot::build(records, &self.blocking));
+        *self.snapshot.write().expect("identity store poisoned") = snapshot;
+        println!("📚 Name variants re-attached to {} records in {:?}", self.snapshot().len(), started.elapsed());
+    }
+
+    /// The current snapshot; cheap, never blocks on a running refresh.
+    pub fn snapshot(&self) -> Arc<StoreSnapshot> {
+        self.snapshot.read().expect("identity store poisoned").clone()
+    }
+
+    /// Swap in a freshly loaded registry, with the dictionary variants
+    /// attached in place of any it carries.
+    pub fn replace(&self, mut records: Vec<IdentityNode>) {
+        // Hold the dictionary until the swap so a concurrent edit cannot be
+        // overwritten by records carrying the variants from before it
+        let variants = self.variants.read().expect("identity store poisoned");
+        variants.attach_all(&mut records);
+        // Index outside the snapshot lock so readers are never blocked
+        let snapshot = Arc::new(StoreSnapshot::build(records, &self.blocking));
+        *self.snapshot.write().expect("identity store poisoned") = snapshot;
+    }
+
+    /// Reload the whole table from PostgreSQL, or re-read the snapshot file
+    /// the store was started from. When that fails the error is logged and
+    /// the current snapshot keeps being served.
+    pub async fn refresh(&self) {
+        let started = Instant::now();
+        let loaded = match &self.source {
+            RegistrySource::Database => load_identities_by_generations(None).await,
+            RegistrySource::SnapshotFile(file_path) => {
+                let (file_path, blocking) = (file_path.clone(), self.blocking.clone());
+                match tokio::task::spawn_blocking(move || StoreSnapshot::load_file(&file_path, &blocking)).await {
+                    Ok(Ok(snapshot)) => Ok(snapshot.into_records()),
+                    Ok(Err(e)) => Err(e.to_string()),
+                    Err(e) => Err(e.to_string()),
+                }
+            }
+        };
+        let records = match loaded {
+            Ok(records) => records,
+            Err(e) => {
+                eprintln!("⚠️  Identity store refresh failed, keeping the current snapshot: {}", e);
+                return;
+            }
+        };
+        let count = records.len();
+        self.replace(records);
+        println!("🔄 Identity store refreshed: {} records in {:?}", count, started.elapsed());
+    }
+
+    /// Refresh every `every` in the background.
+    pub fn spawn_periodic_refresh(self: &Arc<Self>, every: Duration) {
+        let store = Arc::clone(self);
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(every);
+            ticker.tick().await; // the first tick fires immediately
+            loop {
+                ticker.tick().await;
+                store.refresh().await;
+            }
+        });
+    }
+
+    /// Refresh whenever PostgreSQL sends a NOTIFY on `channel`, e.g. from a trigger:
+    /// `CREATE TRIGGER … AFTER INSERT OR UPDATE OR DELETE ON tunisian_citizens
+    ///  FOR EACH STATEMENT EXECUTE FUNCTION notify_registry_changed();`
+    /// with `notify_registry_changed` running `PERFORM pg_notify('<channel>', '')`.
+    pub fn spawn_notify_refresh(self: &Arc<Self>, channel: String) {
+        let store = Arc::clone(self);
+        tokio::spawn(async move {
+            let (client, mut connection) = match tokio_postgres::connect(REGISTRY_DB, NoTls).await {
+                Ok(pair) => pair,
+                Err(e) => {
+                    eprintln!("Failed to connect for LISTEN {}: {}", channel, e);
+                    return;
+                }
+            };
+
+            // The connection has to be polled for the LISTEN below to complete,
+            // so forward its notifications through a channel.
+            let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+            tokio::spawn(async move {
+                while let Some(message) = poll_fn(|cx| connection.poll_message(cx)).await {
+                    match message {
+                        Ok(AsyncMessage::Notification(_)) => {
+                            if tx.send(()).is_err() {
+                                break;
+                            }
+                        }
+                        Ok(_) => {}
+                        Err(e) => {
+                            eprintln!("LISTEN connection error: {}", e);
+                            break;
+                        }
+                    }
+                }
+            });
+
+            if let Err(e) = client.batch_execute(&format!("LISTEN \"{}\"", channel.replace('"', ""))).await {
+                eprintln!("Failed to LISTEN {}: {}", channel, e);
+                return;
+            }
+            println!("👂 Listening for registry changes on '{}'", channel);
+
+            while rx.recv().await.is_some() {
+                // Collapse bursts of notifications into one reload
+                while rx.try_recv().is_ok() {}
+                store.refresh().await;
+            }
+        });
+    }
+}
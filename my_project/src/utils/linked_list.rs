@@ -1,11 +1,116 @@
-#[derive(Debug, Clone)]
-pub struct VariationNode {
-    pub variation: String,
-    pub next_variation: Option<Box<VariationNode>>,
+//! Registry records in memory. Records live contiguously in an
+//! `IdentityArena` indexed by ID, and the spellings of each name field in a
+//! small inline vector; both replace the linked lists this module is named
+//! after.
+
+use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use smallvec::SmallVec;
+use sha2::{Digest, Sha256};
+use crate::utils::matching::CandidateDetails;
+use crate::utils::normalization::{normalize_name, NORMALIZER_VERSION};
+use crate::utils::phonetic::{NameKeys, PhoneticKey};
+
+/// One spelling of a name field: as written in the registry (or the variant
+/// dictionary), normalized, and its phonetic codes, so scoring compares it
+/// without normalizing it again.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Variation {
+    pub raw:  String,
+    pub norm: String,
+    pub key:  PhoneticKey,
+    /// Attached from the variant dictionary rather than read from the
+    /// registry, and dropped when the dictionary changes
+    pub from_dictionary: bool,
+}
+
+impl Variation {
+    pub fn new(raw: &str) -> Self {
+        let norm = normalize_name(raw);
+        let key = PhoneticKey::of(&norm);
+        Variation { raw: raw.to_string(), norm, key, from_dictionary: false }
+    }
+}
+
+/// Spellings of one name field, sorted by raw text and without duplicates.
+/// Most records have one or two, kept inline without a heap allocation.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Variations(SmallVec<[Variation; 2]>);
+
+impl Variations {
+    pub fn single(variation: String) -> Self {
+        Variations(SmallVec::from_elem(Variation::new(&variation), 1))
+    }
+
+    fn position(&self, variation: &str) -> Result<usize, usize> {
+        self.0.binary_search_by(|v| v.raw.as_str().cmp(variation))
+    }
+
+    /// Insert a registry spelling in order; `false` when already present.
+    /// A spelling the dictionary attached becomes a registry one.
+    pub fn insert(&mut self, variation: &str) -> bool {
+        match self.position(variation) {
+            Ok(position) => {
+                self.0[position].from_dictionary = false;
+                false
+            }
+            Err(position) => {
+                self.0.insert(position, Variation::new(variation));
+                true
+            }
+        }
+    }
+
+    /// Insert a spelling from the variant dictionary; `false` when already
+    /// present, registry spellings staying registry ones.
+    pub fn insert_from_dictionary(&mut self, variation: &str) -> bool {
+        match self.position(variation) {
+            Ok(_) => false,
+            Err(position) => {
+                self.0.insert(position, Variation { from_dictionary: true, ..Variation::new(variation) });
+                true
+            }
+        }
+    }
+
+    /// Drop the spellings attached from the variant dictionary.
+    pub fn clear_dictionary(&mut self) {
+        self.0.retain(|v| !v.from_dictionary);
+    }
+
+    /// Add every spelling of `other`.
+    pub fn merge(&mut self, other: &Variations) {
+        for variation in &other.0 {
+            match self.position(&variation.raw) {
+                Ok(position) => self.0[position].from_dictionary &= variation.from_dictionary,
+                Err(position) => self.0.insert(position, variation.clone()),
+            }
+        }
+    }
+
+    /// The raw spellings.
+    pub fn iter(&self) -> impl Iterator<Item = &str> {
+        self.0.iter().map(|v| v.raw.as_str())
+    }
+
+    /// The spellings with their normalized form and phonetic codes.
+    pub fn forms(&self) -> impl Iterator<Item = &Variation> {
+        self.0.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct IdentityNode {
+    /// Registry primary key, or `hashed_identity_id` when there is none
+    pub id: String,
     pub first_name: String,
     pub last_name: String,
     pub father_name: String,
@@ -15,46 +120,132 @@ pub struct IdentityNode {
     pub dob: Option<(u32, u32, u32)>,
     pub sex: u8,
     pub place_of_birth: String,
+    /// Phonetic codes of the six normalized names above
+    pub name_keys: NameKeys,
 
-    pub first_name_variations: Option<Box<VariationNode>>,
-    pub last_name_variations: Option<Box<VariationNode>>,
-    pub father_name_variations: Option<Box<VariationNode>>,
-    pub grandfather_name_variations: Option<Box<VariationNode>>,
-    pub mother_last_name_variations: Option<Box<VariationNode>>,
-    pub mother_name_variations: Option<Box<VariationNode>>,
+    pub first_name_variations: Variations,
+    pub last_name_variations: Variations,
+    pub father_name_variations: Variations,
+    pub grandfather_name_variations: Variations,
+    pub mother_last_name_variations: Variations,
+    pub mother_name_variations: Variations,
+}
 
-    pub next_identity: Option<Box<IdentityNode>>,
+/// Records in insertion order with an index on their ID: O(1) insert and
+/// lookup where the linked list walked every record.
+#[derive(Debug, Clone, Default)]
+pub struct IdentityArena {
+    records: Vec<IdentityNode>,
+    index: HashMap<String, usize>,
 }
 
-// Insert a variation into a sorted linked list (no duplicates)
-pub fn insert_variation(head: &mut Option<Box<VariationNode>>, variation: &str) {
-    let mut current = head;
-
-    loop {
-        let needs_insert = match current {
-            Some(node) if node.variation == variation => return,
-            Some(node) if node.variation.as_str() > variation => true,
-            Some(node) => {
-                current = &mut node.next_variation;
-                continue;
-            }
-            None => true,
-        };
-
-        if needs_insert {
-            let next = current.take();
-            let new_node = Box::new(VariationNode {
-                variation: variation.to_string(),
-                next_variation: next,
-            });
-            *current = Some(new_node);
-            return;
+impl IdentityArena {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        IdentityArena {
+            records: Vec::with_capacity(capacity),
+            index: HashMap::with_capacity(capacity),
         }
     }
+
+    /// Add a record, or merge its variations into the record with the same
+    /// ID; returns its position.
+    pub fn insert(&mut self, node: IdentityNode) -> usize {
+        if let Some(&position) = self.index.get(&node.id) {
+            let kept = &mut self.records[position];
+            kept.first_name_variations.merge(&node.first_name_variations);
+            kept.last_name_variations.merge(&node.last_name_variations);
+            kept.father_name_variations.merge(&node.father_name_variations);
+            kept.grandfather_name_variations.merge(&node.grandfather_name_variations);
+            kept.mother_last_name_variations.merge(&node.mother_last_name_variations);
+            kept.mother_name_variations.merge(&node.mother_name_variations);
+            return position;
+        }
+        let position = self.records.len();
+        self.index.insert(node.id.clone(), position);
+        self.records.push(node);
+        position
+    }
+
+    pub fn get(&self, id: &str) -> Option<&IdentityNode> {
+        self.index.get(id).map(|&position| &self.records[position])
+    }
+
+    pub fn get_mut(&mut self, id: &str) -> Option<&mut IdentityNode> {
+        self.index.get(id).map(|&position| &mut self.records[position])
+    }
+
+    pub fn len(&self) -> usize {
+        self.records.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, IdentityNode> {
+        self.records.iter()
+    }
+
+    pub fn records(&self) -> &[IdentityNode] {
+        &self.records
+    }
+
+    pub fn into_records(self) -> Vec<IdentityNode> {
+        self.records
+    }
+}
+
+impl FromIterator<IdentityNode> for IdentityArena {
+    fn from_iter<I: IntoIterator<Item = IdentityNode>>(iter: I) -> Self {
+        let iter = iter.into_iter();
+        let mut arena = IdentityArena::with_capacity(iter.size_hint().0);
+        for node in iter {
+            arena.insert(node);
+        }
+        arena
+    }
+}
+
+/// Insert a variation into a sorted variation list (no duplicates)
+pub fn insert_variation(list: &mut Variations, variation: &str) {
+    list.insert(variation);
 }
 
+/// Stable ID of a record without a registry key: a hash of its normalized
+/// names, birth date, sex and place of birth, so homonyms born on another
+/// day or elsewhere get another ID. The `NORMALIZER_VERSION` prefix
+/// ("n1-…") changes with the normalization, since the hash does too.
+pub fn hashed_identity_id(names: [&str; 6], dob: Option<(u32, u32, u32)>, sex: u8, place_of_birth: &str) -> String {
+    let (day, month, year) = dob.unwrap_or((0, 0, 0));
+    let mut hasher = Sha256::new();
+    for name in names {
+        hasher.update(name.as_bytes());
+        hasher.update([0x1f]);
+    }
+    hasher.update(format!("{}-{}-{}\u{1f}{}\u{1f}{}", day, month, year, sex, place_of_birth).as_bytes());
+    let hash: String = hasher.finalize()[..8].iter().map(|b| format!("{:02x}", b)).collect();
+    format!("n{}-{}", NORMALIZER_VERSION, hash)
+}
+
+/// Whether `id` is a `hashed_identity_id` of another normalizer version
+/// (or of before the version prefix), i.e. one no loaded record can have.
+pub fn is_stale_hashed_id(id: &str) -> bool {
+    let is_hash = |hash: &str| hash.len() == 16 && hash.bytes().all(|b| b.is_ascii_hexdigit());
+    match id.strip_prefix('n').and_then(|rest| rest.split_once('-')) {
+        Some((version, hash)) if is_hash(hash) => version.parse::<u32>().is_ok_and(|v| v != NORMALIZER_VERSION),
+        _ => is_hash(id),
+    }
+}
+
+/// Add a record to `arena`; the same person (same names, birth date, sex and
+/// place) seen again only adds its spellings.
+#[allow(clippy::too_many_arguments)]
 pub fn insert_identity(
-    head: &mut Option<Box<IdentityNode>>,
+    arena: &mut IdentityArena,
     first_name: &str,
     last_name: &str,
     father_name: &str,
@@ -71,115 +262,70 @@ pub fn insert_identity(
     mother_last_name_var: &str,
     mother_name_var: &str,
 ) {
-    let new_key = format!(
-        "{}{}{}{}{}{}",
-        first_name, last_name, father_name, grandfather_name, mother_last_name, mother_name
+    let id = hashed_identity_id(
+        [first_name, last_name, father_name, grandfather_name, mother_last_name, mother_name],
+        dob,
+        sex,
+        place_of_birth,
     );
 
-    let mut cursor = head;
-
-    loop {
-        // Separate scope for borrowing
-        let insert_here = match cursor {
-            Some(node) => {
-                let node_key = format!(
-                    "{}{}{}{}{}{}",
-                    node.first_name,
-                    node.last_name,
-                    node.father_name,
-                    node.grandfather_name,
-                    node.mother_last_name,
-                    node.mother_name
-                );
-
-                if node_key == new_key {
-                    insert_variation(&mut node.first_name_variations, first_name_var);
-                    insert_variation(&mut node.last_name_variations, last_name_var);
-                    insert_variation(&mut node.father_name_variations, father_name_var);
-                    insert_variation(&mut node.grandfather_name_variations, grandfather_name_var);
-                    insert_variation(&mut node.mother_last_name_variations, mother_last_name_var);
-                    insert_variation(&mut node.mother_name_variations, mother_name_var);
-                    return;
-                }
-
-                node_key > new_key
-            }
-            None => true,
-        };
-
-        if insert_here {
-            let new_node = Box::new(IdentityNode {
-                first_name: first_name.to_string(),
-                last_name: last_name.to_string(),
-                father_name: father_name.to_string(),
-                grandfather_name: grandfather_name.to_string(),
-                mother_last_name: mother_last_name.to_string(),
-                mother_name: mother_name.to_string(),
-                dob,
-                sex,
-                place_of_birth: place_of_birth.to_string(),
-                first_name_variations: Some(Box::new(VariationNode {
-                    variation: first_name_var.to_string(),
-                    next_variation: None,
-                })),
-                last_name_variations: Some(Box::new(VariationNode {
-                    variation: last_name_var.to_string(),
-                    next_variation: None,
-                })),
-                father_name_variations: Some(Box::new(VariationNode {
-                    variation: father_name_var.to_string(),
-                    next_variation: None,
-                })),
-                grandfather_name_variations: Some(Box::new(VariationNode {
-                    variation: grandfather_name_var.to_string(),
-                    next_variation: None,
-                })),
-                mother_last_name_variations: Some(Box::new(VariationNode {
-                    variation: mother_last_name_var.to_string(),
-                    next_variation: None,
-                })),
-                mother_name_variations: Some(Box::new(VariationNode {
-                    variation: mother_name_var.to_string(),
-                    next_variation: None,
-                })),
-                next_identity: cursor.take(),
-            });
-
-            *cursor = Some(new_node);
-            return;
-        }
-
-        if let Some(node) = cursor {
-            cursor = &mut node.next_identity;
-        }
+    if let Some(node) = arena.get_mut(&id) {
+        insert_variation(&mut node.first_name_variations, first_name_var);
+        insert_variation(&mut node.last_name_variations, last_name_var);
+        insert_variation(&mut node.father_name_variations, father_name_var);
+        insert_variation(&mut node.grandfather_name_variations, grandfather_name_var);
+        insert_variation(&mut node.mother_last_name_variations, mother_last_name_var);
+        insert_variation(&mut node.mother_name_variations, mother_name_var);
+        return;
     }
+
+    arena.insert(IdentityNode {
+        id,
+        first_name: first_name.to_string(),
+        last_name: last_name.to_string(),
+        father_name: father_name.to_string(),
+        grandfather_name: grandfather_name.to_string(),
+        mother_last_name: mother_last_name.to_string(),
+        mother_name: mother_name.to_string(),
+        dob,
+        sex,
+        place_of_birth: place_of_birth.to_string(),
+        name_keys: NameKeys::of([first_name, last_name, father_name, grandfather_name, mother_last_name, mother_name]),
+        first_name_variations: Variations::single(first_name_var.to_string()),
+        last_name_variations: Variations::single(last_name_var.to_string()),
+        father_name_variations: Variations::single(father_name_var.to_string()),
+        grandfather_name_variations: Variations::single(grandfather_name_var.to_string()),
+        mother_last_name_variations: Variations::single(mother_last_name_var.to_string()),
+        mother_name_variations: Variations::single(mother_name_var.to_string()),
+    });
 }
 
+// Normalized names, DOB, sex, place of birth, then the six raw name variations
+pub type IdentityRow = (
+    String, String, String, String, String, String,
+    Option<(u32, u32, u32)>,
+    u8,
+    String,
+    String, String, String, String, String, String,
+);
+
 // Rebuild the full identity dictionary from bulk records
-pub fn rebuild_identity_dictionary(
-    records: Vec<(
-        String, String, String, String, String, String,
-        Option<(u32, u32, u32)>,
-        u8,
-        String,
-        String, String, String, String, String, String,
-    )>,
-) -> Option<Box<IdentityNode>> {
-    let mut head = None;
+pub fn rebuild_identity_dictionary(records: Vec<IdentityRow>) -> IdentityArena {
+    let mut arena = IdentityArena::with_capacity(records.len());
 
     for (
         f, l, fa, g, ml, m, dob, sex, place,
         f_var, l_var, fa_var, g_var, ml_var, m_var,
     ) in records {
         insert_identity(
-            &mut head,
+            &mut arena,
             &f, &l, &fa, &g, &ml, &m,
             dob, sex, &place,
             &f_var, &l_var, &fa_var, &g_var, &ml_var, &m_var,
         );
     }
 
-    head
+    arena
 }
 impl IdentityNode {
     pub fn as_tuple(&self) -> (&str, &str, &str, &str, &str, &str) {
@@ -192,4 +338,19 @@ impl IdentityNode {
             &self.mother_name,
         )
     }
+
+    /// Fields in the shape `matching::should_consider_candidate` expects.
+    pub fn as_details(&self) -> CandidateDetails<'_> {
+        (
+            &self.first_name,
+            &self.last_name,
+            &self.father_name,
+            &self.grandfather_name,
+            &self.mother_last_name,
+            &self.mother_name,
+            self.dob,
+            self.sex,
+            &self.place_of_birth,
+        )
+    }
 }
@@ -1,107 +1,199 @@
 use bb8::Pool;
 use bb8_postgres::PostgresConnectionManager;
 use tokio_postgres::{NoTls, Row};
-use crate::utils::linked_list::IdentityNode;
-use crate::utils::normalization::{normalize_arabic, remove_diacritics, standardize_prefixes};
+use crate::utils::dob::BirthDate;
+use crate::utils::linked_list::{hashed_identity_id, IdentityArena, IdentityNode, Variations};
+use crate::utils::normalization::normalize_name;
+use crate::utils::phonetic::NameKeys;
+
+/// Connection string of the citizen registry
+pub const REGISTRY_DB: &str = "host=localhost port=5432 user=postgres password=9155 dbname=tunisian_citizens";
+
+/// Primary key column of `tunisian_citizens` from REGISTRY_ID_COLUMN; without
+/// it records get `hashed_identity_id`.
+pub fn registry_id_column() -> Option<String> {
+    std::env::var("REGISTRY_ID_COLUMN").ok().filter(|c| !c.is_empty())
+}
 
 /// Group birth years into decades (e.g. 1985 → 1980)
 pub fn generation_key(year: i32) -> i32 {
     (year / 10) * 10
 }
 
+/// Decade keys covering every year of `from..=to` (e.g. 1988..=1992 → [1980, 1990])
+pub fn generation_keys(from: i32, to: i32) -> Vec<i32> {
+    (generation_key(from)..=generation_key(to)).step_by(10).collect()
+}
+
+/// Decades to load for a query birth date: every decade overlapping the
+/// date's year range widened by `year_tolerance` on both sides, so a 1989
+/// query with ±10 years loads 1970, 1980 and 1990.
+/// `None` when no year is known: every generation has to be searched.
+pub fn generations_for(dob: Option<&BirthDate>, year_tolerance: u32) -> Option<Vec<i32>> {
+    let (from, to) = dob?.year_range();
+    if to == 0 {
+        return None;
+    }
+    Some(generation_keys(
+        from.saturating_sub(year_tolerance) as i32,
+        to.saturating_add(year_tolerance) as i32,
+    ))
+}
+
+/// One registry row as stored, before normalization; read from the table or
+/// from an extract file (see `registry_file`).
+#[derive(Debug, Clone)]
+pub struct RegistryRow {
+    pub registry_id:      Option<String>,
+    pub first_name:       String,
+    pub last_name:        String,
+    pub father_name:      String,
+    pub grandfather_name: String,
+    pub mother_last_name: String,
+    pub mother_name:      String,
+    pub dob:              Option<(u32, u32, u32)>,
+    /// `1`/`ذكر` or `2`/`أنثى`
+    pub sex:              String,
+    pub place_of_birth:   String,
+}
+
+impl RegistryRow {
+    /// Normalized record keeping the raw names as its only variations; its ID is
+    /// the registry key, or `hashed_identity_id` when there is none.
+    pub fn into_identity(self) -> IdentityNode {
+        // map gender to u8
+        let sex = match self.sex.trim() {
+            "1" | "ذكر"   => 1,
+            "2" | "أنثى"  => 2,
+            _             => 0,
+        };
+
+        // normalized bases
+        let base_first      = normalize_name(&self.first_name);
+        let base_last       = normalize_name(&self.last_name);
+        let base_father     = normalize_name(&self.father_name);
+        let base_grandpa    = normalize_name(&self.grandfather_name);
+        let base_mom_last   = normalize_name(&self.mother_last_name);
+        let base_mom        = normalize_name(&self.mother_name);
+
+        let place_of_birth = normalize_name(&self.place_of_birth);
+        let id = self.registry_id.unwrap_or_else(|| hashed_identity_id(
+            [&base_first, &base_last, &base_father, &base_grandpa, &base_mom_last, &base_mom],
+            self.dob,
+            sex,
+            &place_of_birth,
+        ));
+
+        let name_keys = NameKeys::of([&base_first, &base_last, &base_father, &base_grandpa, &base_mom_last, &base_mom]);
+
+        IdentityNode {
+            id,
+            first_name:      base_first,
+            last_name:       base_last,
+            father_name:     base_father,
+            grandfather_name: base_grandpa,
+            mother_last_name: base_mom_last,
+            mother_name:     base_mom,
+            dob:             self.dob,
+            sex,
+            place_of_birth,
+            name_keys,
+
+            // single‐entry variation lists: just the raw original text
+            first_name_variations:       Variations::single(self.first_name),
+            last_name_variations:        Variations::single(self.last_name),
+            father_name_variations:      Variations::single(self.father_name),
+            grandfather_name_variations: Variations::single(self.grandfather_name),
+            mother_last_name_variations: Variations::single(self.mother_last_name),
+            mother_name_variations:      Variations::single(self.mother_name),
+        }
+    }
+}
+
+/// Merge rows that carry the same ID, i.e. the same registry key, or the
+/// same names, DOB, sex and place when there is none (`hashed_identity_id`);
+/// their variations are combined. Different keys stay different records.
+pub fn merge_same_ids(records: Vec<IdentityNode>) -> Vec<IdentityNode> {
+    records.into_iter().collect::<IdentityArena>().into_records()
+}
+
 /// Load *only* the identities for a given decade (e.g. 1980s → 1980)
-pub async fn load_identities_by_generation(gen: i32) -> Vec<IdentityNode> {
-    println!("🔍 Connecting to PostgreSQL to load generation {}…", gen);
+pub async fn load_identities_by_generation(gen: i32) -> Result<Vec<IdentityNode>, String> {
+    load_identities_by_generations(Some(&[gen])).await
+}
+
+/// Load the identities of several decades, or of the whole table when `gens` is `None`.
+/// Rows are merged with `merge_same_ids`. Errors are those of the connection or the query.
+pub async fn load_identities_by_generations(gens: Option<&[i32]>) -> Result<Vec<IdentityNode>, String> {
+    println!("🔍 Connecting to PostgreSQL to load generations {:?}…", gens);
 
     // 1) Setup BB8 pool
     let manager = PostgresConnectionManager::new_from_stringlike(
-        "host=localhost port=5432 user=postgres password=9155 dbname=tunisian_citizens",
+        REGISTRY_DB,
         NoTls,
-    ).expect("Invalid connection string");
+    ).map_err(|e| format!("Invalid connection string: {}", e))?;
 
     let pool: Pool<PostgresConnectionManager<NoTls>> = Pool::builder()
         .max_size(10)
         .build(manager)
         .await
-        .expect("Failed to build pool");
+        .map_err(|e| format!("Failed to build pool: {}", e))?;
 
-    let conn = pool.get().await.expect("Failed to get connection");
+    let conn = pool.get().await.map_err(|e| format!("Failed to get connection: {}", e))?;
 
-    // 2) Fetch only that decade
-    let sql = r#"
+    // 2) Fetch only those decades
+    let id_column = registry_id_column();
+    let id_select = match &id_column {
+        Some(column) => format!("\"{}\"::text", column.replace('"', "\"\"")),
+        None => "NULL::text".to_string(),
+    };
+    let select = format!(
+        r#"
         SELECT
+            {} AS registry_id,
             الاسم, اسم_العائلة, اسم_الأب, اسم_الجد,
             اسم_عائلة_الأم, اسم_الأم,
             يوم_الميلاد, شهر_الميلاد, سنة_الميلاد,
             الجنس, مكان_الولادة
         FROM tunisian_citizens
-        WHERE (سنة_الميلاد / 10) * 10 = $1
-    "#;
+    "#,
+        id_select
+    );
     println!("🔎 Executing decade query…");
-    let rows: Vec<Row> = conn
-        .query(sql, &[&gen])
-        .await
-        .expect("Query failed");
+    let rows: Vec<Row> = match gens {
+        Some(gens) => {
+            let sql = format!("{} WHERE (سنة_الميلاد / 10) * 10 = ANY($1)", select);
+            conn.query(sql.as_str(), &[&gens]).await
+        }
+        None => conn.query(select.as_str(), &[]).await,
+    }
+    .map_err(|e| format!("Query failed: {}", e))?;
 
-    println!("✅ {} rows in generation {}", rows.len(), gen);
+    println!("✅ {} rows in generations {:?}", rows.len(), gens);
 
     // 3) Parse & normalize into a flat Vec<IdentityNode>
-    let normalize = |s: &str| {
-        let s = remove_diacritics(s);
-        let s = normalize_arabic(&s);
-        standardize_prefixes(&s)
-    };
-
-    rows.into_iter().filter_map(|row| {
+    let records: Vec<IdentityNode> = rows.into_iter().filter_map(|row| {
         // extract, skip row if any required field is missing
-        let first    = row.try_get::<_, String>("الاسم").ok()?;
-        let last     = row.try_get::<_, String>("اسم_العائلة").ok()?;
-        let father   = row.try_get::<_, String>("اسم_الأب").ok()?;
-        let grandpa  = row.try_get::<_, String>("اسم_الجد").ok()?;
-        let mom_last = row.try_get::<_, String>("اسم_عائلة_الأم").ok()?;
-        let mom      = row.try_get::<_, String>("اسم_الأم").ok()?;
-        let day      = row.try_get::<_, i32>("يوم_الميلاد").ok()? as u32;
-        let mon      = row.try_get::<_, i32>("شهر_الميلاد").ok()? as u32;
-        let year     = row.try_get::<_, i32>("سنة_الميلاد").ok()? as u32;
-        let gender   = row.try_get::<_, String>("الجنس").ok()?;
-        let place    = row.try_get::<_, String>("مكان_الولادة").ok()?;
-
-        // map gender to u8
-        let sex = match gender.as_str() {
-            "1" | "ذكر"   => 1,
-            "2" | "أنثى"  => 2,
-            _             => 0,
+        let row = RegistryRow {
+            registry_id:      row.try_get::<_, Option<String>>("registry_id").ok()?,
+            first_name:       row.try_get::<_, String>("الاسم").ok()?,
+            last_name:        row.try_get::<_, String>("اسم_العائلة").ok()?,
+            father_name:      row.try_get::<_, String>("اسم_الأب").ok()?,
+            grandfather_name: row.try_get::<_, String>("اسم_الجد").ok()?,
+            mother_last_name: row.try_get::<_, String>("اسم_عائلة_الأم").ok()?,
+            mother_name:      row.try_get::<_, String>("اسم_الأم").ok()?,
+            dob: Some((
+                row.try_get::<_, i32>("يوم_الميلاد").ok()? as u32,
+                row.try_get::<_, i32>("شهر_الميلاد").ok()? as u32,
+                row.try_get::<_, i32>("سنة_الميلاد").ok()? as u32,
+            )),
+            sex:              row.try_get::<_, String>("الجنس").ok()?,
+            place_of_birth:   row.try_get::<_, String>("مكان_الولادة").ok()?,
         };
+        Some(row.into_identity())
+    }).collect();
 
-        // normalized bases
-        let base_first      = normalize(&first);
-        let base_last       = normalize(&last);
-        let base_father     = normalize(&father);
-        let base_grandpa    = normalize(&grandpa);
-        let base_mom_last   = normalize(&mom_last);
-        let base_mom        = normalize(&mom);
-
-        // build IdentityNode
-        Some(IdentityNode {
-            first_name:      base_first,
-            last_name:       base_last,
-            father_name:     base_father,
-            grandfather_name: base_grandpa,
-            mother_last_name: base_mom_last,
-            mother_name:     base_mom,
-            dob:             Some((day, mon, year)),
-            sex,
-            place_of_birth:  place.clone(),
-
-            // single‐entry variation lists: just the raw original text
-            first_name_variations:      Some(Box::new(crate::utils::linked_list::VariationNode { variation: first, next_variation: None })),
-            last_name_variations:       Some(Box::new(crate::utils::linked_list::VariationNode { variation: last, next_variation: None })),
-            father_name_variations:     Some(Box::new(crate::utils::linked_list::VariationNode { variation: father, next_variation: None })),
-            grandfather_name_variations:Some(Box::new(crate::utils::linked_list::VariationNode { variation: grandpa, next_variation: None })),
-            mother_last_name_variations:Some(Box::new(crate::utils::linked_list::VariationNode { variation: mom_last, next_variation: None })),
-            mother_name_variations:     Some(Box::new(crate::utils::linked_list::VariationNode { variation: mom, next_variation: None })),
-
-            next_identity: None,
-        })
-    }).collect()
+    let unique = merge_same_ids(records);
+    println!("✅ {} distinct identities after merging", unique.len());
+    Ok(unique)
 }
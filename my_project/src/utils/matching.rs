@@ -1,40 +1,71 @@
 // src/utils/matching.rs
 
+use std::sync::Arc;
+use serde::Serialize;
 use strsim::{jaro, levenshtein};
-use crate::utils::linked_list::VariationNode;
-use crate::utils::normalization::{normalize_arabic, remove_diacritics, standardize_prefixes};
-use crate::utils::phonetic::aramix_soundex;
+use crate::utils::dob::compare_dob;
+use crate::utils::linked_list::{IdentityNode, Variations};
+use crate::utils::phonetic::{aramix_soundex, phonetic_key_similarity, phonetic_similarity, PhoneticKey};
+use crate::utils::pipeline::NormalizedInput;
+use crate::utils::scoring_profile::{ScoreField, ScoringProfile};
+use crate::utils::similarity::{soundex_jaro_levenshtein, NameSimilarity, TokenAlignment};
+use crate::utils::transliteration::cross_script_forms;
+
+/// Name fields, DOB, sex and place of birth as passed to `should_consider_candidate`.
+pub type CandidateDetails<'a> = (
+    &'a str, &'a str, &'a str, &'a str, &'a str, &'a str,
+    Option<(u32, u32, u32)>, u8, &'a str,
+);
 
 /// 🔠 Compare two already normalized strings with plain Jaro + normalized Levenshtein, plus a capped 20% Soundex bonus.
-/// Soundex comparison uses its own normalization via `aramix_soundex`.
+/// Soundex comparison uses its own normalization via `aramix_soundex`; when either
+/// string is Latin the bonus comes from `maghrebi_metaphone` instead (half for an alternate-code match).
 pub fn score_pair_with_soundex(norm_s1: &str, norm_s2: &str) -> f64 {
+    jaro_levenshtein_with_bonus(norm_s1, norm_s2, phonetic_similarity(norm_s1, norm_s2))
+}
+
+/// `score_pair_with_soundex` with the phonetic codes precomputed.
+pub fn score_pair_with_soundex_keyed(norm_s1: &str, key1: &PhoneticKey, norm_s2: &str, key2: &PhoneticKey) -> f64 {
+    jaro_levenshtein_with_bonus(norm_s1, norm_s2, phonetic_key_similarity(key1, key2))
+}
+
+/// Body of `score_pair_with_soundex` given the phonetic similarity of the pair.
+fn jaro_levenshtein_with_bonus(norm_s1: &str, norm_s2: &str, phonetic: f64) -> f64 {
     // 1) Strings are assumed to be pre-normalized for Jaro/Levenshtein.
     // 2) Compute plain Jaro (no prefix‐boost) and normalized Levenshtein
     let j = jaro(norm_s1, norm_s2);
-    let lev = 1.0 - (levenshtein(norm_s1, norm_s2)
-        .min(norm_s1.len()) as f64
-        / norm_s1.len().max(1) as f64);
+    let lev = normalized_levenshtein(norm_s1, norm_s2);
 
     // 3) Combine Jaro+Lev into 80% of the score
     let base_score = ((j + lev) / 2.0) * 0.8;
 
-    // 4) Add a flat 20% bonus if Soundex codes match.
+    // 4) Add a 20% bonus if the phonetic codes match.
     // `aramix_soundex` performs its own internal normalization suitable for phonetic coding.
-    let bonus = if aramix_soundex(norm_s1) == aramix_soundex(norm_s2) {
-        0.2
-    } else {
-        0.0
-    };
+    let bonus = 0.2 * phonetic;
 
     // 5) Final score, capped at 1.0
     (base_score + bonus).min(1.0)
 }
 
-/// Helper: average of phonetic match (0/1) and plain Jaro.
+/// Levenshtein distance scaled by the length of the first string, in [0, 1].
+fn normalized_levenshtein(norm_s1: &str, norm_s2: &str) -> f64 {
+    1.0 - (levenshtein(norm_s1, norm_s2)
+        .min(norm_s1.len()) as f64
+        / norm_s1.len().max(1) as f64)
+}
+
+/// Helper: average of phonetic match (0/1, or 0.5 for a Latin alternate-code match) and plain Jaro.
 /// Assumes input strings `norm_a` and `norm_b` are pre-normalized for Jaro.
 /// `aramix_soundex` handles its own normalization for the phonetic part.
 pub fn combo(norm_a: &str, norm_b: &str) -> f32 {
-    let p = (aramix_soundex(norm_a) == aramix_soundex(norm_b)) as u8 as f32;
+    let p = phonetic_similarity(norm_a, norm_b) as f32;
+    let j = jaro(norm_a, norm_b) as f32;
+    (p + j) / 2.0
+}
+
+/// `combo` with the phonetic codes precomputed.
+pub fn combo_keyed(norm_a: &str, key_a: &PhoneticKey, norm_b: &str, key_b: &PhoneticKey) -> f32 {
+    let p = phonetic_key_similarity(key_a, key_b) as f32;
     let j = jaro(norm_a, norm_b) as f32;
     (p + j) / 2.0
 }
@@ -42,119 +73,196 @@ pub fn combo(norm_a: &str, norm_b: &str) -> f32 {
 /// 🎯 Return the best score against the base string *and* all its variations.
 /// `norm_input` is the pre-normalized input string from the request.
 /// `norm_base` is the pre-normalized base string from the IdentityNode.
-/// `variations` contain raw strings that need normalization before comparison.
+/// `variations` carry their normalized forms.
 pub fn best_score_against_variations(
     norm_input: &str, // Pre-normalized input string
     norm_base: &str,  // Pre-normalized base string from IdentityNode
-    variations: &Option<Box<VariationNode>>,
+    variations: &Variations,
 ) -> f64 {
-    let mut best = score_pair_with_soundex(norm_input, norm_base);
-    let mut current_variation_node = variations;
-    while let Some(var_node) = current_variation_node {
-        // Normalize the raw variation string before comparing
-        let norm_variation = standardize_prefixes(&normalize_arabic(&remove_diacritics(&var_node.variation)));
-        let s = score_pair_with_soundex(norm_input, &norm_variation);
+    // Token-aligned so reordered, extra or merged name parts still score well
+    let metric = TokenAlignment::new("token_soundex_jaro_levenshtein", Arc::new(soundex_jaro_levenshtein()));
+    best_match_against_variations(
+        &metric,
+        norm_input,
+        &PhoneticKey::of(norm_input),
+        norm_base,
+        &PhoneticKey::of(norm_base),
+        variations,
+    )
+    .0
+}
+
+/// `metric` on two normalized names; a Latin name against an Arabic one is
+/// compared on their consonant skeletons (`transliteration::skeleton`).
+pub fn cross_script_similarity(metric: &dyn NameSimilarity, norm_a: &str, norm_b: &str) -> f64 {
+    match cross_script_forms(norm_a, norm_b) {
+        Some((a, b)) => metric.similarity(&a, &b),
+        None => metric.similarity(norm_a, norm_b),
+    }
+}
+
+/// `cross_script_similarity` on names given with their `PhoneticKey`s; the
+/// keys are used unless the pair is compared on skeletons.
+fn keyed_cross_script_similarity(
+    metric: &dyn NameSimilarity,
+    norm_a: &str,
+    key_a: &PhoneticKey,
+    norm_b: &str,
+    key_b: &PhoneticKey,
+) -> f64 {
+    match cross_script_forms(norm_a, norm_b) {
+        Some((a, b)) => metric.similarity(&a, &b),
+        None => metric.keyed_similarity(norm_a, key_a, norm_b, key_b),
+    }
+}
+
+/// 🎯 Same as `best_score_against_variations` with any metric, on names
+/// given with their phonetic codes.
+/// Also returns the raw variation that beat the base string, if one did.
+pub fn best_match_against_variations(
+    metric: &dyn NameSimilarity,
+    norm_input: &str,
+    input_key: &PhoneticKey,
+    norm_base: &str,
+    base_key: &PhoneticKey,
+    variations: &Variations,
+) -> (f64, Option<String>) {
+    let mut best = keyed_cross_script_similarity(metric, norm_input, input_key, norm_base, base_key);
+    let mut best_variation = None;
+    for variation in variations.forms() {
+        let s = keyed_cross_script_similarity(metric, norm_input, input_key, &variation.norm, &variation.key);
         if s > best {
             best = s;
+            best_variation = Some(variation.raw.clone());
         }
-        current_variation_node = &var_node.next_variation;
     }
-    best
-}
-
-/// 🎯 Compute the weighted full‐record score.
-/// Assumes `input_names` and `place1` are pre-normalized.
-/// Assumes `target_names` and `place2` (from IdentityNode) are already normalized by the loader.
-pub fn calculate_full_score(
-    // These are pre-normalized strings from the input request
-    input_norm_names: (&str, &str, &str, &str, &str, &str),
-    // These are already normalized strings from the IdentityNode
-    target_norm_names: (&str, &str, &str, &str, &str, &str),
-    _variations: ( // Variations are handled by best_score_against_variations, not directly here
-                   &Option<Box<VariationNode>>, &Option<Box<VariationNode>>, &Option<Box<VariationNode>>,
-                   &Option<Box<VariationNode>>, &Option<Box<VariationNode>>, &Option<Box<VariationNode>>,
-    ),
-    dob1: Option<(u32, u32, u32)>,
-    dob2: Option<(u32, u32, u32)>,
-    // Pre-normalized place from input request
-    place1_norm: &str,
-    // Already normalized place from IdentityNode
-    place2_norm: &str,
-    _sex1: u8, // Sex doesn't require string normalization
-    _sex2: u8,
-) -> f64 {
-    let (in_fn_norm, in_ln_norm, in_fa_norm, in_gd_norm, _in_ml_norm, in_m_norm) = input_norm_names;
-    let (t_fn_norm,  t_ln_norm,  t_fa_norm,  t_gd_norm,  _lt_ml_norm,  t_m_norm ) = target_norm_names;
-
-    // Fields are now assumed to be pre-normalized where necessary.
-    // No more internal `norm = |s: &str| ...` calls for these inputs.
-
-    // Weighted scoring
-    let mut score = 0.0;
-    let mut total = 0.0;
+    (best, best_variation)
+}
 
-    // First name (35%) - uses combo, which expects normalized inputs
-    score += combo(in_fn_norm, t_fn_norm) as f64 * 0.35;
-    total += 0.35;
+/// One line of a `ScoreExplanation`.
+/// `field` and `score` keep the shape of the former breakdown entries.
+#[derive(Debug, Clone, Serialize)]
+pub struct FieldExplanation {
+    /// Arabic label of the field
+    pub field: String,
+    pub key: ScoreField,
+    /// Raw similarity in percent (0–100)
+    pub score: f64,
+    pub metric: String,
+    /// Share of the total weight (0–1); 0 for disabled fields
+    pub weight: f64,
+    /// `score * weight`, in percentage points of the total
+    pub contribution: f64,
+    /// Registry variation that scored higher than the base value
+    pub matched_variation: Option<String>,
+    /// Comparator reason code (graded DOB), e.g. `day_month_swapped`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
 
-    // Last name (30%) - uses combo
-    score += combo(in_ln_norm, t_ln_norm) as f64 * 0.30;
-    total += 0.30;
+/// Per-field account of a candidate's score.
+/// `total_score` is exactly the sum of the `contribution`s.
+#[derive(Debug, Clone, Serialize)]
+pub struct ScoreExplanation {
+    pub profile: String,
+    pub total_score: f64,
+    pub breakdown: Vec<FieldExplanation>,
+}
 
-    // Father name (10%) - uses jaro directly with normalized inputs
-    score += jaro(in_fa_norm, t_fa_norm) * 0.10;
-    total += 0.10;
+fn round2(x: f64) -> f64 {
+    (x * 100.0).round() / 100.0
+}
 
-    // Grandfather name (5%) - uses jaro
-    score += jaro(in_gd_norm, t_gd_norm) * 0.05;
-    total += 0.05;
+/// 🎯 Score `candidate` against the normalized `input` with `profile`.
+/// Every field listed in the profile appears in the breakdown; name fields
+/// are compared against the base value and all its variations.
+pub fn explain_match(profile: &ScoringProfile, input: &NormalizedInput, candidate: &IdentityNode) -> ScoreExplanation {
+    let total_weight: f64 = profile.active_rules().map(|r| r.weight).sum();
 
-    // Mother name (5%) - uses jaro
-    score += jaro(in_m_norm, t_m_norm) * 0.05;
-    total += 0.05;
+    let mut breakdown = Vec::with_capacity(profile.fields.len());
+    for rule in &profile.fields {
+        let mut reason = None;
+        let (similarity, matched_variation) = match rule.field {
+            ScoreField::Dob if rule.metric == "graded" => {
+                let comparison = match &input.dob {
+                    Some(dob) => dob.compare(candidate.dob),
+                    None => compare_dob(None, candidate.dob),
+                };
+                reason = Some(comparison.reason_code());
+                (comparison.score, None)
+            }
+            ScoreField::Dob => {
+                // DOB exact match; a missing or approximate date on either side scores 0
+                let s = match (input.dob.and_then(|d| d.as_tuple()), candidate.dob) {
+                    (Some(d1), Some(d2)) => (d1 == d2) as u8 as f64,
+                    _ => 0.0,
+                };
+                (s, None)
+            }
+            ScoreField::Sex => ((input.sex == candidate.sex) as u8 as f64, None),
+            ScoreField::PlaceOfBirth => {
+                let metric = rule.similarity().expect("place rules carry their metric");
+                (cross_script_similarity(metric, &input.place_of_birth, &candidate.place_of_birth), None)
+            }
+            name_field => {
+                let (ik, ck) = (&input.name_keys, &candidate.name_keys);
+                let (norm_input, input_key, norm_base, base_key, variations) = match name_field {
+                    ScoreField::FirstName       => (&input.first_name,       &ik.first_name,       &candidate.first_name,       &ck.first_name,       &candidate.first_name_variations),
+                    ScoreField::LastName        => (&input.last_name,        &ik.last_name,        &candidate.last_name,        &ck.last_name,        &candidate.last_name_variations),
+                    ScoreField::FatherName      => (&input.father_name,      &ik.father_name,      &candidate.father_name,      &ck.father_name,      &candidate.father_name_variations),
+                    ScoreField::GrandfatherName => (&input.grandfather_name, &ik.grandfather_name, &candidate.grandfather_name, &ck.grandfather_name, &candidate.grandfather_name_variations),
+                    ScoreField::MotherLastName  => (&input.mother_last_name, &ik.mother_last_name, &candidate.mother_last_name, &ck.mother_last_name, &candidate.mother_last_name_variations),
+                    _                           => (&input.mother_name,      &ik.mother_name,      &candidate.mother_name,      &ck.mother_name,      &candidate.mother_name_variations),
+                };
+                let metric = rule.similarity().expect("name rules carry their metric");
+                best_match_against_variations(metric, norm_input, input_key, norm_base, base_key, variations)
+            }
+        };
 
-    // DOB exact match (10%)
-    if let (Some(d1), Some(d2)) = (dob1, dob2) {
-        score += (d1 == d2) as u8 as f64 * 0.10;
+        let active = rule.enabled && rule.weight > 0.0 && total_weight > 0.0;
+        let weight = if active { rule.weight / total_weight } else { 0.0 };
+        let score = round2(similarity * 100.0);
+        breakdown.push(FieldExplanation {
+            field: rule.field.label().to_string(),
+            key: rule.field,
+            score,
+            metric: rule.metric.clone(),
+            weight,
+            contribution: round2(similarity * 100.0 * weight),
+            matched_variation,
+            reason,
+        });
     }
-    total += 0.10;
 
-    // Place of birth (5%) - uses jaro with normalized inputs
-    score += jaro(place1_norm, place2_norm) * 0.05;
-    total += 0.05;
+    let total_score = round2(breakdown.iter().map(|f| f.contribution).sum());
+    ScoreExplanation { profile: profile.name.clone(), total_score, breakdown }
+}
 
-    score / total
+/// 🎯 Weighted full‐record score in [0, 1]; see `explain_match` for the per-field detail.
+pub fn calculate_full_score(profile: &ScoringProfile, input: &NormalizedInput, candidate: &IdentityNode) -> f64 {
+    explain_match(profile, input, candidate).total_score / 100.0
 }
 
+/// Birth-year window (± years) of the pre-filter unless a profile sets its own
+pub const DEFAULT_YEAR_TOLERANCE: u32 = 10;
+
 /// Pre‐filter candidates by sex, decade window, and phonetic last‐name.
 /// `input_norm_ln` is the pre-normalized last name from the request.
 /// `candidate_norm_ln` is the pre-normalized last name from the IdentityNode.
 pub fn should_consider_candidate(
-    input_details: &( // Contains pre-normalized last name
-                      &str, &str, &str, &str, &str, &str, // other names not used by this function directly for filtering
-                      Option<(u32, u32, u32)>, u8, &str // dob, sex, place (place not used for filtering)
-    ),
-    candidate_details: &( // Contains pre-normalized last name
-                          &str, &str, &str, &str, &str, &str, // other names
-                          Option<(u32, u32, u32)>, u8, &str // dob, sex, place
-    ),
+    input_details: &CandidateDetails, // Contains pre-normalized last name; place not used for filtering
+    candidate_details: &CandidateDetails,
+    year_tolerance: u32,
 ) -> bool {
     // Parameter names changed to reflect they are expected to be normalized for string fields
-    let (_, input_norm_ln, _, _, _, _, in_dob, in_sex, _) = input_details;
-    let (_, candidate_norm_ln, _, _, _, _, cand_dob, cand_sex, _) = candidate_details;
+    let (_, input_norm_ln, _, _, _, _, _, _, _) = input_details;
+    let (_, candidate_norm_ln, _, _, _, _, _, _, _) = candidate_details;
 
-    // 1) Sex must match
-    if in_sex != cand_sex {
+    // 1-2) Sex and birth-year window
+    if !passes_demographic_filter(input_details, candidate_details, year_tolerance) {
         return false;
     }
 
-    // 2) Birth-year within ±10 years
-    if let (Some((_,_,y1)), Some((_,_,y2))) = (*in_dob, *cand_dob) {
-        if (y1 as i32 - y2 as i32).abs() > 10 {
-            return false;
-        }
-    }
-
     // 3) Last-name Soundex must match.
     // `aramix_soundex` handles its own normalization.
     // The input strings `input_norm_ln` and `candidate_norm_ln` are passed directly.
@@ -164,3 +272,28 @@ pub fn should_consider_candidate(
 
     true
 }
+
+/// The sex and birth-year part of `should_consider_candidate`, for candidates
+/// that already come out of a blocking index (see `blocking.rs`).
+pub fn passes_demographic_filter(
+    input_details: &CandidateDetails,
+    candidate_details: &CandidateDetails,
+    year_tolerance: u32,
+) -> bool {
+    let (_, _, _, _, _, _, in_dob, in_sex, _) = input_details;
+    let (_, _, _, _, _, _, cand_dob, cand_sex, _) = candidate_details;
+
+    // 1) Sex must match
+    if in_sex != cand_sex {
+        return false;
+    }
+
+    // 2) Birth-year within ±year_tolerance years
+    if let (Some((_,_,y1)), Some((_,_,y2))) = (*in_dob, *cand_dob) {
+        if y1.abs_diff(y2) > year_tolerance {
+            return false;
+        }
+    }
+
+    true
+}
@@ -4,3 +4,20 @@ pub mod linked_list;
 pub mod normalization;
 pub mod phonetic;
 pub mod gold_set;
+pub mod scoring_profile;
+pub mod similarity;
+pub mod pipeline;
+pub mod dob;
+pub mod identity_store;
+pub mod blocking;
+pub mod batch;
+pub mod transliteration;
+pub mod variants;
+pub mod variant_mining;
+pub mod evaluate;
+pub mod tuning;
+pub mod features;
+pub mod classifier;
+pub mod adjudication;
+pub mod registry_snapshot;
+pub mod registry_file;
@@ -1,5 +1,8 @@
 // 📌 Enhanced Normalization for Arabic Names
 use regex::Regex;
+use serde::Serialize;
+use unicode_normalization::UnicodeNormalization;
+use crate::utils::transliteration::{fold_latin, is_latin, strip_latin_prefix};
 
 /// Extract potential named entities (name, location, date) from input
 pub fn extract_named_entities(text: &str) -> (Option<String>, Option<String>, Option<String>) {
@@ -33,38 +36,389 @@ pub fn extract_named_entities(text: &str) -> (Option<String>, Option<String>, Op
     (name, date, place)
 }
 
-pub fn normalize_arabic(text: &str) -> String {
-    text.replace("ة", "ه")  // Convert "ة" to "ه"
-        .replace("ى", "ي")  // Convert "ى" to "ي"
-        .replace("أ", "ا")  // Normalize Hamza variations
-        .replace("إ", "ا")  // Normalize Hamza variations
-        .replace("ؤ", "و")  // Convert Hamza-on-Waw to Waw
-        .replace("ئ", "ي")  // Convert Hamza-on-Ya to Ya
-        .replace("ﻷ", "لا")  // Normalize Lam-Alef ligature
-        .replace("ﻵ", "لا")  // Normalize Lam-Alef ligature
-        .replace("ﻹ", "لا")  // Normalize Lam-Alef ligature
-        .replace("ﻻ", "لا")  // Normalize Lam-Alef ligature
+/// What `canonicalize_arabic` does with one character after NFKC.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Fold {
+    Drop,
+    To(char),
 }
 
-pub fn remove_diacritics(text: &str) -> String {
-    let diacritics = ["َ", "ً", "ُ", "ٌ", "ِ", "ٍ", "ّ", "ْ"];
-    let mut result = text.to_string();
-    for dia in diacritics.iter() {
-        result = result.replace(dia, "");
+/// Letter variants and invisible characters, sorted by code point for
+/// `binary_search_by_key`. Ranges (diacritics, Quranic marks, digits) are
+/// handled in `fold_char`.
+const FOLD_TABLE: &[(char, Fold)] = &[
+    ('\u{061C}', Fold::Drop),       // Arabic letter mark
+    ('\u{0622}', Fold::To('ا')),    // آ
+    ('\u{0623}', Fold::To('ا')),    // أ
+    ('\u{0624}', Fold::To('و')),    // ؤ
+    ('\u{0625}', Fold::To('ا')),    // إ
+    ('\u{0626}', Fold::To('ي')),    // ئ
+    ('\u{0629}', Fold::To('ه')),    // ة
+    ('\u{0640}', Fold::Drop),       // tatweel ـ
+    ('\u{0649}', Fold::To('ي')),    // ى
+    ('\u{0670}', Fold::Drop),       // superscript alef
+    ('\u{0671}', Fold::To('ا')),    // ٱ alef wasla
+    ('\u{0672}', Fold::To('ا')),    // ٲ
+    ('\u{0673}', Fold::To('ا')),    // ٳ
+    ('\u{0674}', Fold::Drop),       // ٴ high hamza, left by NFKC of ٵ ٶ ٷ ٸ
+    ('\u{0675}', Fold::To('ا')),    // ٵ
+    ('\u{0676}', Fold::To('و')),    // ٶ
+    ('\u{0677}', Fold::To('و')),    // ٷ
+    ('\u{0678}', Fold::To('ي')),    // ٸ
+    ('\u{06A9}', Fold::To('ك')),    // ک Persian keheh
+    ('\u{06AA}', Fold::To('ك')),    // ڪ
+    ('\u{06BE}', Fold::To('ه')),    // ھ
+    ('\u{06C0}', Fold::To('ه')),    // ۀ
+    ('\u{06C1}', Fold::To('ه')),    // ہ
+    ('\u{06C2}', Fold::To('ه')),    // ۂ
+    ('\u{06C3}', Fold::To('ه')),    // ۃ
+    ('\u{06C4}', Fold::To('و')),    // ۄ
+    ('\u{06C5}', Fold::To('و')),    // ۅ
+    ('\u{06C7}', Fold::To('و')),    // ۇ
+    ('\u{06CC}', Fold::To('ي')),    // ی Farsi yeh
+    ('\u{06CD}', Fold::To('ي')),    // ۍ
+    ('\u{06CE}', Fold::To('ي')),    // ێ
+    ('\u{06D0}', Fold::To('ي')),    // ې
+    ('\u{06D2}', Fold::To('ي')),    // ے
+    ('\u{06D3}', Fold::To('ي')),    // ۓ
+    ('\u{06D5}', Fold::To('ه')),    // ە
+    ('\u{200B}', Fold::Drop),       // zero-width space
+    ('\u{200C}', Fold::Drop),       // ZWNJ
+    ('\u{200D}', Fold::Drop),       // ZWJ
+    ('\u{200E}', Fold::Drop),       // LRM
+    ('\u{200F}', Fold::Drop),       // RLM
+    ('\u{202A}', Fold::Drop),       // LRE
+    ('\u{202B}', Fold::Drop),       // RLE
+    ('\u{202C}', Fold::Drop),       // PDF
+    ('\u{202D}', Fold::Drop),       // LRO
+    ('\u{202E}', Fold::Drop),       // RLO
+    ('\u{2060}', Fold::Drop),       // word joiner
+    ('\u{2066}', Fold::Drop),       // LRI
+    ('\u{2067}', Fold::Drop),       // RLI
+    ('\u{2068}', Fold::Drop),       // FSI
+    ('\u{2069}', Fold::Drop),       // PDI
+    ('\u{FEFF}', Fold::Drop),       // BOM / zero-width no-break space
+];
+
+/// Fold of one NFKC character; `None` keeps it as is.
+fn fold_char(c: char) -> Option<Fold> {
+    match c {
+        // Harakat, shadda, sukun, hamza/madda marks and Quranic annotations
+        '\u{0610}'..='\u{061A}' | '\u{064B}'..='\u{065F}' | '\u{06D6}'..='\u{06ED}' => Some(Fold::Drop),
+        // Arabic-Indic and Persian digits
+        '\u{0660}'..='\u{0669}' => char::from_digit(c as u32 - 0x0660, 10).map(Fold::To),
+        '\u{06F0}'..='\u{06F9}' => char::from_digit(c as u32 - 0x06F0, 10).map(Fold::To),
+        _ => FOLD_TABLE
+            .binary_search_by_key(&c, |&(from, _)| from)
+            .ok()
+            .map(|i| FOLD_TABLE[i].1),
+    }
+}
+
+/// 🔤 Canonical form of Arabic-script text, in a single pass over its NFKC
+/// decomposition. The output:
+/// - has presentation forms replaced by base letters (NFKC), including every
+///   lam-alef ligature ("ﻷ" → "لا");
+/// - has no harakat, shadda, sukun, superscript alef, Quranic marks or tatweel;
+/// - writes alef as ا (أ إ آ ٱ…), final yeh as ي (ى ئ ی…), ة/ە/ھ as ه,
+///   ؤ as و and Persian ک as ك; the standalone hamza ء is kept;
+/// - has no zero-width (ZWJ, ZWNJ, ZWSP, BOM) or bidi control characters;
+/// - writes Arabic-Indic digits as ASCII digits;
+/// - has whitespace runs collapsed to a single space and no leading or
+///   trailing space.
+///
+/// Latin letters pass through NFKC only.
+pub fn canonicalize_arabic(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut pending_space = false;
+    for c in text.nfkc() {
+        let c = match fold_char(c) {
+            Some(Fold::Drop) => continue,
+            Some(Fold::To(folded)) => folded,
+            None => c,
+        };
+        if c.is_whitespace() {
+            pending_space = !out.is_empty();
+            continue;
+        }
+        if pending_space {
+            out.push(' ');
+            pending_space = false;
+        }
+        out.push(c);
+    }
+    out
+}
+
+/// Role of a token in a canonical name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TokenKind {
+    /// An ordinary name, article stripped ("الطرابلسي" → "طرابلسي")
+    Name,
+    /// عبد + divine name, written fused: "عبد الرحمن" → "عبدالرحمن"
+    Theophoric,
+    /// Name + الدين / الله, written fused: "نور الدين" → "نورالدين"
+    Compound,
+    /// بن / بنت / ولد / ابن; ابن and ولد are written "بن"
+    Nasab,
+    /// ابو / ام + name, written fused; ابو is written "بو" ("ابو زيد" → "بوزيد")
+    Kunya,
+}
+
+/// One token of `tokenize_name`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct NameToken {
+    pub text: String,
+    pub kind: TokenKind,
+}
+
+/// Names where the leading "ال" is part of the name, not an article
+/// (Elyes, Ilham, Olfa…), after `canonicalize_arabic`.
+const INTRINSIC_AL: [&str; 8] = ["الياس", "اليسع", "الهام", "الفه", "الفت", "الماس", "اليسا", "الين"];
+
+/// Second halves that fuse with the preceding token.
+const COMPOUND_TAILS: [&str; 2] = ["الدين", "الله"];
+
+/// Divine names written after عبد without an article; those with one
+/// ("الرحمن", "العزيز"…) are recognized by the article itself.
+const THEOPHORIC_TAILS: [&str; 2] = ["الله", "ربه"];
+
+/// Whether `tail`, what follows عبد in a fused word, is a divine name, so
+/// "عبدالعزيز" is theophoric and the surnames "عبدلي" or "عبدلاوي" are not.
+fn is_theophoric_tail(tail: &str) -> bool {
+    THEOPHORIC_TAILS.contains(&tail) || tail.strip_prefix("ال").is_some_and(|name| name.chars().count() >= 3)
+}
+
+/// Drop the article of a single name unless it belongs to the name
+/// or would leave fewer than three letters.
+fn strip_article(word: &str) -> &str {
+    match word.strip_prefix("ال") {
+        Some(rest) if rest.chars().count() >= 3 && !INTRINSIC_AL.contains(&word) => rest,
+        _ => word,
+    }
+}
+
+/// Split a name into canonical tokens after `canonicalize_arabic`. Particles are only recognized as whole words, so
+/// "بنان" or "امال" are left alone:
+/// - "عبد الرحمن" and "عبدالرحمن" both give `[عبدالرحمن]` (Theophoric);
+/// - "محمد بن علي" gives `[محمد, بن, علي]`, "ولد علي" `[بن, علي]`;
+/// - "ابو بكر" and "ابوبكر" give `[بوبكر]` (Kunya), "ام كلثوم" `[امكلثوم]`;
+/// - "نور الدين" gives `[نورالدين]`, "محمد الامين" `[محمد, امين]`.
+pub fn tokenize_name(text: &str) -> Vec<NameToken> {
+    let cleaned = canonicalize_arabic(text);
+    let words: Vec<&str> = cleaned
+        .split([' ', '-'])
+        .filter(|w| !w.is_empty())
+        .collect();
+
+    let mut tokens = Vec::with_capacity(words.len());
+    let mut i = 0;
+    while i < words.len() {
+        let word = words[i];
+        let next = words.get(i + 1).copied();
+        let token = |text: String, kind: TokenKind| NameToken { text, kind };
+
+        let (tok, used) = match (word, next) {
+            ("عبد", Some(next)) => (token(format!("عبد{}", next), TokenKind::Theophoric), 2),
+            ("ابو" | "بو", Some(next)) => (token(format!("بو{}", next), TokenKind::Kunya), 2),
+            ("ام", Some(next)) => (token(format!("ام{}", next), TokenKind::Kunya), 2),
+            ("بن" | "ابن" | "ولد", Some(_)) => (token("بن".to_string(), TokenKind::Nasab), 1),
+            ("بنت", Some(_)) => (token("بنت".to_string(), TokenKind::Nasab), 1),
+            (_, Some(next)) if COMPOUND_TAILS.contains(&next) => {
+                (token(format!("{}{}", strip_article(word), next), TokenKind::Compound), 2)
+            }
+            _ if word.strip_prefix("عبد").is_some_and(is_theophoric_tail) => {
+                (token(word.to_string(), TokenKind::Theophoric), 1)
+            }
+            _ if word.starts_with("ابو") && word.chars().count() > 3 => {
+                (token(format!("بو{}", &word["ابو".len()..]), TokenKind::Kunya), 1)
+            }
+            _ if COMPOUND_TAILS.iter().any(|tail| word.ends_with(tail) && word.len() > tail.len()) => {
+                (token(word.to_string(), TokenKind::Compound), 1)
+            }
+            _ => (token(strip_article(word).to_string(), TokenKind::Name), 1),
+        };
+        tokens.push(tok);
+        i += used;
     }
-    result
+    tokens
 }
 
-pub fn standardize_prefixes(text: &str) -> String {
-    let prefixes = ["ال", "بن", "ابن", "بنت", "أبو", "أم"];
-    let mut normalized = text.to_string();
+/// Version of `normalize_name`'s output. Bump it whenever a change to the
+/// normalization changes the form of some name: `hashed_identity_id` carries
+/// it, so IDs derived from the old form are recognizably stale.
+pub const NORMALIZER_VERSION: u32 = 1;
 
-    for prefix in prefixes.iter() {
-        if normalized.starts_with(prefix) {
-            normalized = normalized[prefix.len()..].to_string();
-            break; // Remove only the first matching prefix
+/// Full normalization applied to every name before comparison:
+/// `canonicalize_arabic`, then `tokenize_name`'s canonical tokens
+/// joined by single spaces, without a leading بن/بنت ("بن علي" → "علي").
+/// Latin-script names are lowercased, stripped of accents and of a leading
+/// "ben"/"el"…; they are compared to Arabic ones through
+/// `transliteration::cross_script_forms`.
+pub fn normalize_name(text: &str) -> String {
+    if is_latin(text) {
+        return strip_latin_prefix(&fold_latin(text)).to_string();
+    }
+    let tokens = tokenize_name(text);
+    let skip = match tokens.first() {
+        Some(first) if first.kind == TokenKind::Nasab && tokens.len() > 1 => 1,
+        _ => 0,
+    };
+    tokens[skip..].iter().map(|t| t.text.as_str()).collect::<Vec<_>>().join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fold_table_is_sorted_and_reachable() {
+        assert!(
+            FOLD_TABLE.windows(2).all(|pair| pair[0].0 < pair[1].0),
+            "FOLD_TABLE must be sorted by code point for binary_search_by_key"
+        );
+        // No entry is shadowed by one of the ranges of `fold_char`
+        for &(from, fold) in FOLD_TABLE {
+            assert_eq!(fold_char(from), Some(fold), "U+{:04X}", from as u32);
         }
     }
 
-    normalized
+    #[test]
+    fn drops_tatweel_harakat_and_quranic_marks() {
+        assert_eq!(canonicalize_arabic("محـــمد"), "محمد");
+        assert_eq!(canonicalize_arabic("مُحَمَّد"), "محمد");
+        assert_eq!(canonicalize_arabic("عَلِيٌّ"), "علي");
+        assert_eq!(canonicalize_arabic("رحمٰن"), "رحمن");
+        assert_eq!(canonicalize_arabic("الرَّحِيمِۖ"), "الرحيم");
+        assert_eq!(canonicalize_arabic("\u{0610}محمد"), "محمد");
+    }
+
+    #[test]
+    fn folds_alef_variants() {
+        for alef in ["أ", "إ", "آ", "ٱ", "ٲ", "ٳ", "ٵ"] {
+            assert_eq!(canonicalize_arabic(&format!("{}حمد", alef)), "احمد", "{}", alef);
+        }
+        assert_eq!(canonicalize_arabic("ٱلله"), "الله");
+        // NFKC splits these into a base letter and a high hamza
+        assert_eq!(canonicalize_arabic("ٶسف"), "وسف");
+        assert_eq!(canonicalize_arabic("ٷسف"), "وسف");
+        assert_eq!(canonicalize_arabic("ٸس"), "يس");
+    }
+
+    #[test]
+    fn folds_yeh_teh_marbuta_heh_waw_and_kaf_variants() {
+        assert_eq!(canonicalize_arabic("مصطفى"), "مصطفي");
+        assert_eq!(canonicalize_arabic("هانئ"), "هاني");
+        assert_eq!(canonicalize_arabic("علی"), "علي"); // U+06CC Farsi yeh
+        assert_eq!(canonicalize_arabic("موسے"), "موسي"); // U+06D2
+        assert_eq!(canonicalize_arabic("فاطمة"), "فاطمه");
+        assert_eq!(canonicalize_arabic("فاطمە"), "فاطمه"); // U+06D5
+        assert_eq!(canonicalize_arabic("ھادي"), "هادي"); // U+06BE
+        assert_eq!(canonicalize_arabic("مؤمن"), "مومن");
+        assert_eq!(canonicalize_arabic("کمال"), "كمال"); // U+06A9 keheh
+        assert_eq!(canonicalize_arabic("ڪمال"), "كمال"); // U+06AA
+    }
+
+    #[test]
+    fn keeps_standalone_hamza() {
+        assert_eq!(canonicalize_arabic("سماء"), "سماء");
+    }
+
+    #[test]
+    fn replaces_presentation_forms() {
+        // Lam-alef ligatures
+        assert_eq!(canonicalize_arabic("\u{FEFB}"), "لا");
+        assert_eq!(canonicalize_arabic("\u{FEF7}"), "لا");
+        assert_eq!(canonicalize_arabic("\u{FEF5}"), "لا");
+        assert_eq!(canonicalize_arabic("\u{FEF9}"), "لا");
+        // Initial, medial and final forms of Presentation Forms-B
+        assert_eq!(canonicalize_arabic("\u{FEE3}\u{FEA4}\u{FEE4}\u{FEAA}"), "محمد");
+        assert_eq!(canonicalize_arabic("\u{FE8E}\u{FEDF}\u{FEE0}\u{FEEA}"), "الله");
+        assert_eq!(canonicalize_arabic("\u{FE94}"), "ه"); // final teh marbuta
+        assert_eq!(canonicalize_arabic("\u{FEF0}"), "ي"); // final alef maksura
+        assert_eq!(canonicalize_arabic("\u{FE83}"), "ا"); // isolated alef with hamza
+        // Presentation Forms-A: Persian keheh and Farsi yeh
+        assert_eq!(canonicalize_arabic("\u{FB8E}"), "ك");
+        assert_eq!(canonicalize_arabic("\u{FBFC}"), "ي");
+        // Tatweel-with-mark forms fold to nothing
+        assert_eq!(canonicalize_arabic("م\u{FE71}د"), "مد");
+    }
+
+    #[test]
+    fn drops_zero_width_and_bidi_characters() {
+        assert_eq!(canonicalize_arabic("مح\u{200D}مد"), "محمد"); // ZWJ
+        assert_eq!(canonicalize_arabic("مح\u{200C}مد"), "محمد"); // ZWNJ
+        assert_eq!(canonicalize_arabic("مح\u{200B}مد"), "محمد"); // ZWSP
+        assert_eq!(canonicalize_arabic("\u{FEFF}محمد"), "محمد"); // BOM
+        assert_eq!(canonicalize_arabic("مح\u{2060}مد"), "محمد"); // word joiner
+        for mark in ['\u{061C}', '\u{200E}', '\u{200F}', '\u{202A}', '\u{202B}', '\u{202C}', '\u{202D}', '\u{202E}',
+                     '\u{2066}', '\u{2067}', '\u{2068}', '\u{2069}'] {
+            assert_eq!(canonicalize_arabic(&format!("{}علي{}", mark, mark)), "علي", "U+{:04X}", mark as u32);
+        }
+    }
+
+    #[test]
+    fn writes_digits_as_ascii() {
+        assert_eq!(canonicalize_arabic("١٩٨٥"), "1985");
+        assert_eq!(canonicalize_arabic("۱۲۳۴۵۶۷۸۹۰"), "1234567890");
+    }
+
+    #[test]
+    fn collapses_whitespace() {
+        assert_eq!(canonicalize_arabic("  محمد \t\n  علي  "), "محمد علي");
+        assert_eq!(canonicalize_arabic("محمد\u{00A0}علي"), "محمد علي");
+        assert_eq!(canonicalize_arabic("  \u{200F} "), "");
+        assert_eq!(canonicalize_arabic(""), "");
+    }
+
+    #[test]
+    fn latin_goes_through_nfkc_only() {
+        assert_eq!(canonicalize_arabic("Ｍohamed"), "Mohamed");
+        assert_eq!(canonicalize_arabic("ﬁras"), "firas");
+        assert_eq!(canonicalize_arabic("Hélène"), "Hélène");
+    }
+
+    #[test]
+    fn documented_canonical_output() {
+        assert_eq!(
+            canonicalize_arabic("  ٱلسَّيِّدَة\u{200F}  فاطمـــة   الزَّهْرَاء\u{200D} ١٩٨٥ "),
+            "السيده فاطمه الزهراء 1985"
+        );
+        assert_eq!(canonicalize_arabic("إِيمَان بنت عبد الله"), "ايمان بنت عبد الله");
+    }
+
+    #[test]
+    fn canonical_form_is_stable() {
+        for text in ["ٱلسَّيِّدَة فاطمة", "\u{FEFB}مين", "کمال الدین", "  ١٩٨٥ ", "Ｍohamed"] {
+            let once = canonicalize_arabic(text);
+            assert_eq!(canonicalize_arabic(&once), once, "{}", text);
+        }
+    }
+
+    #[test]
+    fn tokenizes_documented_examples() {
+        let texts = |text: &str| tokenize_name(text).into_iter().map(|t| t.text).collect::<Vec<_>>();
+        assert_eq!(texts("عبد الرحمن"), ["عبدالرحمن"]);
+        assert_eq!(texts("عبدالرحمن"), ["عبدالرحمن"]);
+        assert_eq!(texts("محمد بن علي"), ["محمد", "بن", "علي"]);
+        assert_eq!(texts("ولد علي"), ["بن", "علي"]);
+        assert_eq!(texts("ابو بكر"), ["بوبكر"]);
+        assert_eq!(texts("ابوبكر"), ["بوبكر"]);
+        assert_eq!(texts("ام كلثوم"), ["امكلثوم"]);
+        assert_eq!(texts("نور الدين"), ["نورالدين"]);
+        assert_eq!(texts("محمد الامين"), ["محمد", "امين"]);
+        assert_eq!(normalize_name("بن علي"), "علي");
+    }
+
+    #[test]
+    fn tags_theophoric_names_only_with_a_divine_name() {
+        let kinds = |text: &str| tokenize_name(text).into_iter().map(|t| t.kind).collect::<Vec<_>>();
+        assert_eq!(kinds("عبدالعزيز"), [TokenKind::Theophoric]);
+        assert_eq!(kinds("عبدالله"), [TokenKind::Theophoric]);
+        assert_eq!(kinds("عبدربه"), [TokenKind::Theophoric]);
+        assert_eq!(kinds("عبدلي"), [TokenKind::Name]);
+        assert_eq!(kinds("عبدلاوي"), [TokenKind::Name]);
+        assert_eq!(normalize_name("عبدلاوي"), "عبدلاوي");
+    }
 }
@@ -1,22 +1,11 @@
-/// 🔠 Normalise les lettres arabes (alif, ya, waw...) + enlève les diacritiques
+use serde::{Deserialize, Serialize};
+use crate::utils::normalization::canonicalize_arabic;
+use crate::utils::transliteration::{arabic_to_latin, fold_latin, is_latin, latin_to_arabic, strip_latin_prefix};
+
+/// 🔠 Forme canonique (`canonicalize_arabic`) sans la hamza isolée ء,
+/// qui n’a pas de code phonétique
 pub fn normalize_arabic_letters(input: &str) -> String {
-    input
-        .replace('أ', "ا")
-        .replace('إ', "ا")
-        .replace('آ', "ا")
-        .replace('ى', "ي")
-        .replace('ئ', "ي")
-        .replace('ؤ', "و")
-        .replace('ة', "ه")
-        .replace('ء', "")
-        .replace('َ', "")
-        .replace('ً', "")
-        .replace('ُ', "")
-        .replace('ٌ', "")
-        .replace('ِ', "")
-        .replace('ٍ', "")
-        .replace('ْ', "")
-        .replace('ّ', "")
+    canonicalize_arabic(input).replace('ء', "")
 }
 
 /// 🔊 Encode un nom arabe avec un Soundex personnalisé (Aramix Soundex)
@@ -53,7 +42,248 @@ pub fn get_code(name: &str) -> String {
 }
 
 /// 🎯 Point d’entrée unique : normalisation + Soundex
+/// Les noms en caractères latins sont d’abord translittérés en arabe.
 pub fn aramix_soundex(name: &str) -> String {
-    let normalized = normalize_arabic_letters(name);
+    let normalized = if is_latin(name) {
+        normalize_arabic_letters(&latin_to_arabic(name))
+    } else {
+        normalize_arabic_letters(name)
+    };
     get_code(&normalized)
 }
+
+/// Longest code `maghrebi_metaphone` returns.
+const METAPHONE_LENGTH: usize = 6;
+
+fn push_code(code: &mut String, c: char) {
+    if !code.ends_with(c) && code.chars().count() < METAPHONE_LENGTH {
+        code.push(c);
+    }
+}
+
+fn is_latin_vowel(c: Option<char>) -> bool {
+    matches!(c, Some('a' | 'e' | 'i' | 'o' | 'u' | 'y'))
+}
+
+/// 🔊 Encodeur phonétique de type Double Metaphone pour les transcriptions
+/// françaises des noms maghrébins ("Mhamed", "Mohamed", "Chedly", "Ben Salah").
+/// Renvoie (code primaire, code alternatif) :
+/// - voyelles brèves (a, e, o) ignorées ; "ou"/"u" et "i"/"y" longs gardés
+///   en W et Y dans le code primaire seulement ;
+/// - ch/sh → X, dh → D, th → T, dj → J, kh → K (alt. H), gh/rh → G (alt. R),
+///   g → G (alt. K, le ڨ tunisien), z → Z (alt. S), h final omis en alternatif (ة) ;
+/// - chiffres arabizi : 7 → H, 9 → K, 5 → K, 3 et 2 ignorés.
+///
+/// Un nom en arabe est d’abord transcrit par `arabic_to_latin`, ce qui permet
+/// de comparer les deux écritures.
+pub fn maghrebi_metaphone(name: &str) -> (String, String) {
+    let latin = if is_latin(name) {
+        strip_latin_prefix(&fold_latin(name)).to_string()
+    } else {
+        let arabic = normalize_arabic_letters(name.trim());
+        let arabic = arabic.strip_prefix("ال").filter(|rest| !rest.is_empty()).unwrap_or(&arabic);
+        arabic_to_latin(arabic)
+    };
+    let chars: Vec<char> = latin.chars().filter(|c| c.is_alphanumeric()).collect();
+
+    let mut primary = String::new();
+    let mut alternate = String::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let next = chars.get(i + 1).copied();
+        let prev = if i > 0 { Some(chars[i - 1]) } else { None };
+        let last = i + 1 == chars.len();
+        // (primary, alternate, chars used); '\0' = nothing
+        let (p, a, used) = match (c, next) {
+            ('y', n) if i == 0 && is_latin_vowel(n) => ('Y', 'Y', 1),
+            ('a' | 'e' | 'i' | 'o' | 'u' | 'y', _) if i == 0 => {
+                let used = if matches!((c, next), ('o', Some('u')) | ('a', Some('i' | 'y'))) { 2 } else { 1 };
+                ('A', 'A', used)
+            }
+            ('o', Some('u' | 'o')) => {
+                // "ou" before a vowel is the consonant و (Ouali, Saoudi)
+                if is_latin_vowel(chars.get(i + 2).copied()) { ('W', 'W', 2) } else { ('W', '\0', 2) }
+            }
+            ('u', _) => ('W', '\0', 1),
+            // "y" between a consonant and a vowel is the consonant ي
+            ('y', n) if !is_latin_vowel(prev) && is_latin_vowel(n) => ('Y', 'Y', 1),
+            ('e', Some('e')) | ('i', Some('e')) => ('Y', '\0', 2),
+            ('i' | 'y', _) => ('Y', '\0', 1),
+            ('a' | 'e' | 'o', _) => ('\0', '\0', 1),
+            ('t', Some('c')) if chars.get(i + 2) == Some(&'h') => ('X', 'X', 3),
+            ('c' | 's', Some('h')) => ('X', 'X', 2),
+            ('d', Some('h')) => ('D', 'D', 2),
+            ('t', Some('h')) => ('T', 'T', 2),
+            ('p', Some('h')) => ('F', 'F', 2),
+            ('d', Some('j')) => ('J', 'J', 2),
+            ('k', Some('h')) => ('K', 'H', 2),
+            ('g', Some('h')) => ('G', 'R', 2),
+            ('r', Some('h')) => ('G', 'R', 2),
+            ('c', Some('k')) => ('K', 'K', 2),
+            ('c', Some('e' | 'i' | 'y')) => ('S', 'S', 1),
+            ('g', Some('e' | 'i')) => ('J', 'G', 1),
+            ('b' | 'p', _) => ('B', 'B', 1),
+            ('f' | 'v', _) => ('F', 'F', 1),
+            ('t', _) => ('T', 'T', 1),
+            ('d', _) => ('D', 'D', 1),
+            ('c' | 'k' | 'q' | '9' | '5', _) => ('K', 'K', 1),
+            ('g', _) => ('G', 'K', 1),
+            ('s', _) => ('S', 'S', 1),
+            ('z', _) => ('Z', 'S', 1),
+            ('h', _) if last => ('H', '\0', 1),
+            ('h' | '7', _) => ('H', 'H', 1),
+            ('j', _) => ('J', 'J', 1),
+            ('l', _) => ('L', 'L', 1),
+            ('m', _) => ('M', 'M', 1),
+            ('n', _) => ('N', 'N', 1),
+            ('r', _) => ('R', 'R', 1),
+            ('w', _) => ('W', 'W', 1),
+            ('x', _) => {
+                push_code(&mut primary, 'K');
+                push_code(&mut alternate, 'K');
+                ('S', 'S', 1)
+            }
+            _ => ('\0', '\0', 1),
+        };
+        if p != '\0' {
+            push_code(&mut primary, p);
+        }
+        if a != '\0' {
+            push_code(&mut alternate, a);
+        }
+        i += used;
+    }
+    (primary, alternate)
+}
+
+/// Phonetic agreement of two names by `maghrebi_metaphone`:
+/// 1.0 when the primary codes are equal, 0.5 when only an alternate code
+/// matches, 0.0 otherwise.
+pub fn metaphone_similarity(name1: &str, name2: &str) -> f64 {
+    metaphone_codes_similarity(&maghrebi_metaphone(name1), &maghrebi_metaphone(name2))
+}
+
+/// `metaphone_similarity` of two `maghrebi_metaphone` results.
+fn metaphone_codes_similarity((p1, a1): &(String, String), (p2, a2): &(String, String)) -> f64 {
+    if p1.is_empty() || p2.is_empty() {
+        0.0
+    } else if p1 == p2 {
+        1.0
+    } else if p1 == a2 || a1 == p2 || (!a1.is_empty() && a1 == a2) {
+        0.5
+    } else {
+        0.0
+    }
+}
+
+/// Phonetic agreement used by the scorers: Aramix Soundex equality (0 or 1)
+/// for two Arabic names, `metaphone_similarity` as soon as either is Latin.
+pub fn phonetic_similarity(name1: &str, name2: &str) -> f64 {
+    if is_latin(name1) || is_latin(name2) {
+        metaphone_similarity(name1, name2)
+    } else {
+        (aramix_soundex(name1) == aramix_soundex(name2)) as u8 as f64
+    }
+}
+
+/// 🔊 Phonetic codes of a normalized name, computed once when a record is
+/// loaded or a request normalized so scoring does not recode the same names
+/// for every comparison.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PhoneticKey {
+    pub latin:     bool,
+    pub soundex:   String,
+    pub metaphone: (String, String),
+}
+
+impl PhoneticKey {
+    pub fn of(norm_name: &str) -> Self {
+        PhoneticKey {
+            latin:     is_latin(norm_name),
+            soundex:   aramix_soundex(norm_name),
+            metaphone: maghrebi_metaphone(norm_name),
+        }
+    }
+}
+
+/// `phonetic_similarity` of the two names `a` and `b` are the keys of.
+pub fn phonetic_key_similarity(a: &PhoneticKey, b: &PhoneticKey) -> f64 {
+    if a.latin || b.latin {
+        metaphone_codes_similarity(&a.metaphone, &b.metaphone)
+    } else {
+        (a.soundex == b.soundex) as u8 as f64
+    }
+}
+
+/// `PhoneticKey` of each name field of a record or request.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NameKeys {
+    pub first_name:       PhoneticKey,
+    pub last_name:        PhoneticKey,
+    pub father_name:      PhoneticKey,
+    pub grandfather_name: PhoneticKey,
+    pub mother_last_name: PhoneticKey,
+    pub mother_name:      PhoneticKey,
+}
+
+impl NameKeys {
+    /// Keys of the normalized first, last, father, grandfather, mother's
+    /// last and mother's names, in that order.
+    pub fn of(norm_names: [&str; 6]) -> Self {
+        let [first, last, father, grandfather, mother_last, mother] = norm_names.map(PhoneticKey::of);
+        NameKeys {
+            first_name:       first,
+            last_name:        last,
+            father_name:      father,
+            grandfather_name: grandfather,
+            mother_last_name: mother_last,
+            mother_name:      mother,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn codes(primary: &str, alternate: &str) -> (String, String) {
+        (primary.to_string(), alternate.to_string())
+    }
+
+    #[test]
+    fn metaphone_codes_of_french_transcriptions() {
+        assert_eq!(maghrebi_metaphone("Mohamed"), codes("MHMD", "MHMD"));
+        assert_eq!(maghrebi_metaphone("Mhamed"), codes("MHMD", "MHMD"));
+        assert_eq!(maghrebi_metaphone("Chedly"), codes("XDLY", "XDL"));
+        assert_eq!(maghrebi_metaphone("Ben Salah"), codes("SLH", "SL"));
+        assert_eq!(maghrebi_metaphone("Khaled"), codes("KLD", "HLD"));
+        assert_eq!(maghrebi_metaphone("Gharbi"), codes("GRBY", "RB"));
+        assert_eq!(maghrebi_metaphone("Gasmi"), codes("GSMY", "KSM"));
+        assert_eq!(maghrebi_metaphone("Djamel"), codes("JML", "JML"));
+        assert_eq!(maghrebi_metaphone("Thabet"), codes("TBT", "TBT"));
+        assert_eq!(maghrebi_metaphone("Fatmah"), codes("FTMH", "FTM"));
+    }
+
+    #[test]
+    fn metaphone_reads_arabizi_digits_and_arabic_script() {
+        assert_eq!(maghrebi_metaphone("7amza"), maghrebi_metaphone("Hamza"));
+        assert_eq!(maghrebi_metaphone("محمد"), codes("MHMD", "MHMD"));
+        assert_eq!(maghrebi_metaphone("الشاذلي"), maghrebi_metaphone("Chedly"));
+    }
+
+    #[test]
+    fn metaphone_similarity_grades_primary_and_alternate_matches() {
+        assert_eq!(metaphone_similarity("Mohamed", "Mhamed"), 1.0);
+        assert_eq!(metaphone_similarity("Gharbi", "Rharbi"), 1.0);
+        assert_eq!(metaphone_similarity("Fatma", "Fatmah"), 0.5);
+        assert_eq!(metaphone_similarity("Mohamed", "Ali"), 0.0);
+    }
+
+    #[test]
+    fn aramix_soundex_codes() {
+        assert_eq!(aramix_soundex("محمد"), "م79");
+        assert_eq!(aramix_soundex("Mohamed"), "م79");
+        assert_eq!(aramix_soundex("خالد"), aramix_soundex("Khaled"));
+    }
+}
@@ -0,0 +1,388 @@
+// src/utils/pipeline.rs
+
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use crate::utils::classifier::MatchModel;
+use crate::utils::dob::BirthDate;
+use crate::utils::gold_set::GoldSetIdentity;
+use crate::utils::linked_list::IdentityNode;
+use crate::utils::matching::{
+    explain_match, passes_demographic_filter, should_consider_candidate, CandidateDetails, ScoreExplanation,
+};
+use crate::utils::normalization::normalize_name;
+use crate::utils::phonetic::NameKeys;
+use crate::utils::scoring_profile::ScoringProfile;
+
+/// Identity to match, as sent to `/match` or typed into `main_cli`.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct InputIdentity {
+    /// Registry ID when the input is itself a registry record (deduplication,
+    /// gold-set pairs)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id:               Option<String>,
+    pub first_name:       String,
+    pub last_name:        String,
+    pub father_name:      String,
+    pub grandfather_name: String,
+    pub mother_last_name: String,
+    pub mother_name:      String,
+    pub dob:              Option<BirthDate>,
+    pub sex:              u8,
+    pub place_of_birth:   String,
+}
+
+/// `InputIdentity` with every text field passed through `normalize_name`,
+/// i.e. in the same form the loader stores registry records.
+#[derive(Debug, Clone)]
+pub struct NormalizedInput {
+    pub first_name:       String,
+    pub last_name:        String,
+    pub father_name:      String,
+    pub grandfather_name: String,
+    pub mother_last_name: String,
+    pub mother_name:      String,
+    pub dob:              Option<BirthDate>,
+    pub sex:              u8,
+    pub place_of_birth:   String,
+    /// Phonetic codes of the six names
+    pub name_keys:        NameKeys,
+}
+
+impl InputIdentity {
+    /// Normalize input strings once, before pre-filtering and scoring.
+    pub fn normalized(&self) -> NormalizedInput {
+        let first_name       = normalize_name(&self.first_name);
+        let last_name        = normalize_name(&self.last_name);
+        let father_name      = normalize_name(&self.father_name);
+        let grandfather_name = normalize_name(&self.grandfather_name);
+        let mother_last_name = normalize_name(&self.mother_last_name);
+        let mother_name      = normalize_name(&self.mother_name);
+        let name_keys = NameKeys::of([&first_name, &last_name, &father_name, &grandfather_name, &mother_last_name, &mother_name]);
+        NormalizedInput {
+            first_name,
+            last_name,
+            father_name,
+            grandfather_name,
+            mother_last_name,
+            mother_name,
+            dob:              self.dob,
+            sex:              self.sex,
+            place_of_birth:   normalize_name(&self.place_of_birth),
+            name_keys,
+        }
+    }
+}
+
+impl From<&GoldSetIdentity> for InputIdentity {
+    fn from(identity: &GoldSetIdentity) -> Self {
+        InputIdentity {
+            id:               Some(identity.id.clone()),
+            first_name:       identity.first_name.clone(),
+            last_name:        identity.last_name.clone(),
+            father_name:      identity.father_name.clone(),
+            grandfather_name: identity.grandfather_name.clone(),
+            mother_last_name: identity.mother_last_name.clone(),
+            mother_name:      identity.mother_name.clone(),
+            dob:              identity.dob.map(|(d, m, y)| BirthDate::Tuple(d, m, y)),
+            sex:              identity.sex,
+            place_of_birth:   identity.place_of_birth.clone(),
+        }
+    }
+}
+
+impl NormalizedInput {
+    /// Fields in the shape `matching::should_consider_candidate` expects.
+    /// Year ranges and age estimates have no single date and are passed as `None`.
+    pub fn as_details(&self) -> CandidateDetails<'_> {
+        (
+            &self.first_name,
+            &self.last_name,
+            &self.father_name,
+            &self.grandfather_name,
+            &self.mother_last_name,
+            &self.mother_name,
+            self.dob.and_then(|d| d.as_tuple()),
+            self.sex,
+            &self.place_of_birth,
+        )
+    }
+}
+
+/// Serde-friendly copy of an `IdentityNode` for output.
+#[derive(Debug, Clone, Serialize)]
+pub struct IdentityRecord {
+    /// `IdentityNode::id`: the registry row this is
+    pub id:               String,
+    pub first_name:       String,
+    pub last_name:        String,
+    pub father_name:      String,
+    pub grandfather_name: String,
+    pub mother_last_name: String,
+    pub mother_name:      String,
+    pub dob:              (u32, u32, u32),
+    pub sex:              u8,
+    pub place_of_birth:   String,
+}
+
+impl From<&IdentityNode> for IdentityRecord {
+    fn from(node: &IdentityNode) -> Self {
+        IdentityRecord {
+            id:               node.id.clone(),
+            first_name:       node.first_name.clone(),
+            last_name:        node.last_name.clone(),
+            father_name:      node.father_name.clone(),
+            grandfather_name: node.grandfather_name.clone(),
+            mother_last_name: node.mother_last_name.clone(),
+            mother_name:      node.mother_name.clone(),
+            dob:              node.dob.unwrap_or((0, 0, 0)),
+            sex:              node.sex,
+            place_of_birth:   node.place_of_birth.clone(),
+        }
+    }
+}
+
+/// A scored candidate. The explanation is flattened so the JSON keeps
+/// `matched_identity`, `total_score` and `breakdown` at the top level.
+#[derive(Debug, Clone, Serialize)]
+pub struct MatchResult {
+    pub matched_identity: IdentityRecord,
+    #[serde(flatten)]
+    pub explanation:      ScoreExplanation,
+    /// Set when returned only because `include_below_threshold` was asked for
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub below_threshold:  bool,
+    /// Classifier probability (0–1) when ranked with a `MatchModel`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub match_probability: Option<f64>,
+}
+
+impl MatchResult {
+    pub fn total_score(&self) -> f64 {
+        self.explanation.total_score
+    }
+
+    /// What results are ranked and thresholded on, in percent: the match
+    /// probability when there is one, the profile's total otherwise (see
+    /// `MatchOptions::threshold`).
+    pub fn ranking_score(&self) -> f64 {
+        self.match_probability.map(|p| p * 100.0).unwrap_or(self.total_score())
+    }
+}
+
+/// Candidates passing `should_consider_candidate` for `input`.
+/// For a year range or an age estimate the year window is applied around the whole range.
+pub fn prefilter<'a>(
+    input: &NormalizedInput,
+    records: impl IntoIterator<Item = &'a IdentityNode>,
+    year_tolerance: u32,
+) -> Vec<&'a IdentityNode> {
+    let input_details = input.as_details();
+    records
+        .into_iter()
+        .filter(|node| should_consider_candidate(&input_details, &node.as_details(), year_tolerance))
+        .filter(|node| within_year_range(input, node, year_tolerance))
+        .collect()
+}
+
+/// Like `prefilter` without the last-name Soundex check, for candidates
+/// coming out of a `blocking::BlockingIndex`.
+pub fn demographic_filter<'a>(
+    input: &NormalizedInput,
+    records: impl IntoIterator<Item = &'a IdentityNode>,
+    year_tolerance: u32,
+) -> Vec<&'a IdentityNode> {
+    let input_details = input.as_details();
+    records
+        .into_iter()
+        .filter(|node| passes_demographic_filter(&input_details, &node.as_details(), year_tolerance))
+        .filter(|node| within_year_range(input, node, year_tolerance))
+        .collect()
+}
+
+/// Year window around a year range or age estimate (single dates are
+/// handled by `should_consider_candidate`).
+fn within_year_range(input: &NormalizedInput, node: &IdentityNode, year_tolerance: u32) -> bool {
+    let range = input
+        .dob
+        .filter(|d| d.as_tuple().is_none())
+        .map(|d| d.year_range());
+    match (range, node.dob) {
+        (Some((from, to)), Some((_, _, y))) => y.saturating_add(year_tolerance) >= from && y <= to.saturating_add(year_tolerance),
+        _ => true,
+    }
+}
+
+/// Score `candidates` in parallel with `profile`, best first.
+/// Used by both the HTTP API and `main_cli` so they report identical explanations.
+pub fn rank_candidates(
+    profile: &ScoringProfile,
+    input: &NormalizedInput,
+    candidates: &[&IdentityNode],
+) -> Vec<MatchResult> {
+    rank_candidates_with_model(profile, None, input, candidates)
+}
+
+/// `rank_candidates`, ranked on the match probability of `model` when given;
+/// the profile's explanation is kept either way.
+pub fn rank_candidates_with_model(
+    profile: &ScoringProfile,
+    model: Option<&MatchModel>,
+    input: &NormalizedInput,
+    candidates: &[&IdentityNode],
+) -> Vec<MatchResult> {
+    let mut results: Vec<MatchResult> = candidates
+        .par_iter()
+        .map(|node| MatchResult {
+            matched_identity:  IdentityRecord::from(*node),
+            explanation:       explain_match(profile, input, node),
+            below_threshold:   false,
+            match_probability: model.map(|m| m.match_probability(input, node)),
+        })
+        .collect();
+
+    // Sort by descending ranking score so the first entry is the highest match
+    results.sort_unstable_by(|a, b| b.ranking_score().total_cmp(&a.ranking_score()));
+    results
+}
+
+/// Threshold and result count requested by a caller; unset fields fall back
+/// to the server's `MatchLimits`.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct MatchParams {
+    pub min_score:               Option<f64>,
+    /// Threshold on the match probability, in percent, when ranking with a
+    /// `MatchModel`; `min_score` is a cutoff on the profile's total instead
+    pub min_probability:         Option<f64>,
+    pub top_k:                   Option<usize>,
+    pub include_below_threshold: Option<bool>,
+}
+
+impl MatchParams {
+    /// `self` where set, `other` otherwise.
+    pub fn or(self, other: MatchParams) -> MatchParams {
+        MatchParams {
+            min_score:               self.min_score.or(other.min_score),
+            min_probability:         self.min_probability.or(other.min_probability),
+            top_k:                   self.top_k.or(other.top_k),
+            include_below_threshold: self.include_below_threshold.or(other.include_below_threshold),
+        }
+    }
+}
+
+/// Server-side defaults and caps for `MatchParams`, from the environment:
+/// MATCH_MIN_SCORE (default 75), MATCH_MIN_PROBABILITY (default 50),
+/// MATCH_TOP_K (default 3), MATCH_MAX_TOP_K (default 50) and
+/// MATCH_MIN_SCORE_FLOOR (default 0), the lowest `min_score` a caller may ask for.
+#[derive(Debug, Clone)]
+pub struct MatchLimits {
+    pub default_min_score:       f64,
+    pub default_min_probability: f64,
+    pub default_top_k:           usize,
+    pub max_top_k:               usize,
+    pub min_score_floor:         f64,
+}
+
+impl Default for MatchLimits {
+    fn default() -> Self {
+        MatchLimits {
+            default_min_score:       75.0,
+            default_min_probability: 50.0,
+            default_top_k:           3,
+            max_top_k:               50,
+            min_score_floor:         0.0,
+        }
+    }
+}
+
+impl MatchLimits {
+    /// Reads the limits; a variable that does not parse or a threshold
+    /// outside 0–100 is an error rather than a silent default.
+    pub fn from_env() -> Result<Self, String> {
+        fn var<T: std::str::FromStr>(name: &str, default: T) -> Result<T, String> {
+            match std::env::var(name) {
+                Ok(v) => v.trim().parse().map_err(|_| format!("{}={:?} is not a valid value", name, v)),
+                Err(_) => Ok(default),
+            }
+        }
+        let defaults = Self::default();
+        let limits = MatchLimits {
+            default_min_score:       var("MATCH_MIN_SCORE", defaults.default_min_score)?,
+            default_min_probability: var("MATCH_MIN_PROBABILITY", defaults.default_min_probability)?,
+            default_top_k:           var("MATCH_TOP_K", defaults.default_top_k)?,
+            max_top_k:               var("MATCH_MAX_TOP_K", defaults.max_top_k)?,
+            min_score_floor:         var("MATCH_MIN_SCORE_FLOOR", defaults.min_score_floor)?,
+        };
+        limits.validate()?;
+        Ok(limits)
+    }
+
+    /// Thresholds must be percentages; `resolve` clamps between them.
+    pub fn validate(&self) -> Result<(), String> {
+        for (name, value) in [
+            ("MATCH_MIN_SCORE", self.default_min_score),
+            ("MATCH_MIN_PROBABILITY", self.default_min_probability),
+            ("MATCH_MIN_SCORE_FLOOR", self.min_score_floor),
+        ] {
+            if !(0.0..=100.0).contains(&value) {
+                return Err(format!("{} {} is not a percentage", name, value));
+            }
+        }
+        Ok(())
+    }
+
+    /// Fill in defaults and clamp to the caps; a `min_score` or
+    /// `min_probability` that is not a finite number is an error.
+    pub fn resolve(&self, params: &MatchParams) -> Result<MatchOptions, String> {
+        let min_score = params.min_score.unwrap_or(self.default_min_score);
+        let min_probability = params.min_probability.unwrap_or(self.default_min_probability);
+        for (name, value) in [("min_score", min_score), ("min_probability", min_probability)] {
+            if !value.is_finite() {
+                return Err(format!("{} {} is not a number", name, value));
+            }
+        }
+        let top_k = params.top_k.unwrap_or(self.default_top_k);
+        Ok(MatchOptions {
+            min_score:               min_score.clamp(self.min_score_floor, 100.0),
+            min_probability:         min_probability.clamp(0.0, 100.0),
+            top_k:                   top_k.clamp(1, self.max_top_k.max(1)),
+            include_below_threshold: params.include_below_threshold.unwrap_or(false),
+        })
+    }
+}
+
+/// Effective threshold and result count of one request.
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct MatchOptions {
+    pub min_score:               f64,
+    pub min_probability:         f64,
+    pub top_k:                   usize,
+    pub include_below_threshold: bool,
+}
+
+impl MatchOptions {
+    /// The threshold `result.ranking_score()` is held to: `min_probability`
+    /// for a result with a match probability, `min_score` otherwise.
+    pub fn threshold(&self, result: &MatchResult) -> f64 {
+        if result.match_probability.is_some() { self.min_probability } else { self.min_score }
+    }
+}
+
+/// Keep the best `top_k` of ranked `results` reaching their `threshold`;
+/// with `include_below_threshold` the best `top_k` are kept anyway and the
+/// ones under the threshold flagged `below_threshold`.
+pub fn select_matches(results: Vec<MatchResult>, options: &MatchOptions) -> Vec<MatchResult> {
+    results
+        .into_iter()
+        .filter_map(|mut r| {
+            if r.ranking_score() >= options.threshold(&r) {
+                Some(r)
+            } else if options.include_below_threshold {
+                r.below_threshold = true;
+                Some(r)
+            } else {
+                None
+            }
+        })
+        .take(options.top_k)
+        .collect()
+}
@@ -0,0 +1,344 @@
+// src/utils/registry_file.rs
+
+//! Registry extracts read from files instead of PostgreSQL, for matching on
+//! a machine without access to the database. The columns are looked up by
+//! the names of a `ColumnMapping`, by default those of `tunisian_citizens`.
+
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader};
+use std::path::Path;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use crate::utils::linked_list::IdentityNode;
+use crate::utils::loader::{merge_same_ids, registry_id_column, RegistryRow};
+
+/// Column (CSV) or key (JSONL) holding each field of a registry row.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ColumnMapping {
+    /// Registry key; REGISTRY_ID_COLUMN when unset, else records get
+    /// `hashed_identity_id`
+    pub id:               Option<String>,
+    pub first_name:       String,
+    pub last_name:        String,
+    pub father_name:      String,
+    pub grandfather_name: String,
+    pub mother_last_name: String,
+    pub mother_name:      String,
+    pub birth_day:        String,
+    pub birth_month:      String,
+    pub birth_year:       String,
+    pub sex:              String,
+    pub place_of_birth:   String,
+}
+
+impl Default for ColumnMapping {
+    fn default() -> Self {
+        ColumnMapping {
+            id:               None,
+            first_name:       "الاسم".to_string(),
+            last_name:        "اسم_العائلة".to_string(),
+            father_name:      "اسم_الأب".to_string(),
+            grandfather_name: "اسم_الجد".to_string(),
+            mother_last_name: "اسم_عائلة_الأم".to_string(),
+            mother_name:      "اسم_الأم".to_string(),
+            birth_day:        "يوم_الميلاد".to_string(),
+            birth_month:      "شهر_الميلاد".to_string(),
+            birth_year:       "سنة_الميلاد".to_string(),
+            sex:              "الجنس".to_string(),
+            place_of_birth:   "مكان_الولادة".to_string(),
+        }
+    }
+}
+
+impl ColumnMapping {
+    /// Loads a column mapping from a .toml or .json file; unset fields keep
+    /// the table's column names.
+    pub fn load(file_path: &str) -> io::Result<Self> {
+        let path = Path::new(file_path);
+        let extension = path.extension().and_then(|ext| ext.to_str()).unwrap_or("");
+        let contents = fs::read_to_string(path)?;
+
+        match extension.to_lowercase().as_str() {
+            "toml" => toml::from_str(&contents)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string())),
+            "json" => Ok(serde_json::from_str(&contents)?),
+            _ => Err(io::Error::new(io::ErrorKind::InvalidInput, "Unsupported file format")),
+        }
+    }
+
+    /// Loads the mapping from `REGISTRY_COLUMNS` if set, otherwise the table's column names.
+    pub fn from_env() -> io::Result<Self> {
+        match std::env::var("REGISTRY_COLUMNS") {
+            Ok(path) if !path.is_empty() => Self::load(&path),
+            _ => Ok(Self::default()),
+        }
+    }
+
+    /// Every column the mapping names: the fields and `id` when set (not
+    /// REGISTRY_ID_COLUMN, a database setting an extract may not follow).
+    fn mapped_columns(&self) -> Vec<&str> {
+        let mut columns = vec![
+            self.first_name.as_str(),
+            &self.last_name,
+            &self.father_name,
+            &self.grandfather_name,
+            &self.mother_last_name,
+            &self.mother_name,
+            &self.birth_day,
+            &self.birth_month,
+            &self.birth_year,
+            &self.sex,
+            &self.place_of_birth,
+        ];
+        columns.extend(self.id.as_deref());
+        columns
+    }
+
+    /// Build a row from `value`, which returns the text of a column by name.
+    /// Rows without a first or last name are skipped; a birth date needs all
+    /// three of day, month and year, otherwise it is unknown.
+    fn row(&self, id_column: Option<&str>, value: impl Fn(&str) -> Option<String>) -> Option<RegistryRow> {
+        let text = |column: &str| value(column).unwrap_or_default();
+        let number = |column: &str| value(column).and_then(|v| v.trim().parse::<u32>().ok());
+
+        let first_name = text(&self.first_name);
+        let last_name = text(&self.last_name);
+        if first_name.trim().is_empty() || last_name.trim().is_empty() {
+            return None;
+        }
+        let dob = match (number(&self.birth_day), number(&self.birth_month), number(&self.birth_year)) {
+            (Some(day), Some(month), Some(year)) => Some((day, month, year)),
+            _ => None,
+        };
+
+        Some(RegistryRow {
+            registry_id:      id_column.and_then(&value).filter(|id| !id.is_empty()),
+            first_name,
+            last_name,
+            father_name:      text(&self.father_name),
+            grandfather_name: text(&self.grandfather_name),
+            mother_last_name: text(&self.mother_last_name),
+            mother_name:      text(&self.mother_name),
+            dob,
+            sex:              text(&self.sex),
+            place_of_birth:   text(&self.place_of_birth),
+        })
+    }
+}
+
+/// Whether `file_path` is a `.csv` or `.jsonl`/`.ndjson` extract that
+/// `load_registry_file` reads (anything else is taken for a snapshot).
+pub fn is_registry_extract(file_path: &str) -> bool {
+    let extension = Path::new(file_path).extension().and_then(|ext| ext.to_str()).unwrap_or("");
+    matches!(extension.to_lowercase().as_str(), "csv" | "jsonl" | "ndjson")
+}
+
+/// Read and normalize a registry extract, merged like the table with
+/// `merge_same_ids`. A CSV file needs a header row with every mapped column;
+/// a JSONL file holds one object per line, its values strings or numbers,
+/// and the mapped keys missing from its first object are warned about.
+/// A REGISTRY_ID_COLUMN the file does not have gives hashed IDs.
+pub fn load_registry_file(file_path: &str, columns: &ColumnMapping) -> io::Result<Vec<IdentityNode>> {
+    let extension = Path::new(file_path).extension().and_then(|ext| ext.to_str()).unwrap_or("");
+    let id_column = columns.id.clone().or_else(registry_id_column);
+    let id_column = id_column.as_deref();
+    let mut read = 0;
+
+    let rows: Vec<RegistryRow> = match extension.to_lowercase().as_str() {
+        "csv" => {
+            let mut reader = csv::ReaderBuilder::new().has_headers(true).from_path(file_path)?;
+            let headers: HashMap<String, usize> = reader
+                .headers()?
+                .iter()
+                .enumerate()
+                .map(|(i, name)| (name.trim().trim_start_matches('\u{feff}').to_string(), i))
+                .collect();
+            let missing: Vec<&str> = columns
+                .mapped_columns()
+                .into_iter()
+                .filter(|column| !headers.contains_key(*column))
+                .collect();
+            if !missing.is_empty() {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("{} has no column {:?}; map them with --columns or REGISTRY_COLUMNS", file_path, missing),
+                ));
+            }
+
+            let mut rows = Vec::new();
+            for (i, record) in reader.records().enumerate() {
+                let record = record.map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("Row {}: {}", i + 1, e)))?;
+                read += 1;
+                let value = |column: &str| headers.get(column).and_then(|&i| record.get(i)).map(str::to_string);
+                rows.extend(columns.row(id_column, value));
+            }
+            rows
+        }
+        "jsonl" | "ndjson" => {
+            let mut rows = Vec::new();
+            for (i, line) in BufReader::new(File::open(file_path)?).lines().enumerate() {
+                let line = line?;
+                if line.trim().is_empty() {
+                    continue;
+                }
+                let object: HashMap<String, Value> = serde_json::from_str(&line)
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("Line {}: {}", i + 1, e)))?;
+                if read == 0 {
+                    let missing: Vec<&str> = columns
+                        .mapped_columns()
+                        .into_iter()
+                        .filter(|column| !object.contains_key(*column))
+                        .collect();
+                    if !missing.is_empty() {
+                        println!(
+                            "⚠️  The first row of {} has no key {:?}; map them with --columns or REGISTRY_COLUMNS",
+                            file_path, missing
+                        );
+                    }
+                }
+                read += 1;
+                let value = |column: &str| match object.get(column)? {
+                    Value::Null => None,
+                    Value::String(text) => Some(text.clone()),
+                    other => Some(other.to_string()),
+                };
+                rows.extend(columns.row(id_column, value));
+            }
+            rows
+        }
+        _ => return Err(io::Error::new(io::ErrorKind::InvalidInput, "Unsupported file format")),
+    };
+
+    println!("✅ {} rows in {}", read, file_path);
+    if rows.len() < read {
+        println!("⚠️  {} rows without a first or last name skipped", read - rows.len());
+    }
+
+    let unique = merge_same_ids(rows.into_iter().map(RegistryRow::into_identity).collect());
+    println!("✅ {} distinct identities after merging", unique.len());
+    Ok(unique)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+    use crate::utils::normalization::normalize_name;
+
+    /// Removes the file when dropped, so a failed assertion does not leave it behind
+    struct TempFile(PathBuf);
+
+    impl TempFile {
+        fn new(name: &str, contents: &str) -> Self {
+            let path = std::env::temp_dir().join(format!("registry-{}-{}", std::process::id(), name));
+            fs::write(&path, contents).unwrap();
+            TempFile(path)
+        }
+
+        fn path(&self) -> &str {
+            self.0.to_str().unwrap()
+        }
+    }
+
+    impl Drop for TempFile {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.0);
+        }
+    }
+
+    fn mapping() -> ColumnMapping {
+        ColumnMapping {
+            id:               Some("nid".to_string()),
+            first_name:       "first".to_string(),
+            last_name:        "last".to_string(),
+            father_name:      "father".to_string(),
+            grandfather_name: "grandfather".to_string(),
+            mother_last_name: "mother_last".to_string(),
+            mother_name:      "mother".to_string(),
+            birth_day:        "day".to_string(),
+            birth_month:      "month".to_string(),
+            birth_year:       "year".to_string(),
+            sex:              "sex".to_string(),
+            place_of_birth:   "place".to_string(),
+        }
+    }
+
+    const HEADER: &str = "nid,first,last,father,grandfather,mother_last,mother,day,month,year,sex,place";
+
+    #[test]
+    fn mapping_file_keeps_table_names_for_unset_fields() {
+        let file = TempFile::new("columns.toml", "id = \"nid\"\nfirst_name = \"first\"\n");
+        let columns = ColumnMapping::load(file.path()).unwrap();
+        assert_eq!(columns.id.as_deref(), Some("nid"));
+        assert_eq!(columns.first_name, "first");
+        assert_eq!(columns.last_name, "اسم_العائلة");
+    }
+
+    #[test]
+    fn reads_csv_rows_through_the_mapping() {
+        let file = TempFile::new(
+            "mapped.csv",
+            &format!(
+                "{}\n07,أحمد,الطرابلسي,محمد,صالح,,,15,6,1985,1,تونس\n08,سلمى,الحسني,علي,,,,,6,1990,2,\n09,يوسف,,,,,,,,,1,\n",
+                HEADER
+            ),
+        );
+        let mut records = load_registry_file(file.path(), &mapping()).unwrap();
+        records.sort_by(|a, b| a.id.cmp(&b.id));
+
+        // The row without a last name is skipped
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].id, "07");
+        assert_eq!(records[0].last_name, normalize_name("الطرابلسي"));
+        assert_eq!(records[0].dob, Some((15, 6, 1985)));
+        assert_eq!(records[0].sex, 1);
+        // No birth day: the date is unknown
+        assert_eq!(records[1].id, "08");
+        assert_eq!(records[1].dob, None);
+        assert_eq!(records[1].sex, 2);
+    }
+
+    #[test]
+    fn csv_without_a_mapped_column_is_an_error() {
+        let file = TempFile::new("no-sex.csv", "nid,first,last,father,grandfather,mother_last,mother,day,month,year,place\n");
+        let error = load_registry_file(file.path(), &mapping()).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert!(error.to_string().contains("[\"sex\"]"), "{}", error);
+
+        // The ID column is only required when the mapping names it
+        let file = TempFile::new("no-id.csv", &format!("{}\n", HEADER.trim_start_matches("nid,")));
+        let without_id = ColumnMapping { id: None, ..mapping() };
+        assert!(load_registry_file(file.path(), &without_id).is_ok());
+        let error = load_registry_file(file.path(), &mapping()).unwrap_err();
+        assert!(error.to_string().contains("[\"nid\"]"), "{}", error);
+    }
+
+    #[test]
+    fn reads_jsonl_numbers_and_missing_keys() {
+        let file = TempFile::new(
+            "mapped.jsonl",
+            concat!(
+                "{\"nid\": 7, \"first\": \"أحمد\", \"last\": \"الطرابلسي\", \"day\": 15, \"month\": 6, \"year\": 1985, \"sex\": 1}\n",
+                "\n",
+                "{\"nid\": \"8\", \"first\": \"سلمى\", \"last\": \"الحسني\", \"sex\": \"2\"}\n",
+            ),
+        );
+        let mut records = load_registry_file(file.path(), &mapping()).unwrap();
+        records.sort_by(|a, b| a.id.cmp(&b.id));
+
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].id, "7");
+        assert_eq!(records[0].dob, Some((15, 6, 1985)));
+        assert_eq!(records[0].father_name, "");
+        assert_eq!(records[1].id, "8");
+        assert_eq!(records[1].sex, 2);
+    }
+
+    #[test]
+    fn rejects_other_extensions() {
+        let error = load_registry_file("registry.xlsx", &mapping()).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+    }
+}
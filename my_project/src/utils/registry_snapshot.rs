@@ -0,0 +1,71 @@
+// src/utils/registry_snapshot.rs
+
+//! Binary snapshot files of the indexed registry, so the server and the CLI
+//! can start without reading and normalizing the whole table.
+//!
+//! Layout: the `MAGIC` bytes, the schema version (u32, little endian), the
+//! SHA-256 of the payload, then the bincode payload. A file of another schema
+//! version or whose payload does not match its checksum is rejected; rebuild
+//! it with `main_cli --write-snapshot <file>`.
+
+use std::fs;
+use std::io;
+use std::path::Path;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+const MAGIC: &[u8; 8] = b"IDSNAP\r\n";
+
+/// Bump whenever `IdentityNode`, the blocking keys or the index layout
+/// change: snapshots written before are then stale. The `NORMALIZER_VERSION`
+/// and blocking configuration are stored in the payload and checked by
+/// `StoreSnapshot::load_file`.
+pub const SNAPSHOT_SCHEMA_VERSION: u32 = 5;
+
+const HEADER_LEN: usize = MAGIC.len() + 4 + 32;
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// Write `value` as a snapshot file. The file is written next to `file_path`
+/// and renamed over it, so readers never see half a snapshot.
+pub fn write_snapshot<T: Serialize>(file_path: &str, value: &T) -> io::Result<()> {
+    let payload = bincode::serialize(value).map_err(|e| invalid(e.to_string()))?;
+    let checksum = Sha256::digest(&payload);
+
+    let mut bytes = Vec::with_capacity(HEADER_LEN + payload.len());
+    bytes.extend_from_slice(MAGIC);
+    bytes.extend_from_slice(&SNAPSHOT_SCHEMA_VERSION.to_le_bytes());
+    bytes.extend_from_slice(&checksum);
+    bytes.extend_from_slice(&payload);
+
+    let tmp = format!("{}.tmp", file_path);
+    fs::write(&tmp, bytes)?;
+    fs::rename(&tmp, Path::new(file_path))
+}
+
+/// Read a file written by `write_snapshot` with the current schema version.
+pub fn read_snapshot<T: DeserializeOwned>(file_path: &str) -> io::Result<T> {
+    let bytes = fs::read(file_path)?;
+    if bytes.len() < HEADER_LEN || &bytes[..MAGIC.len()] != MAGIC {
+        return Err(invalid(format!("{} is not a registry snapshot", file_path)));
+    }
+
+    let (version, rest) = bytes[MAGIC.len()..].split_at(4);
+    let version = u32::from_le_bytes(version.try_into().expect("4 bytes"));
+    if version != SNAPSHOT_SCHEMA_VERSION {
+        return Err(invalid(format!(
+            "{} has snapshot schema version {}, expected {}; rebuild it",
+            file_path, version, SNAPSHOT_SCHEMA_VERSION
+        )));
+    }
+
+    let (checksum, payload) = rest.split_at(32);
+    if Sha256::digest(payload).as_slice() != checksum {
+        return Err(invalid(format!("{} is corrupted (checksum mismatch)", file_path)));
+    }
+
+    bincode::deserialize(payload).map_err(|e| invalid(format!("{}: {}", file_path, e)))
+}
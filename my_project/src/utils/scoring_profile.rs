@@ -0,0 +1,300 @@
+// src/utils/scoring_profile.rs
+
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+use serde::{Deserialize, Serialize};
+use crate::utils::matching::DEFAULT_YEAR_TOLERANCE;
+use crate::utils::pipeline::MatchParams;
+use crate::utils::similarity::{default_registry, NameSimilarity};
+
+/// Record fields a scoring profile can weight.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ScoreField {
+    FirstName,
+    LastName,
+    FatherName,
+    GrandfatherName,
+    MotherLastName,
+    MotherName,
+    Dob,
+    PlaceOfBirth,
+    Sex,
+}
+
+impl ScoreField {
+    /// Label shown in the match breakdown.
+    pub fn label(&self) -> &'static str {
+        match self {
+            ScoreField::FirstName       => "الاسم الأول",
+            ScoreField::LastName        => "اسم العائلة",
+            ScoreField::FatherName      => "اسم الأب",
+            ScoreField::GrandfatherName => "اسم الجد",
+            ScoreField::MotherLastName  => "اسم عائلة الأم",
+            ScoreField::MotherName      => "اسم الأم",
+            ScoreField::Dob             => "تاريخ الميلاد",
+            ScoreField::PlaceOfBirth    => "مكان الولادة",
+            ScoreField::Sex             => "الجنس",
+        }
+    }
+}
+
+/// Weight and similarity function for one field. The metric is resolved
+/// when the rule is built or loaded, so an unknown one is rejected there.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(try_from = "FieldRuleSpec", into = "FieldRuleSpec")]
+pub struct FieldRule {
+    pub field: ScoreField,
+    pub weight: f64,
+    /// Metric name or weighted combination, see `similarity::SimilarityRegistry`.
+    pub metric: String,
+    /// Disabled fields neither add to the score nor to the total weight.
+    pub enabled: bool,
+    /// `metric` of a name or place field; DOB and sex are compared directly
+    similarity: Option<Arc<dyn NameSimilarity>>,
+}
+
+/// How a `FieldRule` is written in a profiles file.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+struct FieldRuleSpec {
+    field: ScoreField,
+    weight: f64,
+    #[serde(default = "default_metric")]
+    metric: String,
+    #[serde(default = "default_enabled")]
+    enabled: bool,
+}
+
+impl TryFrom<FieldRuleSpec> for FieldRule {
+    type Error = String;
+
+    fn try_from(spec: FieldRuleSpec) -> Result<Self, String> {
+        FieldRule::new(spec.field, spec.weight, &spec.metric, spec.enabled)
+    }
+}
+
+impl From<FieldRule> for FieldRuleSpec {
+    fn from(rule: FieldRule) -> Self {
+        FieldRuleSpec { field: rule.field, weight: rule.weight, metric: rule.metric, enabled: rule.enabled }
+    }
+}
+
+impl FieldRule {
+    /// A rule whose metric makes sense for its field: `exact` or `graded` for
+    /// the DOB, `exact` for sex, a registered metric or weighted combination
+    /// for the others.
+    pub fn new(field: ScoreField, weight: f64, metric: &str, enabled: bool) -> Result<Self, String> {
+        let similarity = match field {
+            ScoreField::Dob if matches!(metric, "exact" | "graded") => None,
+            ScoreField::Sex if metric == "exact" => None,
+            ScoreField::Dob | ScoreField::Sex => {
+                return Err(format!("metric '{}' is not valid for {:?}", metric, field));
+            }
+            _ => Some(
+                default_registry()
+                    .resolve(metric)
+                    .ok_or_else(|| format!("metric '{}' is not valid for {:?}", metric, field))?,
+            ),
+        };
+        Ok(FieldRule { field, weight, metric: metric.to_string(), enabled, similarity })
+    }
+
+    /// The resolved metric of a name or place field.
+    pub fn similarity(&self) -> Option<&dyn NameSimilarity> {
+        self.similarity.as_deref()
+    }
+}
+
+fn default_metric() -> String {
+    "jaro".to_string()
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+/// A named set of field weights used by `calculate_full_score`.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ScoringProfile {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    /// Birth-year window (± years) used to load and pre-filter candidates
+    #[serde(default = "default_year_tolerance")]
+    pub year_tolerance: u32,
+    /// Match threshold (percent) of this profile, e.g. as found by
+    /// `tuning::tune_profile`; MATCH_MIN_SCORE when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_score: Option<f64>,
+    pub fields: Vec<FieldRule>,
+}
+
+fn default_year_tolerance() -> u32 {
+    DEFAULT_YEAR_TOLERANCE
+}
+
+impl ScoringProfile {
+    /// The weights `calculate_full_score` has always used
+    /// (mother's last name and sex do not count), with graded DOB comparison.
+    pub fn builtin_default() -> Self {
+        let rule = |field, weight, metric: &str, enabled| {
+            FieldRule::new(field, weight, metric, enabled).expect("built-in metric")
+        };
+        ScoringProfile {
+            name: "default".to_string(),
+            description: "Built-in weights".to_string(),
+            year_tolerance: DEFAULT_YEAR_TOLERANCE,
+            min_score: None,
+            fields: vec![
+                rule(ScoreField::FirstName,       0.35, "combo", true),
+                rule(ScoreField::LastName,        0.30, "combo", true),
+                rule(ScoreField::FatherName,      0.10, "jaro",  true),
+                rule(ScoreField::GrandfatherName, 0.05, "jaro",  true),
+                rule(ScoreField::MotherLastName,  0.00, "jaro",  false),
+                rule(ScoreField::MotherName,      0.05, "jaro",  true),
+                rule(ScoreField::Dob,             0.10, "graded", true),
+                rule(ScoreField::PlaceOfBirth,    0.05, "jaro",  true),
+                rule(ScoreField::Sex,             0.00, "exact", false),
+            ],
+        }
+    }
+
+    /// The profile's own threshold, to fill in what the caller left unset.
+    pub fn match_params(&self) -> MatchParams {
+        MatchParams { min_score: self.min_score, ..MatchParams::default() }
+    }
+
+    /// Rules that take part in scoring.
+    pub fn active_rules(&self) -> impl Iterator<Item = &FieldRule> {
+        self.fields.iter().filter(|r| r.enabled && r.weight > 0.0)
+    }
+
+    /// Reject negative weights and duplicated fields (metrics are checked
+    /// when the rules are built).
+    pub fn validate(&self) -> Result<(), String> {
+        let mut seen = Vec::new();
+        for rule in &self.fields {
+            if seen.contains(&rule.field) {
+                return Err(format!("profile '{}': field {:?} listed twice", self.name, rule.field));
+            }
+            seen.push(rule.field);
+
+            if !rule.weight.is_finite() || rule.weight < 0.0 {
+                return Err(format!("profile '{}': invalid weight {} for {:?}", self.name, rule.weight, rule.field));
+            }
+        }
+        if let Some(min_score) = self.min_score {
+            if !(0.0..=100.0).contains(&min_score) {
+                return Err(format!("profile '{}': min_score {} is not a percentage", self.name, min_score));
+            }
+        }
+        if self.active_rules().next().is_none() {
+            return Err(format!("profile '{}' has no enabled field with a positive weight", self.name));
+        }
+        Ok(())
+    }
+}
+
+/// On-disk layout of a profiles file (TOML or JSON).
+#[derive(Debug, Deserialize, Serialize)]
+struct ProfilesFile {
+    default: Option<String>,
+    profiles: Vec<ScoringProfile>,
+}
+
+/// All scoring profiles known to the server or CLI, keyed by name.
+#[derive(Debug, Clone)]
+pub struct ScoringProfiles {
+    default: String,
+    profiles: HashMap<String, ScoringProfile>,
+}
+
+impl ScoringProfiles {
+    /// Only the built-in `default` profile.
+    pub fn builtin() -> Self {
+        let profile = ScoringProfile::builtin_default();
+        let mut profiles = HashMap::new();
+        let default = profile.name.clone();
+        profiles.insert(default.clone(), profile);
+        ScoringProfiles { default, profiles }
+    }
+
+    /// Loads profiles from a file
+    ///
+    /// The file extension is used to determine the file format:
+    /// - .toml: TOML format
+    /// - .json: JSON format
+    ///
+    /// The built-in `default` profile stays available unless the file redefines it.
+    pub fn load(file_path: &str) -> io::Result<Self> {
+        let path = Path::new(file_path);
+        let extension = path.extension().and_then(|ext| ext.to_str()).unwrap_or("");
+        let contents = fs::read_to_string(path)?;
+
+        let file: ProfilesFile = match extension.to_lowercase().as_str() {
+            "toml" => toml::from_str(&contents)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?,
+            "json" => serde_json::from_str(&contents)?,
+            _ => return Err(io::Error::new(io::ErrorKind::InvalidInput, "Unsupported file format")),
+        };
+
+        let mut loaded = ScoringProfiles::builtin();
+        for profile in file.profiles {
+            profile
+                .validate()
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            loaded.profiles.insert(profile.name.clone(), profile);
+        }
+
+        if let Some(default) = file.default {
+            if !loaded.profiles.contains_key(&default) {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("default profile '{}' is not defined", default),
+                ));
+            }
+            loaded.default = default;
+        }
+
+        Ok(loaded)
+    }
+
+    /// Loads profiles from `SCORING_PROFILES` if set, otherwise the built-in ones.
+    pub fn from_env() -> io::Result<Self> {
+        match std::env::var("SCORING_PROFILES") {
+            Ok(path) if !path.is_empty() => Self::load(&path),
+            _ => Ok(Self::builtin()),
+        }
+    }
+
+    /// Look up a profile; `None` selects the default one.
+    pub fn get(&self, name: Option<&str>) -> Option<&ScoringProfile> {
+        self.profiles.get(name.unwrap_or(&self.default))
+    }
+
+    pub fn default_name(&self) -> &str {
+        &self.default
+    }
+
+    pub fn names(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = self.profiles.keys().map(String::as_str).collect();
+        names.sort_unstable();
+        names
+    }
+}
+
+/// Write `profiles` to a file `ScoringProfiles::load` reads back; the
+/// extension picks the format (.toml or .json).
+pub fn save_profiles(file_path: &str, profiles: &[ScoringProfile]) -> io::Result<()> {
+    let file = ProfilesFile { default: None, profiles: profiles.to_vec() };
+    let extension = Path::new(file_path).extension().and_then(|ext| ext.to_str()).unwrap_or("");
+    let contents = match extension.to_lowercase().as_str() {
+        "toml" => toml::to_string_pretty(&file).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?,
+        "json" => serde_json::to_string_pretty(&file)?,
+        _ => return Err(io::Error::new(io::ErrorKind::InvalidInput, "Unsupported file format")),
+    };
+    fs::write(file_path, contents)
+}
@@ -0,0 +1,455 @@
+// src/utils/similarity.rs
+
+use std::collections::{BTreeSet, HashMap};
+use std::sync::{Arc, OnceLock, RwLock};
+use strsim::{damerau_levenshtein, jaro, jaro_winkler, normalized_levenshtein};
+use crate::utils::matching::{combo, combo_keyed, score_pair_with_soundex, score_pair_with_soundex_keyed};
+use crate::utils::phonetic::{aramix_soundex, PhoneticKey};
+
+/// Signature of a scoring function given the names' phonetic codes.
+pub type KeyedFn = fn(&str, &PhoneticKey, &str, &PhoneticKey) -> f64;
+
+/// A similarity measure between two pre-normalized name strings.
+/// Implementations return a value in [0, 1], 1 meaning identical.
+pub trait NameSimilarity: Send + Sync {
+    /// Name under which the metric is registered and reported.
+    fn name(&self) -> &str;
+    fn similarity(&self, a: &str, b: &str) -> f64;
+
+    /// `similarity` of two names given with their precomputed `PhoneticKey`s;
+    /// metrics with a phonetic part override it to skip recoding the names.
+    fn keyed_similarity(&self, a: &str, _a_key: &PhoneticKey, b: &str, _b_key: &PhoneticKey) -> f64 {
+        self.similarity(a, b)
+    }
+}
+
+impl std::fmt::Debug for dyn NameSimilarity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Plain Jaro.
+pub struct Jaro;
+
+impl NameSimilarity for Jaro {
+    fn name(&self) -> &str { "jaro" }
+    fn similarity(&self, a: &str, b: &str) -> f64 {
+        jaro(a, b)
+    }
+}
+
+/// Jaro with the Winkler common-prefix boost.
+pub struct JaroWinkler;
+
+impl NameSimilarity for JaroWinkler {
+    fn name(&self) -> &str { "jaro_winkler" }
+    fn similarity(&self, a: &str, b: &str) -> f64 {
+        jaro_winkler(a, b)
+    }
+}
+
+/// Levenshtein distance over chars, scaled by the longer string.
+pub struct NormalizedLevenshtein;
+
+impl NameSimilarity for NormalizedLevenshtein {
+    fn name(&self) -> &str { "levenshtein" }
+    fn similarity(&self, a: &str, b: &str) -> f64 {
+        normalized_levenshtein(a, b)
+    }
+}
+
+/// Levenshtein with adjacent transpositions, scaled by the longer string.
+pub struct DamerauLevenshtein;
+
+impl NameSimilarity for DamerauLevenshtein {
+    fn name(&self) -> &str { "damerau_levenshtein" }
+    fn similarity(&self, a: &str, b: &str) -> f64 {
+        let len = a.chars().count().max(b.chars().count());
+        if len == 0 {
+            return 1.0;
+        }
+        1.0 - damerau_levenshtein(a, b) as f64 / len as f64
+    }
+}
+
+/// Token-set ratio: compares the shared tokens against each side's leftovers,
+/// so word order and an extra word on one side barely matter.
+pub struct TokenSetRatio;
+
+impl NameSimilarity for TokenSetRatio {
+    fn name(&self) -> &str { "token_set" }
+    fn similarity(&self, a: &str, b: &str) -> f64 {
+        let ta: BTreeSet<&str> = a.split_whitespace().collect();
+        let tb: BTreeSet<&str> = b.split_whitespace().collect();
+        if ta.is_empty() || tb.is_empty() {
+            return (ta.is_empty() && tb.is_empty()) as u8 as f64;
+        }
+
+        let join = |set: BTreeSet<&&str>| set.into_iter().copied().collect::<Vec<_>>().join(" ");
+        let common = join(ta.intersection(&tb).collect());
+        let with_rest = |rest: String| match (common.is_empty(), rest.is_empty()) {
+            (true, _) => rest,
+            (false, true) => common.clone(),
+            (false, false) => format!("{} {}", common, rest),
+        };
+        let left = with_rest(join(ta.difference(&tb).collect()));
+        let right = with_rest(join(tb.difference(&ta).collect()));
+
+        normalized_levenshtein(&common, &left)
+            .max(normalized_levenshtein(&common, &right))
+            .max(normalized_levenshtein(&left, &right))
+    }
+}
+
+/// 1 when both names share the same Aramix Soundex code, 0 otherwise.
+pub struct SoundexEquality;
+
+impl NameSimilarity for SoundexEquality {
+    fn name(&self) -> &str { "soundex" }
+    fn similarity(&self, a: &str, b: &str) -> f64 {
+        (aramix_soundex(a) == aramix_soundex(b)) as u8 as f64
+    }
+    fn keyed_similarity(&self, _a: &str, a_key: &PhoneticKey, _b: &str, b_key: &PhoneticKey) -> f64 {
+        (a_key.soundex == b_key.soundex) as u8 as f64
+    }
+}
+
+/// 1 for identical strings, 0 otherwise.
+pub struct ExactMatch;
+
+impl NameSimilarity for ExactMatch {
+    fn name(&self) -> &str { "exact" }
+    fn similarity(&self, a: &str, b: &str) -> f64 {
+        (a == b) as u8 as f64
+    }
+}
+
+/// Adapter for the plain scoring functions of `matching.rs`.
+pub struct FnSimilarity {
+    name: String,
+    f: fn(&str, &str) -> f64,
+    /// Same function on precomputed phonetic codes, if it has a phonetic part
+    keyed: Option<KeyedFn>,
+}
+
+impl FnSimilarity {
+    pub fn new(name: &str, f: fn(&str, &str) -> f64) -> Self {
+        FnSimilarity { name: name.to_string(), f, keyed: None }
+    }
+
+    /// Use `keyed` when the names come with their phonetic codes.
+    pub fn with_keys(mut self, keyed: KeyedFn) -> Self {
+        self.keyed = Some(keyed);
+        self
+    }
+}
+
+impl NameSimilarity for FnSimilarity {
+    fn name(&self) -> &str { &self.name }
+    fn similarity(&self, a: &str, b: &str) -> f64 {
+        (self.f)(a, b)
+    }
+    fn keyed_similarity(&self, a: &str, a_key: &PhoneticKey, b: &str, b_key: &PhoneticKey) -> f64 {
+        match self.keyed {
+            Some(keyed) => keyed(a, a_key, b, b_key),
+            None => (self.f)(a, b),
+        }
+    }
+}
+
+/// Weighted average of other metrics.
+pub struct WeightedSimilarity {
+    name: String,
+    parts: Vec<(Arc<dyn NameSimilarity>, f64)>,
+}
+
+impl WeightedSimilarity {
+    pub fn new(name: &str, parts: Vec<(Arc<dyn NameSimilarity>, f64)>) -> Self {
+        WeightedSimilarity { name: name.to_string(), parts }
+    }
+}
+
+impl NameSimilarity for WeightedSimilarity {
+    fn name(&self) -> &str { &self.name }
+    fn similarity(&self, a: &str, b: &str) -> f64 {
+        let total: f64 = self.parts.iter().map(|(_, w)| w).sum();
+        if total <= 0.0 {
+            return 0.0;
+        }
+        self.parts.iter().map(|(m, w)| m.similarity(a, b) * w).sum::<f64>() / total
+    }
+    fn keyed_similarity(&self, a: &str, a_key: &PhoneticKey, b: &str, b_key: &PhoneticKey) -> f64 {
+        let total: f64 = self.parts.iter().map(|(_, w)| w).sum();
+        if total <= 0.0 {
+            return 0.0;
+        }
+        self.parts.iter().map(|(m, w)| m.keyed_similarity(a, a_key, b, b_key) * w).sum::<f64>() / total
+    }
+}
+
+/// Unmatched tokens on the longer side cost this much each in `TokenAlignment`.
+pub const EXTRA_TOKEN_PENALTY: f64 = 0.1;
+
+/// Beyond this many tokens the optimal assignment gives way to Monge-Elkan.
+const MAX_ALIGNED_TOKENS: usize = 12;
+
+/// Token-level comparison: the tokens of both names are paired one-to-one so
+/// that the sum of `inner` similarities is highest (optimal assignment), which
+/// makes word order irrelevant ("محمد علي" vs "علي محمد"). The score is the
+/// mean over the shorter side minus `EXTRA_TOKEN_PENALTY` per token left over
+/// on the longer one; merged and split tokens ("محمدعلي" vs "محمد علي") are
+/// covered by also trying each side with two adjacent tokens joined.
+/// Never lower than `inner` on the whole strings.
+pub struct TokenAlignment {
+    name: String,
+    inner: Arc<dyn NameSimilarity>,
+}
+
+impl TokenAlignment {
+    pub fn new(name: &str, inner: Arc<dyn NameSimilarity>) -> Self {
+        TokenAlignment { name: name.to_string(), inner }
+    }
+}
+
+impl TokenAlignment {
+    /// The best token pairing of `a` and `b`, or `whole` (`inner` on the
+    /// whole strings) when higher.
+    fn aligned(&self, a: &str, b: &str, whole: f64) -> f64 {
+        let ta: Vec<&str> = a.split_whitespace().collect();
+        let tb: Vec<&str> = b.split_whitespace().collect();
+        if ta.len() <= 1 && tb.len() <= 1 {
+            return whole;
+        }
+
+        let mut best = whole;
+        for left in token_variants(&ta) {
+            for right in token_variants(&tb) {
+                best = best.max(align_tokens(self.inner.as_ref(), &left, &right));
+            }
+        }
+        best
+    }
+}
+
+impl NameSimilarity for TokenAlignment {
+    fn name(&self) -> &str { &self.name }
+    fn similarity(&self, a: &str, b: &str) -> f64 {
+        self.aligned(a, b, self.inner.similarity(a, b))
+    }
+    fn keyed_similarity(&self, a: &str, a_key: &PhoneticKey, b: &str, b_key: &PhoneticKey) -> f64 {
+        self.aligned(a, b, self.inner.keyed_similarity(a, a_key, b, b_key))
+    }
+}
+
+/// `tokens` as they are, then with each pair of adjacent tokens joined.
+fn token_variants(tokens: &[&str]) -> Vec<Vec<String>> {
+    let mut variants = vec![tokens.iter().map(|t| t.to_string()).collect::<Vec<_>>()];
+    for i in 1..tokens.len() {
+        let mut merged: Vec<String> = tokens[..i - 1].iter().map(|t| t.to_string()).collect();
+        merged.push(format!("{}{}", tokens[i - 1], tokens[i]));
+        merged.extend(tokens[i + 1..].iter().map(|t| t.to_string()));
+        variants.push(merged);
+    }
+    variants
+}
+
+/// Best one-to-one pairing of `a` and `b` tokens, scored as described on `TokenAlignment`.
+pub fn align_tokens(inner: &dyn NameSimilarity, a: &[String], b: &[String]) -> f64 {
+    let (short, long) = if a.len() <= b.len() { (a, b) } else { (b, a) };
+    if short.is_empty() {
+        return long.is_empty() as u8 as f64;
+    }
+    let sims: Vec<Vec<f64>> = short
+        .iter()
+        .map(|s| long.iter().map(|l| inner.similarity(s, l)).collect())
+        .collect();
+
+    let total = if long.len() > MAX_ALIGNED_TOKENS {
+        // Monge-Elkan: each short-side token takes its best match
+        sims.iter().map(|row| row.iter().copied().fold(0.0, f64::max)).sum::<f64>()
+    } else {
+        // best[mask] = highest sum pairing the first k short tokens with the
+        // long tokens in `mask`, k being the number of bits set in `mask`
+        let mut best = vec![f64::NEG_INFINITY; 1 << long.len()];
+        best[0] = 0.0;
+        for mask in 0..best.len() {
+            let k = (mask as u32).count_ones() as usize;
+            if best[mask] == f64::NEG_INFINITY || k >= short.len() {
+                continue;
+            }
+            for (j, sim) in sims[k].iter().enumerate() {
+                if mask & (1 << j) == 0 {
+                    let next = mask | (1 << j);
+                    best[next] = best[next].max(best[mask] + sim);
+                }
+            }
+        }
+        best.iter()
+            .enumerate()
+            .filter(|(mask, _)| (*mask as u32).count_ones() as usize == short.len())
+            .map(|(_, score)| *score)
+            .fold(0.0, f64::max)
+    };
+
+    let extra = (long.len() - short.len()) as f64;
+    (total / short.len() as f64 - EXTRA_TOKEN_PENALTY * extra).max(0.0)
+}
+
+/// `matching::score_pair_with_soundex` as a metric.
+pub fn soundex_jaro_levenshtein() -> FnSimilarity {
+    FnSimilarity::new("soundex_jaro_levenshtein", score_pair_with_soundex).with_keys(score_pair_with_soundex_keyed)
+}
+
+/// Metrics addressable by name from scoring profiles.
+///
+/// Besides registered names, `resolve` accepts weighted combinations written
+/// as `"0.6*jaro_winkler + 0.4*soundex"`; they are built on each call, so
+/// resolve once and keep the metric (scoring profiles do so when loaded).
+pub struct SimilarityRegistry {
+    metrics: RwLock<HashMap<String, Arc<dyn NameSimilarity>>>,
+}
+
+impl SimilarityRegistry {
+    /// An empty registry.
+    pub fn new() -> Self {
+        SimilarityRegistry { metrics: RwLock::new(HashMap::new()) }
+    }
+
+    /// A registry holding every built-in metric.
+    pub fn with_builtins() -> Self {
+        let registry = Self::new();
+        registry.register(Arc::new(Jaro));
+        registry.register(Arc::new(JaroWinkler));
+        registry.register(Arc::new(NormalizedLevenshtein));
+        registry.register(Arc::new(DamerauLevenshtein));
+        registry.register(Arc::new(TokenSetRatio));
+        registry.register(Arc::new(SoundexEquality));
+        registry.register(Arc::new(ExactMatch));
+        registry.register(Arc::new(
+            FnSimilarity::new("combo", |a, b| combo(a, b) as f64).with_keys(|a, ka, b, kb| combo_keyed(a, ka, b, kb) as f64),
+        ));
+        registry.register(Arc::new(soundex_jaro_levenshtein()));
+        registry.register(Arc::new(TokenAlignment::new("token_alignment", Arc::new(JaroWinkler))));
+        registry.register(Arc::new(TokenAlignment::new(
+            "token_soundex_jaro_levenshtein",
+            Arc::new(soundex_jaro_levenshtein()),
+        )));
+        registry
+    }
+
+    /// Add or replace a metric under its own name.
+    pub fn register(&self, metric: Arc<dyn NameSimilarity>) {
+        self.metrics
+            .write()
+            .expect("similarity registry poisoned")
+            .insert(metric.name().to_string(), metric);
+    }
+
+    /// Look up a metric by name or weighted-combination spec.
+    pub fn resolve(&self, spec: &str) -> Option<Arc<dyn NameSimilarity>> {
+        let spec = spec.trim();
+        if let Some(metric) = self.metrics.read().expect("similarity registry poisoned").get(spec) {
+            return Some(metric.clone());
+        }
+
+        Some(Arc::new(self.parse_weighted(spec)?))
+    }
+
+    /// Parse `"w1*metric1 + w2*metric2"`; a term without a weight counts 1.
+    fn parse_weighted(&self, spec: &str) -> Option<WeightedSimilarity> {
+        if !spec.contains('+') && !spec.contains('*') {
+            return None;
+        }
+        let mut parts = Vec::new();
+        for term in spec.split('+') {
+            let (weight, name) = match term.split_once('*') {
+                Some((w, n)) => (w.trim().parse::<f64>().ok()?, n.trim()),
+                None => (1.0, term.trim()),
+            };
+            if !weight.is_finite() || weight < 0.0 {
+                return None;
+            }
+            let metric = self.metrics.read().expect("similarity registry poisoned").get(name)?.clone();
+            parts.push((metric, weight));
+        }
+        Some(WeightedSimilarity::new(spec, parts))
+    }
+
+    /// Registered metric names, sorted.
+    pub fn names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self
+            .metrics
+            .read()
+            .expect("similarity registry poisoned")
+            .keys()
+            .cloned()
+            .collect();
+        names.sort_unstable();
+        names
+    }
+}
+
+impl Default for SimilarityRegistry {
+    fn default() -> Self {
+        Self::with_builtins()
+    }
+}
+
+/// Process-wide registry used by `calculate_full_score` and profile validation.
+pub fn default_registry() -> &'static SimilarityRegistry {
+    static REGISTRY: OnceLock<SimilarityRegistry> = OnceLock::new();
+    REGISTRY.get_or_init(SimilarityRegistry::with_builtins)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tokens(name: &str) -> Vec<String> {
+        name.split_whitespace().map(str::to_string).collect()
+    }
+
+    /// Fixed similarities between the tokens "s1", "s2" and "l1", "l2"
+    struct Table;
+
+    impl NameSimilarity for Table {
+        fn name(&self) -> &str { "table" }
+        fn similarity(&self, a: &str, b: &str) -> f64 {
+            match (a, b) {
+                ("s1", "l1") => 0.9,
+                ("s1", "l2") => 0.8,
+                ("s2", "l1") => 0.7,
+                _ => 0.0,
+            }
+        }
+    }
+
+    #[test]
+    fn alignment_ignores_word_order_and_charges_extra_tokens() {
+        assert_eq!(align_tokens(&ExactMatch, &tokens("محمد علي"), &tokens("علي محمد")), 1.0);
+        let score = align_tokens(&ExactMatch, &tokens("محمد علي"), &tokens("علي محمد صالح"));
+        assert!((score - (1.0 - EXTRA_TOKEN_PENALTY)).abs() < 1e-9, "{}", score);
+        assert_eq!(align_tokens(&ExactMatch, &tokens("محمد"), &tokens("علي")), 0.0);
+        assert_eq!(align_tokens(&ExactMatch, &[], &[]), 1.0);
+        assert_eq!(align_tokens(&ExactMatch, &[], &tokens("علي")), 0.0);
+    }
+
+    #[test]
+    fn alignment_is_optimal_not_greedy() {
+        // Greedy pairs s1 with l1 (0.9) and leaves s2 with l2 (0.0): 0.45.
+        // The best assignment is s1–l2 (0.8) and s2–l1 (0.7): 0.75.
+        let score = align_tokens(&Table, &tokens("s1 s2"), &tokens("l1 l2"));
+        assert!((score - 0.75).abs() < 1e-9, "{}", score);
+    }
+
+    #[test]
+    fn token_alignment_joins_split_tokens() {
+        let metric = TokenAlignment::new("token_alignment", Arc::new(ExactMatch));
+        assert_eq!(metric.similarity("محمدعلي", "محمد علي"), 1.0);
+        // "عبد الله" joined, "بن" left over
+        let score = metric.similarity("عبد الله بن علي", "علي عبدالله");
+        assert!((score - (1.0 - EXTRA_TOKEN_PENALTY)).abs() < 1e-9, "{}", score);
+        assert_eq!(metric.similarity("محمد", "علي"), 0.0);
+    }
+}
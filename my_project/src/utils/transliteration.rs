@@ -0,0 +1,296 @@
+// src/utils/transliteration.rs
+
+//! Latin (French-style Tunisian) spellings ↔ Arabic script.
+//!
+//! Names reach us as "Ben Salah", "Mohamed", "Trabelsi" or "Chedly" from
+//! passports and foreign systems. `latin_to_arabic` and `arabic_to_latin`
+//! give a readable canonical spelling in the other script; `skeleton` reduces
+//! either script to the consonants both can express, which is what
+//! cross-script comparisons run on.
+
+/// Particles stripped from the front of a Latin name, like
+/// `normalization::normalize_name` does for Arabic ones.
+const LATIN_PREFIXES: [&str; 9] = ["ben", "bin", "bent", "ibn", "el", "al", "ould", "abou", "oum"];
+
+fn is_arabic_char(c: char) -> bool {
+    matches!(c, '\u{0600}'..='\u{06FF}' | '\u{0750}'..='\u{077F}' | '\u{FB50}'..='\u{FDFF}' | '\u{FE70}'..='\u{FEFF}')
+}
+
+/// True when `text` is written in Latin letters (no Arabic letter in it).
+pub fn is_latin(text: &str) -> bool {
+    !text.chars().any(is_arabic_char) && text.chars().any(|c| c.is_alphabetic())
+}
+
+/// Lowercase, drop French accents and turn hyphens/apostrophes into spaces:
+/// "Chédly" → "chedly", "Ben-Salah" → "ben salah".
+pub fn fold_latin(text: &str) -> String {
+    let folded: String = text
+        .chars()
+        .flat_map(char::to_lowercase)
+        .map(|c| match c {
+            'à' | 'â' | 'ä' | 'á' => 'a',
+            'é' | 'è' | 'ê' | 'ë' => 'e',
+            'î' | 'ï' | 'í' => 'i',
+            'ô' | 'ö' | 'ó' => 'o',
+            'ù' | 'û' | 'ü' | 'ú' => 'u',
+            'ç' => 's',
+            'ÿ' => 'y',
+            '-' | '\'' | '’' | '_' | '.' => ' ',
+            c => c,
+        })
+        .collect();
+    folded.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Drop one leading particle ("ben", "el", …) when something follows it.
+pub fn strip_latin_prefix(folded: &str) -> &str {
+    for prefix in LATIN_PREFIXES {
+        if let Some(rest) = folded.strip_prefix(prefix) {
+            if let Some(rest) = rest.strip_prefix(' ') {
+                return rest;
+            }
+        }
+    }
+    folded
+}
+
+fn is_vowel(c: char) -> bool {
+    matches!(c, 'a' | 'e' | 'i' | 'o' | 'u' | 'y')
+}
+
+/// One Latin grapheme of a word as Arabic letters; `None` for a short vowel.
+/// Returns the Arabic text and how many Latin chars it used.
+fn latin_grapheme(word: &[char], i: usize) -> (Option<&'static str>, usize) {
+    let c = word[i];
+    let next = word.get(i + 1).copied();
+    let third = word.get(i + 2).copied();
+    let initial = i == 0;
+    let last = i + 1 == word.len();
+
+    match (c, next, third) {
+        ('t', Some('c'), Some('h')) => (Some("تش"), 3),
+        ('c' | 's', Some('h'), _) => (Some("ش"), 2),
+        ('k', Some('h'), _) => (Some("خ"), 2),
+        ('g', Some('h'), _) => (Some("غ"), 2),
+        ('d', Some('h'), _) => (Some("ذ"), 2),
+        ('t', Some('h'), _) => (Some("ث"), 2),
+        ('p', Some('h'), _) => (Some("ف"), 2),
+        ('d', Some('j'), _) => (Some("ج"), 2),
+        ('o', Some('u'), _) | ('o', Some('o'), _) => (Some("و"), 2),
+        ('e', Some('e'), _) | ('i', Some('i'), _) => (Some("ي"), 2),
+        ('a', Some('a'), _) => (Some("ا"), 2),
+        ('a' | 'e', Some('i' | 'y'), _) if !initial => (Some("ي"), 2),
+        ('c', Some('e' | 'i' | 'y'), _) => (Some("س"), 1),
+        // Doubled consonants are written once
+        (a, Some(b), _) if a == b && !is_vowel(a) => {
+            let (arabic, used) = latin_grapheme(word, i + 1);
+            (arabic, used + 1)
+        }
+        ('a' | 'e' | 'i' | 'o' | 'u', _, _) if initial => (Some("ا"), 1),
+        ('a', _, _) if last => (Some("ة"), 1),
+        ('i' | 'y', _, _) => (Some("ي"), 1),
+        ('u', _, _) => (Some("و"), 1),
+        ('a' | 'e' | 'o', _, _) => (None, 1),
+        ('b' | 'p', _, _) => (Some("ب"), 1),
+        ('c' | 'k' | 'q', _, _) => (Some("ك"), 1),
+        ('g', _, _) => (Some("ق"), 1),
+        ('d', _, _) => (Some("د"), 1),
+        ('f' | 'v', _, _) => (Some("ف"), 1),
+        // "…llah" as in Abdallah
+        ('h', _, _) if last && i > 1 && word[i - 1] == 'a' && word[i - 2] == 'l' => (Some("ه"), 1),
+        ('h' | '7', _, _) => (Some("ح"), 1),
+        ('j', _, _) => (Some("ج"), 1),
+        ('l', _, _) => (Some("ل"), 1),
+        ('m', _, _) => (Some("م"), 1),
+        ('n', _, _) => (Some("ن"), 1),
+        ('r', _, _) => (Some("ر"), 1),
+        ('s', _, _) => (Some("س"), 1),
+        ('t', _, _) => (Some("ت"), 1),
+        ('w', _, _) => (Some("و"), 1),
+        ('x', _, _) => (Some("كس"), 1),
+        ('z', _, _) => (Some("ز"), 1),
+        // Arabizi digits
+        ('3', _, _) => (Some("ع"), 1),
+        ('9', _, _) => (Some("ق"), 1),
+        ('5', _, _) => (Some("خ"), 1),
+        ('2', _, _) => (Some("ء"), 1),
+        _ => (None, 1),
+    }
+}
+
+/// Canonical Arabic spelling of a Latin Tunisian name:
+/// "Mohamed" → "محمد", "Trabelsi" → "تربلسي"… written without short vowels,
+/// so the emphatic letters (ط ص ض) and ع cannot be recovered.
+pub fn latin_to_arabic(text: &str) -> String {
+    fold_latin(text)
+        .split(' ')
+        .map(|word| {
+            let chars: Vec<char> = word.chars().collect();
+            let mut out = String::new();
+            let mut i = 0;
+            while i < chars.len() {
+                let (arabic, used) = latin_grapheme(&chars, i);
+                if let Some(arabic) = arabic {
+                    out.push_str(arabic);
+                }
+                i += used;
+            }
+            out
+        })
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// French-style Latin spelling of an Arabic name: "الشاذلي" → "elchadhli",
+/// "محمد" → "mhmd". Short vowels are not written in Arabic and stay absent.
+pub fn arabic_to_latin(text: &str) -> String {
+    let mut out = String::new();
+    for word in text.split_whitespace() {
+        if !out.is_empty() {
+            out.push(' ');
+        }
+        let word = match word.strip_prefix("ال") {
+            Some(rest) if !rest.is_empty() => {
+                out.push_str("el");
+                rest
+            }
+            _ => word,
+        };
+        for (i, c) in word.chars().enumerate() {
+            out.push_str(match c {
+                'ع' if i == 0 => "a",
+                'و' if i == 0 => "w",
+                'ي' if i == 0 => "y",
+                'ا' | 'أ' | 'إ' | 'آ' | 'ى' => "a",
+                'ب' => "b",
+                'ت' | 'ط' => "t",
+                'ث' => "th",
+                'ج' => "j",
+                'ح' | 'ه' | 'ة' => "h",
+                'خ' => "kh",
+                'د' => "d",
+                'ذ' | 'ض' | 'ظ' => "dh",
+                'ر' => "r",
+                'ز' => "z",
+                'س' | 'ص' => "s",
+                'ش' => "ch",
+                'ع' | 'ء' | 'ئ' | 'ؤ' => "",
+                'غ' => "gh",
+                'ف' => "f",
+                'ق' | 'ك' => "k",
+                'ڨ' | 'گ' => "g",
+                'ل' => "l",
+                'م' => "m",
+                'ن' => "n",
+                'و' => "ou",
+                'ي' => "i",
+                _ => "",
+            });
+        }
+    }
+    out
+}
+
+/// Arabic letter of the script-neutral skeleton, `None` when dropped
+/// (vowels, ع and hamza, which Latin spellings do not write reliably).
+fn skeleton_letter(c: char) -> Option<char> {
+    match c {
+        'ط' => Some('ت'),
+        'ث' => Some('ت'),
+        'ص' => Some('س'),
+        'ض' | 'ظ' | 'ذ' => Some('د'),
+        'ق' | 'ڨ' | 'گ' => Some('ك'),
+        'ح' | 'ة' => Some('ه'),
+        'ا' | 'أ' | 'إ' | 'آ' | 'ى' | 'و' | 'ي' | 'ؤ' | 'ئ' | 'ء' | 'ع' => None,
+        c if is_arabic_char(c) => Some(c),
+        _ => None,
+    }
+}
+
+/// Consonant skeleton of a name in either script, in Arabic letters:
+/// "Ben Salah" and "بن صالح" both give "سله", "Chedly" and "الشاذلي" "شدل".
+/// Leading particles, vowels, ع/hamza and spaces are dropped and the letters
+/// Latin spellings cannot tell apart (ت/ط, س/ص, ح/ه, ك/ق…) are merged.
+pub fn skeleton(text: &str) -> String {
+    let arabic = if is_latin(text) {
+        latin_to_arabic(strip_latin_prefix(&fold_latin(text)))
+    } else {
+        let trimmed = text.trim();
+        ["ال", "بن ", "ابن ", "بنت "]
+            .iter()
+            .find_map(|prefix| trimmed.strip_prefix(prefix).filter(|rest| !rest.trim().is_empty()))
+            .unwrap_or(trimmed)
+            .to_string()
+    };
+
+    let mut out = String::new();
+    for c in arabic.chars().filter_map(skeleton_letter) {
+        if !out.ends_with(c) {
+            out.push(c);
+        }
+    }
+    out
+}
+
+/// Skeletons of `a` and `b` when one is Latin and the other Arabic, so the
+/// pair can be compared; `None` when both are in the same script.
+pub fn cross_script_forms(a: &str, b: &str) -> Option<(String, String)> {
+    if a.is_empty() || b.is_empty() || is_latin(a) == is_latin(b) {
+        return None;
+    }
+    Some((skeleton(a), skeleton(b)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn folds_accents_hyphens_and_particles() {
+        assert_eq!(fold_latin("Chédly"), "chedly");
+        assert_eq!(fold_latin("Ben-Salah"), "ben salah");
+        assert_eq!(strip_latin_prefix("ben salah"), "salah");
+        assert!(is_latin("Ben Salah"));
+        assert!(!is_latin("بن صالح"));
+    }
+
+    #[test]
+    fn transliterates_both_ways() {
+        assert_eq!(latin_to_arabic("Mohamed"), "محمد");
+        assert_eq!(latin_to_arabic("Trabelsi"), "تربلسي");
+        assert_eq!(latin_to_arabic("Chedly"), "شدلي");
+        assert_eq!(arabic_to_latin("محمد"), "mhmd");
+        assert_eq!(arabic_to_latin("الشاذلي"), "elchadhli");
+        assert_eq!(arabic_to_latin("خالد"), "khald");
+    }
+
+    #[test]
+    fn skeleton_is_shared_by_both_scripts() {
+        for (latin, arabic, skel) in [
+            ("Ben Salah", "بن صالح", "سله"),
+            ("Chedly", "الشاذلي", "شدل"),
+            ("Mohamed", "محمد", "مهمد"),
+            ("Trabelsi", "الطرابلسي", "تربلس"),
+            ("Ahmed", "أحمد", "همد"),
+            ("Youssef", "يوسف", "سف"),
+        ] {
+            assert_eq!(skeleton(latin), skel, "{}", latin);
+            assert_eq!(skeleton(arabic), skel, "{}", arabic);
+        }
+        assert_eq!(cross_script_forms("Mohamed", "محمد"), Some(("مهمد".into(), "مهمد".into())));
+        assert_eq!(cross_script_forms("محمد", "احمد"), None);
+    }
+
+    #[test]
+    fn round_trip_keeps_the_skeleton() {
+        for name in ["Mohamed", "Trabelsi", "Chedly", "Khaled", "Youssef"] {
+            let back = arabic_to_latin(&latin_to_arabic(name));
+            assert_eq!(skeleton(&back), skeleton(name), "{} → {}", name, back);
+        }
+        for name in ["محمد", "أحمد", "يوسف", "خالد", "الطرابلسي"] {
+            let back = latin_to_arabic(&arabic_to_latin(name));
+            assert_eq!(skeleton(&back), skeleton(name), "{} → {}", name, back);
+        }
+    }
+}
@@ -0,0 +1,279 @@
+// src/utils/tuning.rs
+
+//! Field weights and match threshold learnt from a labelled gold set.
+//!
+//! Per-field similarities do not depend on the weights, so each pair is
+//! scored field by field once (`field_scores`) and the search only re-weights
+//! those numbers. `tune_profile` runs a coordinate descent over the weights,
+//! picking the best threshold for each candidate weighting, and reports how
+//! the result holds up under k-fold cross-validation.
+
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
+use rayon::prelude::*;
+use serde::Serialize;
+use crate::utils::evaluate::{gold_pairs, ConfusionMatrix};
+use crate::utils::gold_set::GoldSetIdentity;
+use crate::utils::linked_list::IdentityNode;
+use crate::utils::matching::explain_match;
+use crate::utils::scoring_profile::ScoringProfile;
+
+/// Values tried for each weight; weights are relative, so the grid only
+/// needs to span the useful ratios.
+const WEIGHT_GRID: [f64; 12] = [0.0, 0.025, 0.05, 0.075, 0.1, 0.15, 0.2, 0.25, 0.3, 0.35, 0.4, 0.5];
+
+/// Coordinate-descent passes over all the weights, at most.
+const MAX_ROUNDS: usize = 10;
+
+/// What the tuning maximizes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Objective {
+    F1,
+    /// Recall among the thresholds reaching this precision (0–1)
+    RecallAtPrecision(f64),
+}
+
+impl Objective {
+    /// `f1`, or `recall@0.95` (also `recall@95`).
+    pub fn parse(text: &str) -> Option<Self> {
+        match text.to_lowercase().as_str() {
+            "f1" => Some(Objective::F1),
+            other => {
+                let precision: f64 = other.strip_prefix("recall@")?.parse().ok()?;
+                let precision = if precision > 1.0 { precision / 100.0 } else { precision };
+                (0.0..=1.0).contains(&precision).then_some(Objective::RecallAtPrecision(precision))
+            }
+        }
+    }
+
+    pub fn name(&self) -> String {
+        match self {
+            Objective::F1 => "f1".to_string(),
+            Objective::RecallAtPrecision(p) => format!("recall@{}", p),
+        }
+    }
+
+    fn value(&self, matrix: &ConfusionMatrix) -> f64 {
+        match self {
+            Objective::F1 => matrix.f1(),
+            Objective::RecallAtPrecision(p) if matrix.precision() >= *p => matrix.recall(),
+            Objective::RecallAtPrecision(_) => 0.0,
+        }
+    }
+}
+
+/// Similarity (percent) of every field of `profile` for each labelled pair,
+/// in the order of `profile.fields`, with the pair's label.
+pub fn field_scores(
+    profile: &ScoringProfile,
+    records: &[IdentityNode],
+    gold: &[(GoldSetIdentity, GoldSetIdentity, bool)],
+) -> Vec<(Vec<f64>, bool)> {
+    gold_pairs(records, gold)
+        .par_iter()
+        .map(|(input, candidate, is_match)| {
+            let explanation = explain_match(profile, input, candidate);
+            (explanation.breakdown.iter().map(|f| f.score).collect(), *is_match)
+        })
+        .collect()
+}
+
+/// Total score (percent) of each pair under `weights`.
+fn weighted_scores(features: &[&(Vec<f64>, bool)], weights: &[f64]) -> Vec<(f64, bool)> {
+    let total: f64 = weights.iter().sum();
+    features
+        .iter()
+        .map(|(scores, is_match)| {
+            let score = if total > 0.0 {
+                scores.iter().zip(weights).map(|(s, w)| s * w).sum::<f64>() / total
+            } else {
+                0.0
+            };
+            (score, *is_match)
+        })
+        .collect()
+}
+
+/// Best whole-percent threshold for `objective` and its value; ties go to
+/// the higher threshold.
+fn best_threshold(scored: &[(f64, bool)], objective: Objective) -> (f64, f64) {
+    let mut sorted: Vec<(f64, bool)> = scored.to_vec();
+    sorted.sort_by(|a, b| b.0.total_cmp(&a.0));
+    let positives = sorted.iter().filter(|(_, m)| *m).count();
+    let negatives = sorted.len() - positives;
+
+    // Sweep thresholds downwards, admitting pairs as they clear each one
+    let mut best = (100.0, f64::NEG_INFINITY);
+    let (mut tp, mut fp, mut next) = (0, 0, 0);
+    for t in (0..=100).rev() {
+        while next < sorted.len() && sorted[next].0 >= t as f64 {
+            if sorted[next].1 { tp += 1 } else { fp += 1 }
+            next += 1;
+        }
+        let matrix = ConfusionMatrix {
+            true_positives:  tp,
+            false_positives: fp,
+            true_negatives:  negatives - fp,
+            false_negatives: positives - tp,
+        };
+        let value = objective.value(&matrix);
+        if value > best.1 {
+            best = (t as f64, value);
+        }
+    }
+    best
+}
+
+/// Coordinate descent from `start`: weights, threshold and objective value
+/// on `features`.
+fn optimize(features: &[&(Vec<f64>, bool)], start: &[f64], objective: Objective) -> (Vec<f64>, f64, f64) {
+    let evaluate = |weights: &[f64]| best_threshold(&weighted_scores(features, weights), objective);
+    let mut weights = start.to_vec();
+    let (mut threshold, mut value) = evaluate(&weights);
+
+    for _ in 0..MAX_ROUNDS {
+        let mut improved = false;
+        for i in 0..weights.len() {
+            for &w in &WEIGHT_GRID {
+                if w == weights[i] {
+                    continue;
+                }
+                let mut trial = weights.clone();
+                trial[i] = w;
+                if trial.iter().sum::<f64>() == 0.0 {
+                    continue;
+                }
+                let (t, v) = evaluate(&trial);
+                if v > value + 1e-9 {
+                    (weights, threshold, value) = (trial, t, v);
+                    improved = true;
+                }
+            }
+        }
+        if !improved {
+            break;
+        }
+    }
+    (weights, threshold, value)
+}
+
+/// Objective of one cross-validation fold.
+#[derive(Debug, Clone, Serialize)]
+pub struct FoldResult {
+    pub fold: usize,
+    /// On the other folds, which the weights were tuned on
+    pub train: f64,
+    /// On this fold, at the threshold tuned on the others
+    pub test: f64,
+    pub threshold: f64,
+}
+
+/// Outcome of `tune_profile`.
+#[derive(Debug, Clone, Serialize)]
+pub struct TuningReport {
+    pub objective: String,
+    pub pairs: usize,
+    /// Objective of the starting weights at their best threshold
+    pub baseline: f64,
+    /// Objective of the tuned weights on all pairs
+    pub tuned: f64,
+    pub folds: Vec<FoldResult>,
+    pub cv_mean: f64,
+    pub cv_std: f64,
+    /// `base` with the tuned weights and threshold (`min_score`)
+    pub profile: ScoringProfile,
+}
+
+/// Shuffled round-robin assignment of `len` pairs to folds, the same for the
+/// same `seed`. Returns the number of folds, 0 (no cross-validation) when
+/// `folds` is below 2 or above `len`, and the fold of each pair.
+pub fn assign_folds(len: usize, folds: usize, seed: u64) -> (usize, Vec<usize>) {
+    let folds = if folds >= 2 && len >= folds { folds } else { 0 };
+    let mut order: Vec<usize> = (0..len).collect();
+    order.shuffle(&mut StdRng::seed_from_u64(seed));
+    let mut fold_of = vec![0; len];
+    for (position, &i) in order.iter().enumerate() {
+        fold_of[i] = position % folds.max(1);
+    }
+    (folds, fold_of)
+}
+
+/// Tune the weights of `base` and its threshold on `features` (from
+/// `field_scores` with the same profile). `folds` ≥ 2 runs k-fold
+/// cross-validation with a `seed`ed shuffle; the returned profile is tuned
+/// on all the pairs.
+pub fn tune_profile(
+    base: &ScoringProfile,
+    features: &[(Vec<f64>, bool)],
+    objective: Objective,
+    folds: usize,
+    seed: u64,
+) -> TuningReport {
+    let start: Vec<f64> = base.fields.iter().map(|r| if r.enabled { r.weight } else { 0.0 }).collect();
+    let all: Vec<&(Vec<f64>, bool)> = features.iter().collect();
+    let (_, baseline) = best_threshold(&weighted_scores(&all, &start), objective);
+
+    let (folds, fold_of) = assign_folds(features.len(), folds, seed);
+    let fold_results: Vec<FoldResult> = (0..folds)
+        .into_par_iter()
+        .map(|fold| {
+            let split = |in_fold: bool| -> Vec<&(Vec<f64>, bool)> {
+                features
+                    .iter()
+                    .zip(&fold_of)
+                    .filter(|&(_, &f)| (f == fold) == in_fold)
+                    .map(|(pair, _)| pair)
+                    .collect()
+            };
+            let (train, test) = (split(false), split(true));
+            let (weights, threshold, train_value) = optimize(&train, &start, objective);
+            let matrix = ConfusionMatrix::at(&weighted_scores(&test, &weights), threshold);
+            FoldResult { fold: fold + 1, train: train_value, test: objective.value(&matrix), threshold }
+        })
+        .collect();
+
+    let n = fold_results.len().max(1) as f64;
+    let cv_mean = fold_results.iter().map(|f| f.test).sum::<f64>() / n;
+    let cv_std = (fold_results.iter().map(|f| (f.test - cv_mean).powi(2)).sum::<f64>() / n).sqrt();
+
+    let (weights, threshold, tuned) = optimize(&all, &start, objective);
+    let total: f64 = weights.iter().sum();
+    let mut profile = base.clone();
+    profile.name = format!("{}-tuned", base.name);
+    profile.description = format!("{} tuned for {} on {} pairs", base.name, objective.name(), features.len());
+    profile.min_score = Some(threshold);
+    for (rule, w) in profile.fields.iter_mut().zip(&weights) {
+        rule.weight = (w / total * 1000.0).round() / 1000.0;
+        rule.enabled = rule.weight > 0.0;
+    }
+
+    TuningReport {
+        objective: objective.name(),
+        pairs: features.len(),
+        baseline,
+        tuned,
+        folds: fold_results,
+        cv_mean,
+        cv_std,
+        profile,
+    }
+}
+
+impl TuningReport {
+    /// Plain-text summary, folds and tuned weights.
+    pub fn print(&self) {
+        println!("🎛️  Tuning '{}' for {} on {} pairs", self.profile.name, self.objective, self.pairs);
+        println!("  Baseline {:.4} → tuned {:.4} at {}%", self.baseline, self.tuned, self.profile.min_score.unwrap_or(0.0));
+        if !self.folds.is_empty() {
+            println!("  {:>5} {:>8} {:>8} {:>10}", "fold", "train", "test", "threshold");
+            for fold in &self.folds {
+                println!("  {:>5} {:>8.4} {:>8.4} {:>9}%", fold.fold, fold.train, fold.test, fold.threshold);
+            }
+            println!("  Cross-validated {:.4} ± {:.4}", self.cv_mean, self.cv_std);
+        }
+        for rule in &self.profile.fields {
+            println!("  {:<18} {:>6.3}  [{}]", rule.field.label(), rule.weight, rule.metric);
+        }
+    }
+}
@@ -0,0 +1,156 @@
+// src/utils/variant_mining.rs
+
+//! Name variants learnt from adjudicated matches.
+//!
+//! Two records confirmed as the same person (`GoldSetRecord::is_match`) that
+//! spell a name differently give a candidate equivalence ("حمادي" ~ "محمد").
+//! `mine_variants` counts how many confirmed pairs show each difference and
+//! proposes those seen often enough; a reviewer then marks each proposal
+//! approved or rejected in the proposals file, and `apply_approved` adds the
+//! approved ones to the `VariantDictionary`.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use serde::{Deserialize, Serialize};
+use crate::utils::gold_set::GoldSetIdentity;
+use crate::utils::scoring_profile::ScoreField;
+use crate::utils::variants::VariantDictionary;
+
+/// Confirmed pairs a difference needs before it is proposed.
+pub const DEFAULT_MIN_SUPPORT: usize = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReviewStatus {
+    #[default]
+    Pending,
+    Approved,
+    Rejected,
+}
+
+/// A proposed equivalence between two normalized spellings.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VariantProposal {
+    /// The two spellings, in sorted order
+    pub names: [String; 2],
+    /// Confirmed pairs showing this difference
+    pub support: usize,
+    /// Fields the difference was seen in
+    pub fields: Vec<ScoreField>,
+    #[serde(default)]
+    pub status: ReviewStatus,
+}
+
+fn name_fields(identity: &GoldSetIdentity) -> [(ScoreField, &str); 6] {
+    [
+        (ScoreField::FirstName,       &identity.first_name),
+        (ScoreField::LastName,        &identity.last_name),
+        (ScoreField::FatherName,      &identity.father_name),
+        (ScoreField::GrandfatherName, &identity.grandfather_name),
+        (ScoreField::MotherLastName,  &identity.mother_last_name),
+        (ScoreField::MotherName,      &identity.mother_name),
+    ]
+}
+
+/// Spelling differences between two normalized values of a field: the
+/// differing parts when both have as many parts ("محمد امين" / "حمادي امين"
+/// gives محمد ~ حمادي), otherwise the whole values.
+fn field_differences(a: &str, b: &str) -> Vec<[String; 2]> {
+    let sorted = |x: &str, y: &str| {
+        if x <= y { [x.to_string(), y.to_string()] } else { [y.to_string(), x.to_string()] }
+    };
+    if a.is_empty() || b.is_empty() || a == b {
+        return Vec::new();
+    }
+    let (parts_a, parts_b): (Vec<&str>, Vec<&str>) = (a.split(' ').collect(), b.split(' ').collect());
+    if parts_a.len() != parts_b.len() {
+        return vec![sorted(a, b)];
+    }
+    parts_a
+        .iter()
+        .zip(&parts_b)
+        .filter(|(x, y)| x != y)
+        .map(|(x, y)| sorted(x, y))
+        .collect()
+}
+
+/// Spelling differences of the confirmed pairs of a gold set (as returned by
+/// `load_gold_set_for_records`) seen in at least `min_support` pairs and not
+/// already equivalent in `dictionary`, most supported first.
+pub fn mine_variants(
+    gold: &[(GoldSetIdentity, GoldSetIdentity, bool)],
+    dictionary: &VariantDictionary,
+    min_support: usize,
+) -> Vec<VariantProposal> {
+    let mut counts: HashMap<[String; 2], VariantProposal> = HashMap::new();
+
+    for (input, candidate, _) in gold.iter().filter(|(_, _, is_match)| *is_match) {
+        // Each difference counts once per pair, whatever the fields it shows in
+        let mut seen: HashMap<[String; 2], Vec<ScoreField>> = HashMap::new();
+        for ((field, a), (_, b)) in name_fields(input).into_iter().zip(name_fields(candidate)) {
+            for names in field_differences(a, b) {
+                let fields = seen.entry(names).or_default();
+                if !fields.contains(&field) {
+                    fields.push(field);
+                }
+            }
+        }
+
+        for (names, fields) in seen {
+            let proposal = counts.entry(names.clone()).or_insert_with(|| VariantProposal {
+                names,
+                support: 0,
+                fields: Vec::new(),
+                status: ReviewStatus::Pending,
+            });
+            proposal.support += 1;
+            for field in fields {
+                if !proposal.fields.contains(&field) {
+                    proposal.fields.push(field);
+                }
+            }
+        }
+    }
+
+    let mut proposals: Vec<VariantProposal> = counts
+        .into_values()
+        .filter(|p| p.support >= min_support && !dictionary.are_variants(&p.names[0], &p.names[1]))
+        .collect();
+    proposals.sort_by(|a, b| b.support.cmp(&a.support).then_with(|| a.names.cmp(&b.names)));
+    proposals
+}
+
+/// Keep the decisions of an earlier review for the proposals mined again.
+pub fn carry_over_reviews(proposals: &mut [VariantProposal], previous: &[VariantProposal]) {
+    let decided: HashMap<&[String; 2], ReviewStatus> = previous
+        .iter()
+        .filter(|p| p.status != ReviewStatus::Pending)
+        .map(|p| (&p.names, p.status))
+        .collect();
+    for proposal in proposals {
+        if let Some(&status) = decided.get(&proposal.names) {
+            proposal.status = status;
+        }
+    }
+}
+
+/// Read a proposals file written by `save_proposals`.
+pub fn load_proposals(file_path: &str) -> io::Result<Vec<VariantProposal>> {
+    let contents = fs::read_to_string(file_path)?;
+    Ok(serde_json::from_str(&contents)?)
+}
+
+/// Write proposals as a JSON array for review.
+pub fn save_proposals(file_path: &str, proposals: &[VariantProposal]) -> io::Result<()> {
+    fs::write(file_path, serde_json::to_string_pretty(proposals)?)
+}
+
+/// Add the approved proposals to `dictionary`; returns how many were added.
+pub fn apply_approved(proposals: &[VariantProposal], dictionary: &mut VariantDictionary) -> usize {
+    proposals
+        .iter()
+        .filter(|p| p.status == ReviewStatus::Approved)
+        .filter(|p| dictionary.link(&p.names[0], &p.names[1]).is_ok())
+        .count()
+}
@@ -0,0 +1,396 @@
+// src/utils/variants.rs
+
+//! Curated name-variant dictionary: equivalence classes of spellings and
+//! nicknames of the same name ("محمد / امحمد / حمادي / Mohamed / Med").
+//!
+//! The identity store attaches every class member to the variation lists of
+//! the records whose name is in the class, so `best_match_against_variations`
+//! scores a query against all the known spellings. Attached spellings are
+//! flagged (`Variation::from_dictionary`) and never written to snapshots, so
+//! an edited dictionary replaces them without touching registry spellings.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io;
+use std::path::Path;
+use serde::{Deserialize, Serialize};
+use crate::utils::linked_list::{IdentityNode, Variations};
+use crate::utils::normalization::normalize_name;
+
+/// One equivalence class, names as written in the dictionary file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VariantClass {
+    pub id:    u32,
+    pub names: Vec<String>,
+}
+
+/// A class in a JSON file: a bare list of names or `{"id": 3, "names": [...]}`.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum ClassEntry {
+    Names(Vec<String>),
+    Class(VariantClass),
+}
+
+/// Equivalence classes indexed by normalized name.
+#[derive(Debug, Clone, Default)]
+pub struct VariantDictionary {
+    classes: BTreeMap<u32, Vec<String>>,
+    /// `normalize_name` of the names of each class, in the same order
+    keys: HashMap<u32, Vec<String>>,
+    /// `normalize_name` of each member → ids of the classes it belongs to
+    by_name: HashMap<String, Vec<u32>>,
+    next_id: u32,
+    /// File the dictionary was loaded from and is saved back to
+    path: Option<String>,
+}
+
+impl VariantDictionary {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Load classes from a file; the extension picks the format:
+    /// - .csv: one class per line, one name per cell (no header)
+    /// - .json: an array of classes, each a list of names or `{"id", "names"}`
+    ///
+    /// A class with fewer than two distinct names or a repeated id is an
+    /// error naming its line (CSV) or position (JSON).
+    pub fn load(file_path: &str) -> io::Result<Self> {
+        let path = Path::new(file_path);
+        let extension = path.extension().and_then(|ext| ext.to_str()).unwrap_or("");
+        let contents = fs::read_to_string(path)?;
+
+        // Each class with where it is in the file
+        let entries: Vec<(String, ClassEntry)> = match extension.to_lowercase().as_str() {
+            "csv" => {
+                let mut reader = csv::ReaderBuilder::new()
+                    .has_headers(false)
+                    .flexible(true)
+                    .trim(csv::Trim::All)
+                    .from_reader(contents.as_bytes());
+                reader
+                    .records()
+                    .map(|record| {
+                        let record = record.map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+                        let line = record.position().map(|p| p.line()).unwrap_or_default();
+                        Ok((format!("line {}", line), ClassEntry::Names(record.iter().map(str::to_string).collect())))
+                    })
+                    .collect::<io::Result<_>>()?
+            }
+            "json" => serde_json::from_str::<Vec<ClassEntry>>(&contents)?
+                .into_iter()
+                .enumerate()
+                .map(|(i, entry)| (format!("class {}", i + 1), entry))
+                .collect(),
+            _ => return Err(io::Error::new(io::ErrorKind::InvalidInput, "Unsupported file format")),
+        };
+
+        let mut dictionary = VariantDictionary { path: Some(file_path.to_string()), ..Self::default() };
+        for (place, entry) in entries {
+            let invalid = |message: String| {
+                io::Error::new(io::ErrorKind::InvalidData, format!("{}, {}: {}", file_path, place, message))
+            };
+            let (id, names) = match entry {
+                ClassEntry::Names(names) => (None, names),
+                ClassEntry::Class(class) => (Some(class.id), class.names),
+            };
+            if let Some(id) = id.filter(|id| dictionary.classes.contains_key(id)) {
+                return Err(invalid(format!("class id {} is used twice", id)));
+            }
+            dictionary.insert(id, names).map_err(invalid)?;
+        }
+        Ok(dictionary)
+    }
+
+    /// Loads the dictionary from `NAME_VARIANTS` if set, otherwise an empty one.
+    pub fn from_env() -> io::Result<Self> {
+        match std::env::var("NAME_VARIANTS") {
+            Ok(path) if !path.is_empty() => Self::load(&path),
+            _ => Ok(Self::new()),
+        }
+    }
+
+    /// Write the classes back to the file they were loaded from (same format);
+    /// an `Unsupported` error for a dictionary without a file.
+    pub fn save(&self) -> io::Result<()> {
+        let Some(path) = &self.path else {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "no NAME_VARIANTS file to save the name variants to",
+            ));
+        };
+        let contents = if path.to_lowercase().ends_with(".csv") {
+            let mut writer = csv::WriterBuilder::new().flexible(true).from_writer(Vec::new());
+            for names in self.classes.values() {
+                writer.write_record(names).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            }
+            let bytes = writer.into_inner().map_err(|e| io::Error::other(e.to_string()))?;
+            String::from_utf8(bytes).expect("CSV is UTF-8")
+        } else {
+            serde_json::to_string_pretty(&self.classes())?
+        };
+        fs::write(path, contents)
+    }
+
+    /// File the dictionary is saved to, if any.
+    pub fn path(&self) -> Option<&str> {
+        self.path.as_deref()
+    }
+
+    pub fn len(&self) -> usize {
+        self.classes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.classes.is_empty()
+    }
+
+    /// All classes, by id.
+    pub fn classes(&self) -> Vec<VariantClass> {
+        self.classes
+            .iter()
+            .map(|(&id, names)| VariantClass { id, names: names.clone() })
+            .collect()
+    }
+
+    pub fn get(&self, id: u32) -> Option<VariantClass> {
+        self.classes.get(&id).map(|names| VariantClass { id, names: names.clone() })
+    }
+
+    /// Add a class of at least two distinct names (after normalization).
+    pub fn add(&mut self, names: Vec<String>) -> Result<VariantClass, String> {
+        self.insert(None, names)
+    }
+
+    fn insert(&mut self, id: Option<u32>, names: Vec<String>) -> Result<VariantClass, String> {
+        let mut seen = Vec::new();
+        let mut kept = Vec::new();
+        for name in names {
+            let name = name.trim().to_string();
+            let key = normalize_name(&name);
+            if key.is_empty() || seen.contains(&key) {
+                continue;
+            }
+            seen.push(key);
+            kept.push(name);
+        }
+        if kept.len() < 2 {
+            return Err("A variant class needs at least two distinct names".to_string());
+        }
+
+        let id = match id {
+            Some(id) if !self.classes.contains_key(&id) => id,
+            _ => self.next_id,
+        };
+        self.next_id = self.next_id.max(id + 1);
+        for key in &seen {
+            self.by_name.entry(key.clone()).or_default().push(id);
+        }
+        self.keys.insert(id, seen);
+        self.classes.insert(id, kept.clone());
+        Ok(VariantClass { id, names: kept })
+    }
+
+    /// Make `a` and `b` equivalent: the second joins the class of the first
+    /// when one of them already has a class, otherwise they form a new one.
+    pub fn link(&mut self, a: &str, b: &str) -> Result<VariantClass, String> {
+        let existing = [a, b]
+            .iter()
+            .find_map(|name| self.by_name.get(&normalize_name(name)).and_then(|ids| ids.first().copied()));
+        match existing {
+            Some(id) => {
+                let mut names = self.classes[&id].clone();
+                names.extend([a.to_string(), b.to_string()]);
+                self.remove(id);
+                self.insert(Some(id), names)
+            }
+            None => self.add(vec![a.to_string(), b.to_string()]),
+        }
+    }
+
+    /// Remove a class; `None` when there is no class `id`.
+    pub fn remove(&mut self, id: u32) -> Option<VariantClass> {
+        let names = self.classes.remove(&id)?;
+        for key in self.keys.remove(&id).unwrap_or_default() {
+            if let Some(ids) = self.by_name.get_mut(&key) {
+                ids.retain(|&other| other != id);
+                if ids.is_empty() {
+                    self.by_name.remove(&key);
+                }
+            }
+        }
+        Some(VariantClass { id, names })
+    }
+
+    /// True when two normalized names share a class.
+    pub fn are_variants(&self, norm_a: &str, norm_b: &str) -> bool {
+        match (self.by_name.get(norm_a), self.by_name.get(norm_b)) {
+            (Some(a), Some(b)) => a.iter().any(|id| b.contains(id)),
+            _ => false,
+        }
+    }
+
+    /// Every other spelling of a normalized name, as written in the dictionary.
+    pub fn variants_of(&self, norm_name: &str) -> Vec<&str> {
+        let Some(ids) = self.by_name.get(norm_name) else {
+            return Vec::new();
+        };
+        ids.iter()
+            .flat_map(|id| self.classes[id].iter().zip(&self.keys[id]))
+            .filter(|(_, key)| key.as_str() != norm_name)
+            .map(|(name, _)| name.as_str())
+            .collect()
+    }
+
+    /// The variants of a normalized field value: those of the whole value,
+    /// then, for a multi-part name, the value with one part replaced by each
+    /// of its variants ("محمد امين" → "Med امين").
+    fn field_variants(&self, norm_value: &str) -> Vec<String> {
+        let mut found: Vec<String> = self.variants_of(norm_value).into_iter().map(str::to_string).collect();
+        let parts: Vec<&str> = norm_value.split(' ').collect();
+        if parts.len() < 2 {
+            return found;
+        }
+        for (i, part) in parts.iter().enumerate() {
+            for variant in self.variants_of(part) {
+                let mut replaced = parts.clone();
+                replaced[i] = variant;
+                found.push(replaced.join(" "));
+            }
+        }
+        found
+    }
+
+    /// The name fields of `node` with their variation lists.
+    fn name_fields(node: &mut IdentityNode) -> [(&str, &mut Variations); 6] {
+        [
+            (&node.first_name, &mut node.first_name_variations),
+            (&node.last_name, &mut node.last_name_variations),
+            (&node.father_name, &mut node.father_name_variations),
+            (&node.grandfather_name, &mut node.grandfather_name_variations),
+            (&node.mother_last_name, &mut node.mother_last_name_variations),
+            (&node.mother_name, &mut node.mother_name_variations),
+        ]
+    }
+
+    /// Attach the dictionary variants of every name field to its variation
+    /// list, in place of those a dictionary attached before.
+    pub fn attach(&self, node: &mut IdentityNode) {
+        for (norm_value, list) in Self::name_fields(node) {
+            list.clear_dictionary();
+            for variant in self.field_variants(norm_value) {
+                list.insert_from_dictionary(&variant);
+            }
+        }
+    }
+
+    /// `attach` on every record.
+    pub fn attach_all(&self, records: &mut [IdentityNode]) {
+        for node in records {
+            self.attach(node);
+        }
+    }
+
+    /// Undo `attach`: drop the dictionary variants of every record, keeping
+    /// the registry spellings.
+    pub fn detach_all(records: &mut [IdentityNode]) {
+        for node in records {
+            for (_, list) in Self::name_fields(node) {
+                list.clear_dictionary();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::loader::RegistryRow;
+
+    fn record(first_name: &str, father_name: &str) -> IdentityNode {
+        RegistryRow {
+            registry_id:      Some("1".to_string()),
+            first_name:       first_name.to_string(),
+            last_name:        "الطرابلسي".to_string(),
+            father_name:      father_name.to_string(),
+            grandfather_name: "صالح".to_string(),
+            mother_last_name: String::new(),
+            mother_name:      String::new(),
+            dob:              Some((15, 6, 1985)),
+            sex:              "1".to_string(),
+            place_of_birth:   String::new(),
+        }
+        .into_identity()
+    }
+
+    fn spellings(list: &Variations) -> Vec<&str> {
+        let mut spellings: Vec<&str> = list.iter().collect();
+        spellings.sort();
+        spellings
+    }
+
+    fn sorted(mut names: Vec<&str>) -> Vec<&str> {
+        names.sort();
+        names
+    }
+
+    fn dictionary() -> VariantDictionary {
+        let mut dictionary = VariantDictionary::new();
+        dictionary.link("محمد", "حمادي").unwrap();
+        dictionary.link("امحمد", "محمد").unwrap();
+        dictionary
+    }
+
+    #[test]
+    fn link_grows_an_existing_class_or_starts_one() {
+        let mut dictionary = dictionary();
+        assert_eq!(dictionary.len(), 1);
+        assert_eq!(dictionary.get(0).unwrap().names, ["محمد", "حمادي", "امحمد"]);
+        assert!(dictionary.are_variants("امحمد", "حمادي"));
+        assert_eq!(sorted(dictionary.variants_of("محمد")), sorted(vec!["حمادي", "امحمد"]));
+
+        let class = dictionary.link("علي", "عليا").unwrap();
+        assert_eq!(class.id, 1);
+        assert!(!dictionary.are_variants("علي", "محمد"));
+
+        // Equal once normalized
+        assert!(dictionary.add(vec!["محمد".into(), "مُحَمَّد".into()]).is_err());
+    }
+
+    #[test]
+    fn attach_adds_class_members_and_replaces_earlier_ones() {
+        let mut dictionary = dictionary();
+        let mut node = record("محمد", "محمد امين");
+        dictionary.attach(&mut node);
+        assert_eq!(spellings(&node.first_name_variations), sorted(vec!["محمد", "حمادي", "امحمد"]));
+        assert!(node.father_name_variations.iter().any(|v| v == "حمادي امين"));
+
+        dictionary.remove(0);
+        dictionary.attach(&mut node);
+        assert_eq!(spellings(&node.first_name_variations), ["محمد"]);
+        assert_eq!(spellings(&node.father_name_variations), ["محمد امين"]);
+    }
+
+    #[test]
+    fn detach_keeps_registry_spellings() {
+        let mut node = record("محمد", "علي");
+        // Also a registry spelling of this record
+        node.first_name_variations.insert("حمادي");
+        dictionary().attach(&mut node);
+        assert_eq!(node.first_name_variations.len(), 3);
+
+        VariantDictionary::detach_all(std::slice::from_mut(&mut node));
+        assert_eq!(spellings(&node.first_name_variations), sorted(vec!["محمد", "حمادي"]));
+    }
+
+    #[test]
+    fn load_names_the_invalid_class() {
+        let path = std::env::temp_dir().join(format!("variants-{}.csv", std::process::id()));
+        fs::write(&path, "محمد,حمادي\nعلي\n").unwrap();
+        let error = VariantDictionary::load(path.to_str().unwrap()).unwrap_err();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert!(error.to_string().contains("line 2"), "{}", error);
+    }
+}